@@ -0,0 +1,150 @@
+use serde::{Deserialize, Serialize};
+use ts_rs::TS;
+
+use crate::{mix::Ramp, transport::Transport, types::StereoParams};
+
+/// ms, time constant of the level each set is matched on, long enough to ride over words and pauses
+const LEVEL_MS: f32 = 3000.0;
+/// mean square below which a set counts as silent and isn't matched
+const SILENCE: f32 = 1e-10;
+
+/// one of the two stored parameter sets
+#[derive(Clone, Copy, Debug, Deserialize, Serialize, PartialEq, TS)]
+#[ts(export)]
+pub enum AbSlot {
+    A,
+    B,
+}
+
+impl AbSlot {
+    fn index(&self) -> usize {
+        match self {
+            AbSlot::A => 0,
+            AbSlot::B => 1,
+        }
+    }
+}
+
+/// sent to the callback, the sets are built from the db before they're sent so the callback doesn't allocate
+/// a stored set goes back in the message in place of the one it replaced, or stays there if it's ignored,
+/// so whatever's left is freed with the message off the audio thread
+#[derive(Clone, Debug)]
+pub enum AbMessage {
+    Store(AbSlot, Option<Box<StereoParams>>),
+    Select(AbSlot),
+    Enabled(bool),
+}
+
+/// two stored parameter sets run side by side on the same input, played one at a time
+/// each is turned down to the level of the quieter one so the louder doesn't win by being louder
+pub struct AbCompare {
+    sets: [Option<Box<StereoParams>>; 2],
+    outputs: [Vec<f32>; 2],
+    /// mean square of each set's output
+    power: [f32; 2],
+    level_coeff: f32,
+    /// Hz, of the stream, sets at another rate are ignored
+    sample_rate: f32,
+    pub enabled: bool,
+    pub selected: AbSlot,
+    /// 0 plays a, 1 plays b
+    fade: Ramp,
+    /// transport jumps seen, a jump leaves the sets' state behind like the live params'
+    jumps: usize,
+}
+
+impl AbCompare {
+    pub fn new(num_channels: usize, sample_rate: f32) -> Self {
+        Self {
+            sets: [None, None],
+            outputs: [vec![0.0; num_channels], vec![0.0; num_channels]],
+            power: [0.0; 2],
+            level_coeff: 1.0 - (-1.0 / (LEVEL_MS / 1000.0 * sample_rate)).exp(),
+            sample_rate,
+            enabled: false,
+            selected: AbSlot::A,
+            fade: Ramp::new(0.0, sample_rate),
+            jumps: 0,
+        }
+    }
+
+    /// a set for a different channel count or rate than the stream is ignored
+    pub fn apply(&mut self, message: &mut AbMessage) {
+        match message {
+            AbMessage::Store(slot, set) => {
+                let fits = set.as_ref().is_some_and(|set| {
+                    set.num_channels == self.outputs[0].len() && set.sample_rate == self.sample_rate
+                });
+                if fits {
+                    if let Some(set) = set.as_mut() {
+                        set.reset();
+                    }
+                    self.power[slot.index()] = 0.0;
+                    std::mem::swap(&mut self.sets[slot.index()], set);
+                }
+            }
+            AbMessage::Select(slot) => self.selected = *slot,
+            AbMessage::Enabled(enabled) => {
+                if *enabled && !self.enabled {
+                    self.reset();
+                    self.fade = Ramp::new(self.selected.index() as f32, self.sample_rate);
+                }
+                self.enabled = *enabled;
+            }
+        }
+    }
+
+    /// on with both sets stored, otherwise the live params play
+    pub fn is_active(&self) -> bool {
+        self.enabled && self.sets.iter().all(|s| s.is_some())
+    }
+
+    pub fn sync(&mut self, transport: &Transport) {
+        if transport.jumps != self.jumps {
+            self.jumps = transport.jumps;
+            self.reset();
+        }
+    }
+
+    pub fn reset(&mut self) {
+        for set in self.sets.iter_mut().flatten() {
+            set.reset();
+        }
+        self.power = [0.0; 2];
+    }
+
+    /// gain of each set, the louder one comes down to the quieter
+    pub fn gains(&self) -> [f32; 2] {
+        let quieter = self.power[0].min(self.power[1]);
+        self.power.map(|p| {
+            if quieter > SILENCE {
+                (quieter / p).sqrt()
+            } else {
+                1.0
+            }
+        })
+    }
+
+    /// process one frame through both sets, out gets the selected one, crossfaded when the selection changes
+    pub fn process_frame(&mut self, frame: &[f32], out: &mut [f32]) {
+        for ((set, output), power) in self
+            .sets
+            .iter_mut()
+            .zip(self.outputs.iter_mut())
+            .zip(self.power.iter_mut())
+        {
+            let Some(set) = set else {
+                continue;
+            };
+            set.process_frame(frame, output);
+            set.monitor_frame(frame, output);
+            let square = output.iter().map(|x| x * x).sum::<f32>() / output.len() as f32;
+            *power += self.level_coeff * (square - *power);
+        }
+        let [a, b] = self.gains();
+        let fade = self.fade.next(self.selected.index() as f32);
+        for (i, o) in out.iter_mut().enumerate() {
+            *o = (1.0 - fade) * a * self.outputs[0][i] + fade * b * self.outputs[1][i];
+        }
+    }
+}
@@ -1,6 +1,16 @@
+use crate::ab::AbCompare;
 use crate::constants::*;
+use crate::declip::declip;
 use crate::errors::DenoiserResult;
-use crate::messages::{AudioUIMessage, FingerprintMessage, UIAudioMessage};
+use crate::freq_scale::{saved_scale, FreqMapping, SPECTRUM_POINTS};
+use crate::messages::{AudioUIMessage, ClipMessage, FingerprintMessage, UIAudioMessage};
+use crate::metering::Meter;
+use crate::mix::Mix;
+use crate::report::{snapshot_buffer, spawn_reporter, Snapshot};
+use crate::settings::PlotScale;
+use crate::speed::Speed;
+use crate::sql::{query_declip, query_mix, query_mute, query_ui_rate};
+use crate::transport::Transport;
 use crate::{fourier::averaged_stft, types::*};
 use cpal::FromSample;
 use cpal::{self};
@@ -8,7 +18,6 @@ use cpal::{
     traits::{DeviceTrait, HostTrait},
     SizedSample,
 };
-use rustfft::num_complex::Complex;
 use samplerate::{convert, ConverterType};
 use std::fs::File;
 use std::path::PathBuf;
@@ -16,30 +25,66 @@ use tauri::{AppHandle, Window};
 
 // this function and fn above should be combined, obviously
 // but also, might want to make option to resample, otherwise when using from process_export, getting wav samples and resampling to output device does not make sense...
-pub fn get_wav_samples(path: PathBuf) -> (Vec<f32>, bool) {
-    // if let Ok(file_in) = File::open(path) {
-    //     let (head, samples) = wav_io::read_from_file(file_in).unwrap();
-    //     let is_stereo = if head.channels == 1 { false } else { true };
-    //     (samples, is_stereo)
-    // } else {
+pub fn get_wav_samples(path: PathBuf) -> (Vec<f32>, usize) {
+    let (samples, num_channels, sample_rate) = read_wav_samples(path);
+    (
+        resample_to_device(samples, num_channels, sample_rate),
+        num_channels,
+    )
+}
+
+/// interleaved samples, channel count and rate of the file as it is on disk
+pub fn read_wav_samples(path: PathBuf) -> (Vec<f32>, usize, u32) {
     let f = File::open(path).unwrap();
     let (head, samples) = wav_io::read_from_file(f).unwrap();
-    let is_stereo = if head.channels == 1 { false } else { true };
+    (samples, head.channels.max(1) as usize, head.sample_rate)
+}
+
+/// rate files play at, get_wav_samples resamples them to the device
+pub fn playback_sample_rate() -> f32 {
+    device_sample_rate()
+        .map(|r| r.0 as f32)
+        .unwrap_or(SAMPLING_RATE)
+}
+
+/// interleaved samples at the file rate converted to the device rate, unchanged if they match
+pub fn resample_to_device(samples: Vec<f32>, num_channels: usize, sample_rate: u32) -> Vec<f32> {
     let device_sample_rate = device_sample_rate().unwrap();
-    if device_sample_rate != cpal::SampleRate(head.sample_rate) {
-        let resampled = convert(
-            head.sample_rate,
+    if device_sample_rate != cpal::SampleRate(sample_rate) {
+        convert(
+            sample_rate,
             device_sample_rate.0,
-            1,
+            num_channels,
             ConverterType::SincBestQuality,
             &samples,
         )
-        .unwrap();
-        (resampled, is_stereo)
+        .unwrap()
     } else {
-        (samples, is_stereo)
+        samples
+    }
+}
+
+/// fit a frame of the file to the device channels
+/// a mono file plays on every channel, extra device channels are silent,
+/// and when the device has fewer channels the file channels are folded onto them and averaged
+pub fn map_channels(input: &[f32], output: &mut [f32]) {
+    if input.len() == 1 {
+        output.fill(input[0]);
+        return;
+    }
+    output.fill(0.0);
+    if input.len() <= output.len() {
+        output[..input.len()].copy_from_slice(input);
+        return;
+    }
+    let n = output.len();
+    for (i, x) in input.iter().enumerate() {
+        output[i % n] += x;
+    }
+    for (j, y) in output.iter_mut().enumerate() {
+        let folded = (input.len() - j + n - 1) / n;
+        *y /= folded as f32;
     }
-    // }
 }
 
 #[tauri::command]
@@ -48,7 +93,11 @@ pub fn setup_stream(
     app_handle: AppHandle,
     file_path: Option<PathBuf>,
     window: Window,
-) -> DenoiserResult<(cpal::Stream, tauri::async_runtime::Sender<UIAudioMessage>)>
+) -> DenoiserResult<(
+    cpal::Stream,
+    tauri::async_runtime::Sender<UIAudioMessage>,
+    StreamFormat,
+)>
 where
 {
     let (_host, device, config) = host_device_setup()?;
@@ -133,238 +182,201 @@ pub fn make_stream<T>(
     app_handle: AppHandle,
     file_path: Option<PathBuf>,
     window: Window,
-) -> DenoiserResult<(cpal::Stream, tauri::async_runtime::Sender<UIAudioMessage>)>
+) -> DenoiserResult<(
+    cpal::Stream,
+    tauri::async_runtime::Sender<UIAudioMessage>,
+    StreamFormat,
+)>
 where
     T: SizedSample + FromSample<f32>,
 {
-    let num_channels = config.channels as usize;
+    let device_channels = config.channels as usize;
     let err_fn = |err| eprintln!("Error building output sound stream: {}", err);
 
-    let (tx, mut rx) = tauri::async_runtime::channel::<UIAudioMessage>(1);
+    // room for a burst of messages, a seek and a play sent together shouldn't lose one
+    let (tx, mut rx) = tauri::async_runtime::channel::<UIAudioMessage>(64);
 
     // variables that stream will use, including params
     let mut file_samples = vec![];
-    let mut is_stereo = true;
+    let mut num_channels = 2;
+    let db_path = app_handle
+        .path_resolver()
+        .app_local_data_dir()
+        .expect("app local data dir should exist")
+        .join("db.sqlite");
     if let Some(f) = file_path.clone() {
         let p = app_handle.path_resolver().resource_dir().unwrap().join(f);
-        (file_samples, is_stereo) = get_wav_samples(p);
+        // declipped at the file rate, resampling smears the flat tops so they aren't found after
+        let (mut samples, channels, sample_rate) = read_wav_samples(p);
+        num_channels = channels;
+
+        let channel_params: Vec<DeclipParams> = (0..num_channels)
+            .map(|i| query_declip(StereoChoice::for_channel(i), &db_path).unwrap_or_default())
+            .collect();
+        let clips = declip(&mut samples, &channel_params);
+        file_samples = resample_to_device(samples, num_channels, sample_rate);
+        let _ = window.emit(
+            ClipMessage::name(),
+            ClipMessage {
+                count: clips.len(),
+                clips,
+            },
+        );
     }
     let mut stereo_params = StereoParams::new();
-    stereo_params.is_stereo = is_stereo;
+    stereo_params.set_sample_rate(config.sample_rate.0 as f32);
+    stereo_params.set_num_channels(num_channels);
+    stereo_params.freq_scale = (saved_scale(&app_handle), SPECTRUM_POINTS);
+    stereo_params.ui_rate = query_ui_rate(&db_path).unwrap_or_default().rate;
+    stereo_params.mix = Mix::new(
+        query_mix(&db_path).unwrap_or_default(),
+        config.sample_rate.0 as f32,
+    );
+    for i in 0..num_channels {
+        stereo_params.channel_mut(i).ui_params.mute =
+            query_mute(StereoChoice::for_channel(i), &db_path).unwrap_or(false);
+    }
     let _ = tx_ui.try_send(AudioUIMessage {
-        is_stereo: Some(is_stereo),
+        num_channels: Some(num_channels),
         ..Default::default()
     });
     stereo_params.num_file_samples = file_samples.len();
+    stereo_params.transport = Transport::new(
+        file_samples.len() / num_channels,
+        config.sample_rate.0 as f32,
+    );
     let _ = window
         .clone()
         .emit("update_sampling_rate", config.sample_rate.0);
+    let _ = window.clone().emit("update_num_channels", num_channels);
+
+    // one frame of the file before and after processing, and the same frame mapped to the device
+    let mut input = vec![0.0; num_channels];
+    let mut processed = vec![0.0; num_channels];
+    let mut mapped = vec![0.0; device_channels];
+    let mut meter = Meter::new(num_channels, config.sample_rate.0 as f32);
+    let mut speed = Speed::new(num_channels);
+    let mut ab = AbCompare::new(num_channels, config.sample_rate.0 as f32);
+    // a reading of nothing yet, sized for the channels
+    let mut reading = meter.take();
+    let mut readings = 0;
+
+    // the callback only copies into the snapshot, the reporter thread maps, serializes and emits it
+    let (mut writer, reader) = snapshot_buffer(Snapshot::new(&stereo_params));
+    spawn_reporter(
+        reader,
+        window,
+        stereo_params.left.sdft.size / 2,
+        config.sample_rate.0 as f32,
+    );
+
+    let spent = spawn_dropper();
 
     let stream = device.build_output_stream(
         config,
         move |output: &mut [T], _: &cpal::OutputCallbackInfo| {
-            if let Ok(msg) = rx.try_recv() {
+            while let Ok(mut msg) = rx.try_recv() {
+                if let Some(m) = msg.ab.as_mut() {
+                    ab.apply(m);
+                }
                 msg.receive(&mut stereo_params);
+                // the message now holds whatever it replaced, freed off the audio thread
+                let _ = spent.try_send(msg);
                 // if msg.fingerprint.is_some() {
                 //     println!("fingerprint time");
                 // }
             }
 
-            if !stereo_params.is_stereo {
-                let mut spectrum: Vec<f32> = vec![];
-                if stereo_params.clean {
-                    // ...each frame has 2 samples
-                    for frame in output.chunks_mut(num_channels) {
-                        if stereo_params.is_looping
-                            && stereo_params.time
-                                > stereo_params.loop_start_time + stereo_params.loop_length
-                        {
-                            stereo_params.time = stereo_params.loop_start_time;
-                        }
-                        if stereo_params.time >= stereo_params.num_file_samples {
-                            break;
-                        }
-                        let sample = file_samples[stereo_params.time]
-                            * stereo_params.left.ui_params.output_gain;
-                        let v: T = T::from_sample(sample);
-                        spectrum.push(sample);
-
-                        // copy mono input to both output channels
-                        for out_sample in frame.iter_mut() {
-                            *out_sample = v;
-                        }
-                        stereo_params.time += 1;
-                    }
-                } else {
-                    for frame in output.chunks_mut(num_channels) {
-                        if stereo_params.is_looping
-                            && stereo_params.time
-                                > stereo_params.loop_start_time + stereo_params.loop_length
-                        {
-                            stereo_params.time = stereo_params.loop_start_time;
-                        }
-                        if stereo_params.time >= stereo_params.num_file_samples {
-                            break;
-                        }
-                        let sample = file_samples[stereo_params.time]
-                            * stereo_params.left.ui_params.output_gain;
-                        let filtered = stereo_params.left.sdft.spectral_subtraction(
-                            sample,
-                            &stereo_params.left.noise_spectrum,
-                            stereo_params.left.ui_params.noise_gain,
-                            stereo_params.left.ui_params.pre_smooth_gain,
-                            stereo_params.left.ui_params.post_smooth_gain,
-                        );
-
-                        let v: T = T::from_sample(filtered);
-                        spectrum.push(filtered);
-
-                        // copying to all channels for now
-                        for out_sample in frame.iter_mut() {
-                            *out_sample = v;
-                        }
-                        stereo_params.time += 1;
-                    }
-                }
+            speed.sync(&stereo_params.transport);
+            ab.sync(&stereo_params.transport);
+            for frame in output.chunks_mut(device_channels) {
+                // frames are processed at the file's rate as the speed stage asks for them
+                let playing = speed.next(&mut processed, |processed| {
+                    let Some(playhead) = stereo_params.transport.advance() else {
+                        return false;
+                    };
+                    playhead.read(&file_samples, &mut input);
 
-                let _ = window.emit(
-                    AudioUIMessage::name(),
-                    AudioUIMessage {
-                        spectrum: Some(
-                            stereo_params.left.sdft.norm_vec()[0..stereo_params.left.sdft.size / 2]
-                                .to_vec(),
-                        ),
-                        time: Some(stereo_params.time as f32),
-                        ..Default::default()
-                    },
-                );
-            }
-            // PROCESS STEREO
-            else {
-                let mut left_spectrum: Vec<f32> = vec![];
-                let mut right_spectrum: Vec<f32> = vec![];
-                if stereo_params.clean {
-                    // ...each frame has 2 samples
-                    for frame in output.chunks_mut(num_channels) {
-                        if stereo_params.is_looping
-                            && stereo_params.time
-                                > stereo_params.loop_start_time + stereo_params.loop_length
-                        {
-                            stereo_params.time = stereo_params.loop_start_time;
-                        }
-                        if stereo_params.time + 2 >= stereo_params.num_file_samples {
-                            break;
-                        }
-                        if !stereo_params.left.ui_params.left_mute {
-                            let left_sample = file_samples[stereo_params.time]
-                                * stereo_params.left.ui_params.output_gain;
-                            let left_samp: T = T::from_sample(left_sample);
-                            left_spectrum.push(left_sample);
-                            let fr = frame.get_mut(0).unwrap();
-                            *fr = left_samp;
-                        } else {
-                            let fr = frame.get_mut(0).unwrap();
-                            *fr = T::from_sample(0.0);
-                        }
+                    // the live params keep running under the compare so switching back is seamless
+                    stereo_params.process_frame(&input, processed);
+                    stereo_params.monitor_frame(&input, processed);
+                    if ab.is_active() {
+                        ab.process_frame(&input, processed);
+                    }
 
-                        if !stereo_params.right.ui_params.right_mute {
-                            let right_sample = file_samples[stereo_params.time + 1]
-                                * stereo_params.right.ui_params.output_gain;
-                            let right_samp: T = T::from_sample(right_sample);
-                            right_spectrum.push(right_sample);
-
-                            let fr = frame.get_mut(1).unwrap();
-                            *fr = right_samp;
-                        } else {
-                            let fr = frame.get_mut(1).unwrap();
-                            *fr = T::from_sample(0.0);
+                    for (i, p) in processed.iter_mut().enumerate() {
+                        if stereo_params.channel(i).ui_params.mute {
+                            *p = 0.0;
                         }
-                        stereo_params.time += 2;
                     }
-                } else {
-                    for frame in output.chunks_mut(num_channels) {
-                        if stereo_params.is_looping
-                            && stereo_params.time
-                                > stereo_params.loop_start_time + stereo_params.loop_length
-                        {
-                            stereo_params.time = stereo_params.loop_start_time;
-                        }
-                        if stereo_params.time + 2 >= stereo_params.num_file_samples {
-                            break;
-                        }
 
-                        if !stereo_params.left.ui_params.left_mute {
-                            let left_sample = file_samples[stereo_params.time]
-                                * stereo_params.left.ui_params.output_gain;
-                            let left_filtered = stereo_params.left.sdft.spectral_subtraction(
-                                left_sample,
-                                &stereo_params.left.noise_spectrum,
-                                stereo_params.left.ui_params.noise_gain,
-                                stereo_params.left.ui_params.pre_smooth_gain,
-                                stereo_params.left.ui_params.post_smooth_gain,
-                            );
-
-                            let left_samp: T = T::from_sample(left_filtered);
-                            left_spectrum.push(left_filtered);
-                            let fr = frame.get_mut(0).unwrap();
-                            *fr = left_samp;
-                        } else {
-                            let fr = frame.get_mut(0).unwrap();
-                            *fr = T::from_sample(0.0);
-                        }
-                        if !stereo_params.right.ui_params.right_mute {
-                            let right_sample = file_samples[stereo_params.time + 1]
-                                * stereo_params.right.ui_params.output_gain;
-                            let right_filtered = stereo_params.right.sdft.spectral_subtraction(
-                                right_sample,
-                                &stereo_params.right.noise_spectrum,
-                                stereo_params.right.ui_params.noise_gain,
-                                stereo_params.right.ui_params.pre_smooth_gain,
-                                stereo_params.right.ui_params.post_smooth_gain,
-                            );
-                            let right_samp: T = T::from_sample(right_filtered);
-                            right_spectrum.push(right_filtered);
-                            let fr = frame.get_mut(1).unwrap();
-                            *fr = right_samp;
-                        } else {
-                            let fr = frame.get_mut(1).unwrap();
-                            *fr = T::from_sample(0.0);
-                        }
-                        stereo_params.time += 2;
+                    meter.process_frame(processed);
+                    true
+                });
+                if !playing {
+                    for out_sample in frame.iter_mut() {
+                        *out_sample = T::from_sample(0.0);
                     }
+                    continue;
+                }
+
+                map_channels(&processed, &mut mapped);
+                for (out_sample, m) in frame.iter_mut().zip(mapped.iter()) {
+                    *out_sample = T::from_sample(*m);
                 }
+            }
 
-                let _ = window.emit(
-                    AudioUIMessage::name(),
-                    AudioUIMessage {
-                        spectrum: Some(
-                            stereo_params.left.sdft.norm_vec()[0..stereo_params.left.sdft.size / 2]
-                                .to_vec(),
-                        ),
-                        time: Some(stereo_params.time as f32),
-                        ..Default::default()
-                    },
-                );
+            if meter.ready() {
+                meter.take_into(&mut reading);
+                readings += 1;
             }
+            writer.get().update(&stereo_params, &reading, readings);
+            writer.publish();
         },
         err_fn,
         None,
     )?;
 
-    Ok((stream, tx))
+    let format = StreamFormat {
+        num_channels,
+        sample_rate: config.sample_rate.0 as f32,
+    };
+    Ok((stream, tx, format))
+}
+
+/// messages waiting to be freed, a bigger burst than this is freed on the audio thread
+const SPENT_MESSAGES: usize = 64;
+
+/// frees messages once the callback is done with them, along with whatever they took the place of
+/// the thread ends when the callback holding the sender is dropped with its stream
+fn spawn_dropper() -> std::sync::mpsc::SyncSender<UIAudioMessage> {
+    let (tx, rx) = std::sync::mpsc::sync_channel::<UIAudioMessage>(SPENT_MESSAGES);
+    std::thread::spawn(move || for _msg in rx {});
+    tx
 }
 
-pub fn calculate_fingerprint(file_path: PathBuf, start: usize, len: usize, window: Window) {
+/// the emitted spectrum is mapped to the scale, the filters are found from the linear one
+pub fn calculate_fingerprint(
+    file_path: PathBuf,
+    start: usize,
+    len: usize,
+    scale: PlotScale,
+    points: usize,
+    window: Window,
+) {
     println!(" ready to get fingerprint");
-    let (file_samples, is_stereo) = get_wav_samples(file_path);
-    // need to do something about stereo...
-    let mut buf = vec![];
-    for samp in file_samples[start..start + len].iter() {
-        buf.push(Complex { re: *samp, im: 0.0 });
-    }
+    let (file_samples, num_channels) = get_wav_samples(file_path);
+    let num_channels = num_channels.max(1);
     let fft_size = 256;
 
-    let smooth_spectrum = averaged_stft(buf, fft_size, fft_size);
+    // start and len are frames, the channels are averaged
+    let mono: Vec<f32> = file_samples
+        .chunks_exact(num_channels)
+        .skip(start)
+        .take(len)
+        .map(|f| f.iter().sum::<f32>() / num_channels as f32)
+        .collect();
+    let smooth_spectrum = averaged_stft(&mono, fft_size, fft_size).unwrap_or_default();
 
     let mut indices = vec![];
     let mut maxes_found = 0;
@@ -409,7 +421,15 @@ pub fn calculate_fingerprint(file_path: PathBuf, start: usize, len: usize, windo
     let _ = window.emit(
         FingerprintMessage::name(),
         FingerprintMessage {
-            spectrum: Some(smooth_spectrum.clone()),
+            spectrum: Some(
+                FreqMapping::new(
+                    scale,
+                    points,
+                    smooth_spectrum.len(),
+                    device_sample_rate().map_or(SAMPLING_RATE, |s| s.0 as f32),
+                )
+                .map(&smooth_spectrum),
+            ),
             filters,
         },
     );
@@ -0,0 +1,262 @@
+use rustfft::{num_complex::Complex, Fft, FftPlanner};
+use serde::{Deserialize, Serialize};
+use std::sync::Arc;
+use ts_rs::TS;
+
+use crate::{
+    fourier::{window, WindowKind},
+    freq_scale::FreqMapping,
+};
+
+/// longest processing latency the input can be lined up with, power of two
+const MAX_LATENCY: usize = 1 << 13;
+/// samples in each comparison spectrum, same as the sdft
+const COMPARE_FFT_SIZE: usize = 256;
+/// samples of each stream kept for the ui, enough for a message every ~85 ms at 96 kHz
+pub const HISTORY_LENGTH: usize = 1 << 13;
+
+/// what goes to the speakers
+#[derive(Clone, Copy, Debug, Deserialize, Serialize, PartialEq, TS)]
+#[ts(export)]
+pub enum Audition {
+    Output,
+    /// the unprocessed input, lined up with the output so switching doesn't jump
+    Input,
+    /// only what the denoiser is removing, input minus output
+    Residual,
+}
+
+/// input, output and residual of one channel for the ui, samples since the last message and their spectra
+#[derive(Clone, Debug, Serialize, Deserialize, Default)]
+pub struct ChannelComparison {
+    pub input: Vec<f32>,
+    pub output: Vec<f32>,
+    pub residual: Vec<f32>,
+    pub input_spectrum: Vec<f32>,
+    pub output_spectrum: Vec<f32>,
+    pub residual_spectrum: Vec<f32>,
+}
+
+/// the last HISTORY_LENGTH samples of a channel's input, output and residual
+#[derive(Clone, Debug)]
+pub struct Recent {
+    pub samples: [Vec<f32>; 3],
+    /// samples written since the start, the ring is at written % HISTORY_LENGTH
+    pub written: usize,
+}
+
+impl Recent {
+    pub fn new() -> Self {
+        Self {
+            samples: [
+                vec![0.0; HISTORY_LENGTH],
+                vec![0.0; HISTORY_LENGTH],
+                vec![0.0; HISTORY_LENGTH],
+            ],
+            written: 0,
+        }
+    }
+
+    /// copy in place, the derived clone_from would allocate
+    pub fn copy_from(&mut self, other: &Recent) {
+        for (s, o) in self.samples.iter_mut().zip(other.samples.iter()) {
+            s.copy_from_slice(o);
+        }
+        self.written = other.written;
+    }
+
+    fn push(&mut self, input: f32, output: f32, residual: f32) {
+        let pos = self.written % HISTORY_LENGTH;
+        for (s, x) in self.samples.iter_mut().zip([input, output, residual]) {
+            s[pos] = x;
+        }
+        self.written += 1;
+    }
+
+    /// the n samples of one stream before end, oldest first
+    fn last(&self, stream: usize, n: usize, end: usize) -> impl Iterator<Item = &f32> {
+        let start = end + HISTORY_LENGTH - n;
+        (start..start + n).map(move |k| &self.samples[stream][k % HISTORY_LENGTH])
+    }
+}
+
+impl Default for Recent {
+    fn default() -> Self {
+        Recent::new()
+    }
+}
+
+#[derive(Clone, Debug)]
+struct ChannelMonitor {
+    /// input history, read back at the processing latency
+    delay: Vec<f32>,
+    pos: usize,
+    recent: Recent,
+}
+
+impl ChannelMonitor {
+    fn new() -> Self {
+        Self {
+            delay: vec![0.0; MAX_LATENCY],
+            pos: 0,
+            recent: Recent::new(),
+        }
+    }
+}
+
+/// keeps the input of every channel lined up with the output, so the residual is exactly what was removed
+#[derive(Clone)]
+pub struct Monitor {
+    pub audition: Audition,
+    /// keep the recent samples for the ui
+    pub compare: bool,
+    channels: Vec<ChannelMonitor>,
+}
+
+impl std::fmt::Debug for Monitor {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Monitor")
+            .field("audition", &self.audition)
+            .field("compare", &self.compare)
+            .field("channels", &self.channels.len())
+            .finish()
+    }
+}
+
+impl Monitor {
+    pub fn new(num_channels: usize) -> Self {
+        Self {
+            audition: Audition::Output,
+            compare: false,
+            channels: (0..num_channels.max(1))
+                .map(|_| ChannelMonitor::new())
+                .collect(),
+        }
+    }
+
+    pub fn reset(&mut self) {
+        for c in self.channels.iter_mut() {
+            c.delay.fill(0.0);
+            c.pos = 0;
+            for s in c.recent.samples.iter_mut() {
+                s.fill(0.0);
+            }
+            c.recent.written = 0;
+        }
+    }
+
+    /// one sample of a channel, input is before processing with the output gain applied
+    /// wet is the share of the output mixed with the lined up input, returns the sample to play for the current audition mode
+    pub fn process(
+        &mut self,
+        channel: usize,
+        input: f32,
+        output: f32,
+        latency: usize,
+        wet: f32,
+    ) -> f32 {
+        let c = &mut self.channels[channel];
+        c.delay[c.pos] = input;
+        let aligned = c.delay[(c.pos + MAX_LATENCY - latency.min(MAX_LATENCY - 1)) % MAX_LATENCY];
+        c.pos = (c.pos + 1) % MAX_LATENCY;
+        // exactly the output when fully wet
+        let output = wet * output + (1.0 - wet) * aligned;
+        let residual = aligned - output;
+
+        if self.compare {
+            c.recent.push(aligned, output, residual);
+        }
+
+        match self.audition {
+            Audition::Output => output,
+            Audition::Input => aligned,
+            Audition::Residual => residual,
+        }
+    }
+
+    /// copy every channel's history out for the ui, only allocates when the channel count changes
+    pub fn copy_recent(&self, out: &mut Vec<Recent>) {
+        out.resize_with(self.channels.len(), Recent::new);
+        for (o, c) in out.iter_mut().zip(self.channels.iter()) {
+            o.copy_from(&c.recent);
+        }
+    }
+}
+
+/// turns copies of the monitor's history into comparisons, away from the audio thread
+pub struct Comparer {
+    fft: Arc<dyn Fft<f32>>,
+    window: Vec<f32>,
+    /// samples of each channel already taken
+    taken: Vec<usize>,
+}
+
+impl Comparer {
+    pub fn new() -> Self {
+        Self {
+            fft: FftPlanner::new().plan_fft_forward(COMPARE_FFT_SIZE),
+            window: window(WindowKind::Hann, COMPARE_FFT_SIZE),
+            taken: vec![],
+        }
+    }
+
+    /// samples written since the last call, at most HISTORY_LENGTH, and the current spectra, mapped like the main spectrum
+    pub fn take(&mut self, recent: &[Recent], mapping: &FreqMapping) -> Vec<ChannelComparison> {
+        self.taken.resize(recent.len(), 0);
+        let mut buf = vec![Complex::new(0.0, 0.0); COMPARE_FFT_SIZE];
+        // same units as the sdft, which is unwindowed
+        let coherent_gain = 0.5;
+        let mut out = vec![];
+        for (r, taken) in recent.iter().zip(self.taken.iter_mut()) {
+            // the history starts over when the monitor is reset
+            let new = if r.written >= *taken {
+                r.written - *taken
+            } else {
+                r.written
+            };
+            let n = new.min(HISTORY_LENGTH);
+            *taken = r.written;
+
+            let mut streams = vec![];
+            let mut spectra = vec![];
+            for stream in 0..3 {
+                streams.push(r.last(stream, n, r.written).cloned().collect());
+                for (b, (x, w)) in buf.iter_mut().zip(
+                    r.last(stream, COMPARE_FFT_SIZE, r.written)
+                        .zip(self.window.iter()),
+                ) {
+                    *b = Complex::new(x * w, 0.0);
+                }
+                self.fft.process(&mut buf);
+                let mags: Vec<f32> = buf[..COMPARE_FFT_SIZE / 2]
+                    .iter()
+                    .map(|b| b.norm() / coherent_gain)
+                    .collect();
+                spectra.push(mapping.map(&mags));
+            }
+            let mut spectra = spectra.into_iter();
+            let mut streams = streams.into_iter();
+            out.push(ChannelComparison {
+                input: streams.next().unwrap(),
+                output: streams.next().unwrap(),
+                residual: streams.next().unwrap(),
+                input_spectrum: spectra.next().unwrap(),
+                output_spectrum: spectra.next().unwrap(),
+                residual_spectrum: spectra.next().unwrap(),
+            });
+        }
+        out
+    }
+}
+
+impl Default for Comparer {
+    fn default() -> Self {
+        Comparer::new()
+    }
+}
+
+impl Default for Monitor {
+    fn default() -> Self {
+        Monitor::new(1)
+    }
+}
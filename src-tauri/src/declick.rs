@@ -0,0 +1,388 @@
+use crate::{constants::SAMPLING_RATE, types::DeclickParams};
+
+/// samples per analysis hop, the declicker delays its output by two of these
+pub const DECLICK_HOP: usize = 512;
+/// AR model order, enough to predict most program material over a click
+pub const AR_ORDER: usize = 32;
+/// detections closer than this many samples are treated as one click
+const MERGE_GAP: usize = 8;
+/// samples added on each side of a detected click before interpolating
+const CLICK_PAD: usize = 2;
+/// ms, longest click that is repaired, the interpolation is O(n^3) in the click length and runs in the callback
+pub const MAX_CLICK_LENGTH: f32 = 3.0;
+
+/// detects impulses from the AR prediction error and interpolates over them (LSAR)
+/// works on a window of three hops, only clicks in the middle hop are repaired so there is always context on both sides
+#[derive(Clone, Debug)]
+pub struct Declicker {
+    pub params: DeclickParams,
+    /// Hz, for the max click length
+    pub sample_rate: f32,
+    /// the last 3 hops of input, repaired in place
+    window: Vec<f32>,
+    /// repaired middle hop, waiting to be output
+    output: Vec<f32>,
+    /// position in the current hop, for both input and output
+    pos: usize,
+    coeffs: Vec<f32>,
+    error: Vec<f32>,
+    /// sorted in place for the median of the error
+    abs_error: Vec<f32>,
+    scratch: ArScratch,
+    /// clicks found in the last window, for reporting
+    pub clicks_found: usize,
+}
+
+impl Declicker {
+    pub fn new(params: DeclickParams, sample_rate: f32) -> Self {
+        Self {
+            params,
+            sample_rate,
+            window: vec![0.0; 3 * DECLICK_HOP],
+            output: vec![0.0; DECLICK_HOP],
+            pos: 0,
+            coeffs: vec![0.0; AR_ORDER + 1],
+            error: vec![0.0; 3 * DECLICK_HOP],
+            abs_error: vec![0.0; 3 * DECLICK_HOP - AR_ORDER],
+            scratch: ArScratch::new(AR_ORDER, params.max_samples(sample_rate)),
+            clicks_found: 0,
+        }
+    }
+
+    /// room for repairing clicks with these params, built before they're sent
+    pub fn scratch(params: DeclickParams, sample_rate: f32) -> ArScratch {
+        ArScratch::new(AR_ORDER, params.max_samples(sample_rate))
+    }
+
+    /// new params from the ui, the scratch is only swapped for the one built with them if clicks can be longer than before
+    /// the one that isn't used is left in scratch so it's freed off the audio thread
+    pub fn set_params(&mut self, params: DeclickParams, scratch: &mut ArScratch) {
+        self.params = params;
+        if !self
+            .scratch
+            .fits(AR_ORDER, params.max_samples(self.sample_rate))
+        {
+            std::mem::swap(&mut self.scratch, scratch);
+        }
+    }
+
+    pub fn reset(&mut self) {
+        self.window.iter_mut().for_each(|x| *x = 0.0);
+        self.output.iter_mut().for_each(|x| *x = 0.0);
+        self.pos = 0;
+    }
+
+    /// output is delayed by 2 hops
+    pub fn process(&mut self, sample: f32) -> f32 {
+        let out = self.output[self.pos];
+        self.window[2 * DECLICK_HOP + self.pos] = sample;
+        self.pos += 1;
+        if self.pos == DECLICK_HOP {
+            self.pos = 0;
+            self.repair_window();
+            self.output
+                .copy_from_slice(&self.window[DECLICK_HOP..2 * DECLICK_HOP]);
+            self.window.copy_within(DECLICK_HOP.., 0);
+        }
+        out
+    }
+
+    fn repair_window(&mut self) {
+        self.clicks_found = 0;
+        if !fit_ar(&self.window, &mut self.coeffs, &mut self.scratch) {
+            return;
+        }
+
+        // prediction error, first AR_ORDER samples don't have enough history
+        for n in 0..self.window.len() {
+            self.error[n] = if n < AR_ORDER {
+                0.0
+            } else {
+                (0..=AR_ORDER)
+                    .map(|k| self.coeffs[k] * self.window[n - k])
+                    .sum()
+            };
+        }
+
+        // robust estimate of the error level, clicks are outliers so they don't move the median much
+        for (a, e) in self.abs_error.iter_mut().zip(self.error[AR_ORDER..].iter()) {
+            *a = e.abs();
+        }
+        let mid = self.abs_error.len() / 2;
+        let (_, median, _) = self.abs_error.select_nth_unstable_by(mid, |a, b| {
+            a.partial_cmp(b).unwrap_or(std::cmp::Ordering::Equal)
+        });
+        let sigma = 1.4826 * *median;
+        if sigma <= 1e-9 {
+            return;
+        }
+        let threshold = self.params.threshold() * sigma;
+        let max_len = self.params.max_samples(self.sample_rate);
+
+        // look for clicks that start in the middle hop
+        let mut n = DECLICK_HOP;
+        while n < 2 * DECLICK_HOP {
+            if self.error[n].abs() <= threshold {
+                n += 1;
+                continue;
+            }
+            // the error from a click keeps going for AR_ORDER samples after it, only the first detections
+            // and anything joined within MERGE_GAP count towards its length
+            let start = n;
+            let mut end = n;
+            let mut m = n + 1;
+            while m < self.window.len() - AR_ORDER && m <= end + MERGE_GAP {
+                if self.error[m].abs() > threshold {
+                    end = m;
+                }
+                m += 1;
+            }
+
+            let s = start.saturating_sub(CLICK_PAD).max(AR_ORDER);
+            let e = (end + 1 + CLICK_PAD).min(self.window.len() - AR_ORDER);
+            let len = e - s;
+            // longer than a click, probably a transient that belongs to the program
+            if len <= max_len {
+                lsar_interpolate(&mut self.window, &self.coeffs, s, len, &mut self.scratch);
+                self.clicks_found += 1;
+            }
+            n = end + AR_ORDER.max(MERGE_GAP);
+        }
+    }
+}
+
+impl Default for Declicker {
+    fn default() -> Self {
+        Declicker::new(DeclickParams::new(), SAMPLING_RATE)
+    }
+}
+
+/// buffers for fit_ar, lsar_interpolate and solve, so repairing a window doesn't allocate
+#[derive(Debug, Default)]
+pub struct ArScratch {
+    r: Vec<f64>,
+    a: Vec<f64>,
+    tmp: Vec<f64>,
+    au: Vec<f64>,
+    b: Vec<f64>,
+    m: Vec<f64>,
+    rhs: Vec<f64>,
+    x: Vec<f64>,
+}
+
+impl ArScratch {
+    /// room for a model of order p and gaps up to max_len samples
+    pub fn new(p: usize, max_len: usize) -> Self {
+        let mut scratch = ArScratch::default();
+        scratch.reserve(p, max_len);
+        scratch
+    }
+
+    pub fn reserve(&mut self, p: usize, max_len: usize) {
+        let rows = max_len + p;
+        for (v, n) in [
+            (&mut self.r, p + 1),
+            (&mut self.a, p + 1),
+            (&mut self.tmp, p + 1),
+            (&mut self.au, rows * max_len),
+            (&mut self.b, rows),
+            (&mut self.m, max_len * max_len),
+            (&mut self.rhs, max_len),
+            (&mut self.x, max_len),
+        ] {
+            v.reserve(n.saturating_sub(v.len()));
+        }
+    }
+
+    /// already has room for a model of order p and gaps up to max_len samples
+    pub fn fits(&self, p: usize, max_len: usize) -> bool {
+        let rows = max_len + p;
+        [
+            (&self.r, p + 1),
+            (&self.a, p + 1),
+            (&self.tmp, p + 1),
+            (&self.au, rows * max_len),
+            (&self.b, rows),
+            (&self.m, max_len * max_len),
+            (&self.rhs, max_len),
+            (&self.x, max_len),
+        ]
+        .iter()
+        .all(|(v, n)| v.capacity() >= *n)
+    }
+}
+
+/// a derived clone only keeps the length, the reserved room has to come along too
+/// or a cloned declicker allocates the first time it repairs a click
+impl Clone for ArScratch {
+    fn clone(&self) -> Self {
+        let keep = |v: &Vec<f64>| {
+            let mut c = Vec::with_capacity(v.capacity());
+            c.extend_from_slice(v);
+            c
+        };
+        Self {
+            r: keep(&self.r),
+            a: keep(&self.a),
+            tmp: keep(&self.tmp),
+            au: keep(&self.au),
+            b: keep(&self.b),
+            m: keep(&self.m),
+            rhs: keep(&self.rhs),
+            x: keep(&self.x),
+        }
+    }
+}
+
+/// n zeros, reusing the allocation when it's big enough
+fn zeroed(v: &mut Vec<f64>, n: usize) -> &mut [f64] {
+    v.clear();
+    v.resize(n, 0.0);
+    v
+}
+
+/// AR coefficients from the autocorrelation (Levinson-Durbin)
+/// coeffs are the prediction error filter, coeffs[0] = 1 and e(n) = sum coeffs[k] x(n-k)
+pub fn fit_ar(x: &[f32], coeffs: &mut [f32], scratch: &mut ArScratch) -> bool {
+    let p = coeffs.len() - 1;
+    let r = zeroed(&mut scratch.r, p + 1);
+    for (lag, rl) in r.iter_mut().enumerate() {
+        *rl = x[lag..]
+            .iter()
+            .zip(x.iter())
+            .map(|(a, b)| *a as f64 * *b as f64)
+            .sum();
+    }
+    if r[0] <= 1e-12 {
+        return false;
+    }
+    // slight white noise correction keeps the recursion stable
+    r[0] *= 1.0 + 1e-6;
+
+    let a = zeroed(&mut scratch.a, p + 1);
+    let tmp = zeroed(&mut scratch.tmp, p + 1);
+    a[0] = 1.0;
+    let mut err = r[0];
+    for i in 1..=p {
+        let mut acc = r[i];
+        for j in 1..i {
+            acc += a[j] * r[i - j];
+        }
+        let k = -acc / err;
+        tmp[..=i].copy_from_slice(&a[..=i]);
+        for j in 1..i {
+            a[j] = tmp[j] + k * tmp[i - j];
+        }
+        a[i] = k;
+        err *= 1.0 - k * k;
+        if err <= 0.0 {
+            return false;
+        }
+    }
+    for (c, a) in coeffs.iter_mut().zip(a.iter()) {
+        *c = *a as f32;
+    }
+    true
+}
+
+/// least squares AR interpolation of x[start..start + len]
+/// the unknown samples are chosen to minimize the prediction error over the gap and the AR_ORDER samples after it
+pub fn lsar_interpolate(
+    x: &mut [f32],
+    coeffs: &[f32],
+    start: usize,
+    len: usize,
+    scratch: &mut ArScratch,
+) {
+    let p = coeffs.len() - 1;
+    if len == 0 || start < p || start + len + p > x.len() {
+        return;
+    }
+    let rows = len + p;
+    // e(n) for n in start..start + rows, split into the unknown part A_u x_u and the known part b
+    let au = zeroed(&mut scratch.au, rows * len);
+    let b = zeroed(&mut scratch.b, rows);
+    for r in 0..rows {
+        let n = start + r;
+        for (k, c) in coeffs.iter().enumerate() {
+            let m = n - k;
+            if m >= start && m < start + len {
+                au[r * len + (m - start)] = *c as f64;
+            } else {
+                b[r] += *c as f64 * x[m] as f64;
+            }
+        }
+    }
+
+    // normal equations, (A_u^T A_u) x_u = -A_u^T b
+    let m = zeroed(&mut scratch.m, len * len);
+    let rhs = zeroed(&mut scratch.rhs, len);
+    for i in 0..len {
+        for j in i..len {
+            let v: f64 = (0..rows).map(|r| au[r * len + i] * au[r * len + j]).sum();
+            m[i * len + j] = v;
+            m[j * len + i] = v;
+        }
+        rhs[i] = -(0..rows).map(|r| au[r * len + i] * b[r]).sum::<f64>();
+    }
+
+    let xu = zeroed(&mut scratch.x, len);
+    if solve(m, rhs, xu) {
+        for (i, v) in xu.iter().enumerate() {
+            x[start + i] = *v as f32;
+        }
+    }
+}
+
+/// gaussian elimination with partial pivoting, the solution goes in x, false if m is singular
+pub fn solve(m: &mut [f64], rhs: &mut [f64], x: &mut [f64]) -> bool {
+    let n = x.len();
+    for col in 0..n {
+        let pivot = (col..n)
+            .max_by(|a, b| {
+                m[a * n + col]
+                    .abs()
+                    .partial_cmp(&m[b * n + col].abs())
+                    .unwrap_or(std::cmp::Ordering::Equal)
+            })
+            .unwrap_or(col);
+        if m[pivot * n + col].abs() < 1e-12 {
+            return false;
+        }
+        if pivot != col {
+            for k in 0..n {
+                m.swap(col * n + k, pivot * n + k);
+            }
+            rhs.swap(col, pivot);
+        }
+        for row in col + 1..n {
+            let f = m[row * n + col] / m[col * n + col];
+            for k in col..n {
+                m[row * n + k] -= f * m[col * n + k];
+            }
+            rhs[row] -= f * rhs[col];
+        }
+    }
+    for row in (0..n).rev() {
+        let mut acc = rhs[row];
+        for k in row + 1..n {
+            acc -= m[row * n + k] * x[k];
+        }
+        x[row] = acc / m[row * n + row];
+    }
+    true
+}
+
+impl DeclickParams {
+    /// max click length in samples, never more than MAX_CLICK_LENGTH
+    pub fn max_samples(&self, sample_rate: f32) -> usize {
+        let ms = self.max_click_length.clamp(0.0, MAX_CLICK_LENGTH);
+        ((ms * 0.001 * sample_rate) as usize).max(1)
+    }
+
+    /// detection threshold in multiples of the prediction error level
+    /// sensitivity 0 only catches huge clicks, 1 catches almost anything above the noise
+    pub fn threshold(&self) -> f32 {
+        10.0 - 7.0 * self.sensitivity.clamp(0.0, 1.0)
+    }
+}
@@ -0,0 +1,171 @@
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    declick::{lsar_interpolate, solve, ArScratch, AR_ORDER},
+    types::DeclipParams,
+};
+
+/// samples on each side of a clipped run used to fit the AR model
+const CLIP_CONTEXT: usize = 1024;
+/// longer runs are reported but not reconstructed, the AR model can't extrapolate that far
+pub const MAX_CLIP_LENGTH: usize = 256;
+
+/// a run of clipped samples
+#[derive(Clone, Copy, Debug, Serialize, Deserialize, PartialEq)]
+pub struct Clip {
+    pub channel: usize,
+    /// index into the interleaved file samples at the file rate, frame * channels + channel,
+    /// playback time is at the device rate so it only lines up when the two rates match
+    pub start: usize,
+    /// samples in the channel
+    pub length: usize,
+    /// false if the run was too long to reconstruct or declipping is off
+    pub repaired: bool,
+}
+
+fn clip_level(samples: &[f32], params: &DeclipParams) -> f32 {
+    let peak = samples.iter().fold(0.0f32, |m, x| m.max(x.abs()));
+    params.threshold.clamp(0.0, 1.0) * peak
+}
+
+/// runs of at least min_run samples at or above the clip level, all with the same sign
+/// the clip level is relative to the channel peak so files that were turned down after clipping are still caught
+pub fn find_clips(samples: &[f32], params: &DeclipParams) -> Vec<(usize, usize)> {
+    let level = clip_level(samples, params);
+    if level <= 0.0 {
+        return vec![];
+    }
+
+    let mut clips = vec![];
+    let mut n = 0;
+    while n < samples.len() {
+        if samples[n].abs() < level {
+            n += 1;
+            continue;
+        }
+        let positive = samples[n] > 0.0;
+        let start = n;
+        while n < samples.len() && samples[n].abs() >= level && (samples[n] > 0.0) == positive {
+            n += 1;
+        }
+        if n - start >= params.min_run.max(1) {
+            clips.push((start, n - start));
+        }
+    }
+    clips
+}
+
+/// reconstruct the clipped runs of a single channel in place, returns the runs and whether each was repaired
+/// each run is filled in with LSAR from the surrounding context, then kept consistent with the clipping:
+/// a reconstructed sample can't be closer to zero than the value it was clipped to
+pub fn declip_channel(samples: &mut [f32], params: &DeclipParams) -> Vec<(usize, usize, bool)> {
+    let clips = find_clips(samples, params);
+    let level = clip_level(samples, params);
+    let mut coeffs = vec![0.0; AR_ORDER + 1];
+    let mut scratch = ArScratch::new(AR_ORDER, MAX_CLIP_LENGTH);
+    let mut found = vec![];
+    for (start, len) in clips {
+        if !params.enabled || len > MAX_CLIP_LENGTH {
+            found.push((start, len, false));
+            continue;
+        }
+        let cs = start.saturating_sub(CLIP_CONTEXT);
+        let ce = (start + len + CLIP_CONTEXT).min(samples.len());
+        // not enough context at the very start or end of the file
+        if start - cs < AR_ORDER || ce - (start + len) < AR_ORDER {
+            found.push((start, len, false));
+            continue;
+        }
+
+        let mut segment = samples[cs..ce].to_vec();
+        let mask: Vec<bool> = segment.iter().map(|x| x.abs() < level).collect();
+        if !fit_ar_unclipped(&segment, &mask, &mut coeffs) {
+            found.push((start, len, false));
+            continue;
+        }
+        lsar_interpolate(&mut segment, &coeffs, start - cs, len, &mut scratch);
+
+        for i in 0..len {
+            let clipped = samples[start + i];
+            let v = segment[start - cs + i];
+            samples[start + i] = if clipped > 0.0 {
+                v.max(clipped)
+            } else {
+                v.min(clipped)
+            };
+        }
+        found.push((start, len, true));
+    }
+    found
+}
+
+/// AR fit by least squares over the stretches with no clipped samples (covariance method)
+/// heavily clipped audio is mostly flat tops, including them would make the model predict more flat tops
+fn fit_ar_unclipped(x: &[f32], mask: &[bool], coeffs: &mut [f32]) -> bool {
+    let p = coeffs.len() - 1;
+    // normal equations for predicting x(n) from x(n-1)..x(n-p)
+    let mut m = vec![0.0f64; p * p];
+    let mut rhs = vec![0.0f64; p];
+    let mut rows = 0;
+    for n in p..x.len() {
+        if !mask[n - p..=n].iter().all(|m| *m) {
+            continue;
+        }
+        rows += 1;
+        for i in 0..p {
+            let xi = x[n - 1 - i] as f64;
+            rhs[i] -= xi * x[n] as f64;
+            for j in 0..p {
+                m[i * p + j] += xi * x[n - 1 - j] as f64;
+            }
+        }
+    }
+    if rows < 2 * p {
+        return false;
+    }
+    // same white noise correction as the autocorrelation fit
+    for i in 0..p {
+        m[i * p + i] *= 1.0 + 1e-6;
+    }
+
+    let mut a = vec![0.0f64; p];
+    if solve(&mut m, &mut rhs, &mut a) {
+        coeffs[0] = 1.0;
+        for (c, a) in coeffs[1..].iter_mut().zip(a.iter()) {
+            *c = *a as f32;
+        }
+        true
+    } else {
+        false
+    }
+}
+
+/// declip interleaved samples, one set of params per channel
+pub fn declip(samples: &mut [f32], channel_params: &[DeclipParams]) -> Vec<Clip> {
+    let num_channels = channel_params.len().max(1);
+    let mut clips = vec![];
+    for (channel, params) in channel_params.iter().enumerate() {
+        let mut channel_samples: Vec<f32> = samples
+            .iter()
+            .skip(channel)
+            .step_by(num_channels)
+            .cloned()
+            .collect();
+        let found = declip_channel(&mut channel_samples, params);
+        if params.enabled {
+            for (i, s) in channel_samples.iter().enumerate() {
+                samples[i * num_channels + channel] = *s;
+            }
+        }
+        for (start, length, repaired) in found {
+            clips.push(Clip {
+                channel,
+                start: start * num_channels + channel,
+                length,
+                repaired,
+            });
+        }
+    }
+    clips.sort_by_key(|c| c.start);
+    clips
+}
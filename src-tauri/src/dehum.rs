@@ -0,0 +1,224 @@
+use std::f32::consts::PI;
+
+use crate::{
+    constants::SAMPLING_RATE,
+    fourier::averaged_stft,
+    types::{HumFundamental, HumParams, IIR2},
+};
+
+/// fft size for finding the fundamental, needs a couple Hz of resolution to tell 50 from 60
+pub const HUM_FFT_SIZE: usize = 16384;
+/// samples used by the drift tracker
+const TRACK_SIZE: usize = 8192;
+/// how often the tracker looks for drift, in samples
+const TRACK_INTERVAL: usize = 4096;
+/// Hz, the tracker won't wander further than this from the nominal fundamental
+const MAX_DRIFT: f32 = 2.0;
+/// Hz, spacing of the candidates the tracker compares around the current fundamental
+const TRACK_STEP: f32 = 0.25;
+/// only the first few harmonics are used to track, higher ones are often buried
+const TRACK_HARMONICS: usize = 3;
+
+/// notch comb for mains hum, follows small drifts of the fundamental
+#[derive(Clone, Debug)]
+pub struct Dehum {
+    pub params: HumParams,
+    /// current fundamental, starts at nominal and moves when tracking
+    pub freq: f32,
+    pub notches: Vec<IIR2>,
+    /// Hz, the notches and the tracker are designed for it
+    pub sample_rate: f32,
+    history: Vec<f32>,
+    pos: usize,
+    count: usize,
+}
+
+impl Dehum {
+    pub fn new(params: HumParams, sample_rate: f32) -> Self {
+        Self {
+            params,
+            freq: params.nominal(),
+            notches: Dehum::comb(params, sample_rate),
+            sample_rate,
+            history: vec![0.0; TRACK_SIZE],
+            pos: 0,
+            count: 0,
+        }
+    }
+
+    /// one notch per harmonic of the nominal fundamental, stopping below nyquist
+    /// built before params are sent so the callback only swaps it in
+    pub fn comb(params: HumParams, sample_rate: f32) -> Vec<IIR2> {
+        let freq = params.nominal();
+        let max = ((sample_rate / 2.0 - 1.0) / freq.max(1.0)) as usize;
+        (1..=params.harmonics.min(max))
+            .map(|h| IIR2::notch(h as f32 * freq, params.Q, sample_rate))
+            .collect()
+    }
+
+    /// new params from the ui, the comb is only swapped for one built from them if something it depends on changed
+    /// the replaced comb is left in comb so it's freed off the audio thread
+    pub fn set_params(&mut self, params: HumParams, comb: &mut Vec<IIR2>) {
+        let rebuild = params.nominal() != self.params.nominal()
+            || params.harmonics != self.params.harmonics
+            || params.Q != self.params.Q;
+        self.params = params;
+        if rebuild {
+            self.freq = params.nominal();
+            std::mem::swap(&mut self.notches, comb);
+        }
+    }
+
+    /// retune the existing notches, filter state is kept so there is no click
+    fn retune(&mut self) {
+        for (h, notch) in self.notches.iter_mut().enumerate() {
+            notch.update_coeffs(IIR2::notch(
+                (h + 1) as f32 * self.freq,
+                self.params.Q,
+                self.sample_rate,
+            ));
+        }
+    }
+
+    /// back to the nominal fundamental with the same notches, nothing is rebuilt on the audio thread
+    pub fn reset(&mut self) {
+        self.freq = self.params.nominal();
+        self.history.fill(0.0);
+        self.pos = 0;
+        self.count = 0;
+        self.retune();
+        for notch in self.notches.iter_mut() {
+            notch.x = [0.0; 2];
+            notch.y = [0.0; 2];
+        }
+    }
+
+    pub fn process(&mut self, sample: f32) -> f32 {
+        if self.params.track {
+            self.history[self.pos] = sample;
+            self.pos = (self.pos + 1) % TRACK_SIZE;
+            self.count += 1;
+            if self.count >= TRACK_INTERVAL {
+                self.count = 0;
+                self.track();
+            }
+        }
+
+        let mut out = sample;
+        for notch in self.notches.iter_mut() {
+            out = notch.process(out);
+        }
+        out
+    }
+
+    /// compare harmonic energy at a few candidates around the current fundamental and move toward the best one
+    fn track(&mut self) {
+        let mut best = self.freq;
+        let mut best_power = 0.0;
+        let mut powers = [0.0f32; 3];
+        for i in -1..=1 {
+            let f = self.freq + i as f32 * TRACK_STEP;
+            let mut p = 0.0;
+            for h in 1..=TRACK_HARMONICS {
+                p += self.goertzel(h as f32 * f);
+            }
+            powers[(i + 1) as usize] = p;
+            if p > best_power {
+                best_power = p;
+                best = f;
+            }
+        }
+        if best_power <= 0.0 {
+            return;
+        }
+
+        // parabolic interpolation between the three candidates
+        let (a, b, c) = (powers[0], powers[1], powers[2]);
+        let denom = a - 2.0 * b + c;
+        if best == self.freq && denom.abs() > 1e-12 {
+            let offset = (0.5 * (a - c) / denom).clamp(-1.0, 1.0);
+            best = self.freq + offset * TRACK_STEP;
+        }
+
+        let nominal = self.params.nominal();
+        let target = best.clamp(nominal - MAX_DRIFT, nominal + MAX_DRIFT);
+        // move slowly, a single window can be fooled by program material
+        self.freq = 0.8 * self.freq + 0.2 * target;
+        self.retune();
+    }
+
+    /// power of a single frequency over the tracking history
+    fn goertzel(&self, freq: f32) -> f32 {
+        let w = 2.0 * PI * freq / self.sample_rate;
+        let c = 2.0 * w.cos();
+        let mut s1 = 0.0;
+        let mut s2 = 0.0;
+        // oldest sample is at pos
+        for x in self.history[self.pos..]
+            .iter()
+            .chain(self.history[..self.pos].iter())
+        {
+            let s = x + c * s1 - s2;
+            s2 = s1;
+            s1 = s;
+        }
+        s1 * s1 + s2 * s2 - c * s1 * s2
+    }
+}
+
+impl Default for Dehum {
+    fn default() -> Self {
+        Dehum::new(HumParams::new(), SAMPLING_RATE)
+    }
+}
+
+/// find the hum fundamental from the averaged spectrum
+/// candidates around 50 and 60 Hz are scored by the summed magnitude at their harmonics
+pub fn detect_hum_fundamental(samples: &[f32], harmonics: usize, sample_rate: f32) -> Option<f32> {
+    let mut size = HUM_FFT_SIZE;
+    while size > samples.len() && size > 1024 {
+        size /= 2;
+    }
+    if samples.len() < size {
+        return None;
+    }
+
+    let spectrum = averaged_stft(samples, size, size).ok()?;
+    let bin_width = sample_rate / size as f32;
+    let harmonics = harmonics.max(1);
+
+    let mut best = None;
+    let mut best_score = 0.0;
+    for nominal in [50.0, 60.0] {
+        // 0.1 Hz steps within the drift range of each nominal
+        for i in -20..=20 {
+            let f0 = nominal + i as f32 * 0.1;
+            let mut score = 0.0;
+            for h in 1..=harmonics {
+                let bin = h as f32 * f0 / bin_width;
+                let k = bin.floor() as usize;
+                if k + 1 >= spectrum.len() {
+                    break;
+                }
+                let frac = bin - k as f32;
+                score += (1.0 - frac) * spectrum[k] + frac * spectrum[k + 1];
+            }
+            if score > best_score {
+                best_score = score;
+                best = Some(f0);
+            }
+        }
+    }
+    best
+}
+
+/// hum params ready to process with, auto fundamental is detected from the samples
+pub fn resolve_hum_params(params: HumParams, samples: &[f32], sample_rate: f32) -> HumParams {
+    let mut params = params;
+    if params.fundamental == HumFundamental::Auto {
+        if let Some(f) = detect_hum_fundamental(samples, params.harmonics, sample_rate) {
+            params.freq = f;
+        }
+    }
+    params
+}
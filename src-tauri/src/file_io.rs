@@ -1,13 +1,30 @@
 use cpal::traits::StreamTrait;
 use samplerate::{convert, ConverterType};
+use serde::{Deserialize, Serialize};
 use std::{fs::File, path::PathBuf, str::FromStr};
 use tauri::{AppHandle, State, Window};
 
 use crate::{
-    audio::device_sample_rate,
+    audio::{device_sample_rate, get_wav_samples, playback_sample_rate},
     constants::{from_log, DOWN_RATE},
-    sql::{query_filter_bank, query_ui_params},
-    types::{MStreamSend, StereoChoice, StereoParams},
+    declick::Declicker,
+    declip::declip,
+    dehum::{resolve_hum_params, Dehum},
+    errors::DenoiserResult,
+    fourier::{block_denoise, sliding_denoise, WindowKind, SLIDING_DELAY},
+    generator::Source,
+    metering::{Meter, Metering},
+    metrics::{channel_metrics, quality_metrics, QualityMetrics},
+    mix::Mix,
+    nlms::Nlms,
+    normalize::{normalize, Normalization},
+    sql::{
+        query_adaptive_cancel, query_declick, query_declip, query_filter_bank,
+        query_gain_smoothing, query_hum, query_mix, query_normalize, query_spectral_gate,
+        query_stereo_mode, query_ui_params, query_vad,
+    },
+    types::{AudioParams, DeclipParams, MStreamSend, StereoChoice, StereoModeParams, StereoParams},
+    vad::{vad_segments, Vad, VadSegment},
 };
 #[tauri::command]
 pub async fn get_time_data(
@@ -36,7 +53,7 @@ pub async fn get_time_data(
                 let resampled = convert(
                     head.sample_rate,
                     device_sample_rate.0,
-                    1,
+                    head.channels.max(1) as usize,
                     ConverterType::SincBestQuality,
                     &samples,
                 )
@@ -88,6 +105,48 @@ pub async fn get_time_data(
     Ok(time_data)
 }
 
+/// speech and non-speech segments of the file for drawing under the time plot
+/// starts are in playback time, both uses the average of the channels
+#[tauri::command]
+pub async fn get_vad_segments(
+    path: String,
+    stereo_choice: StereoChoice,
+    app_handle: AppHandle,
+) -> Result<Vec<VadSegment>, String> {
+    let p = PathBuf::from_str(path.as_str()).map_err(|_| "bad path".to_string())?;
+    if !p.is_file() {
+        return Err("file not found".to_string());
+    }
+    let db_path = app_handle
+        .path_resolver()
+        .app_local_data_dir()
+        .expect("app local data dir should exist")
+        .join("db.sqlite");
+    let vad_params = query_vad(stereo_choice, &db_path).unwrap_or_default();
+
+    let thread = tauri::async_runtime::spawn(async move {
+        let (samples, num_channels) = get_wav_samples(p);
+        let channel = match stereo_choice {
+            StereoChoice::Left => Some(0),
+            StereoChoice::Right => Some(1),
+            StereoChoice::Both => None,
+            StereoChoice::Channel(i) => Some(i),
+        };
+        vad_segments(
+            &samples,
+            num_channels,
+            channel,
+            &vad_params,
+            256,
+            playback_sample_rate(),
+        )
+    });
+
+    thread
+        .await
+        .map_err(|_| "failed to find speech".to_string())
+}
+
 #[tauri::command]
 pub async fn process_export(
     streamsend: State<'_, MStreamSend>,
@@ -107,15 +166,9 @@ pub async fn process_export(
         .pause();
 
     // file samples are not an audio param, stream is remade when file is changed so this stays
-    let file_samples;
-    let is_stereo;
-    let head;
-    // need to update this
     let p = PathBuf::from_str(file_path.as_str()).expect("bad path");
-    // (file_samples, is_stereo) = get_wav_samples(p);
-    let f = File::open(p).unwrap();
-    (head, file_samples) = wav_io::read_from_file(f).unwrap();
-    is_stereo = if head.channels == 1 { false } else { true };
+    let source = Source::load(&p).map_err(|e| e.to_string())?;
+    let (num_channels, sample_rate) = (source.num_channels, source.sample_rate);
 
     let db_path = app_handle
         .path_resolver()
@@ -123,123 +176,22 @@ pub async fn process_export(
         .expect("app local data dir should exist")
         .join("db.sqlite");
 
-    // query db
-
-    let mut stereo_params = StereoParams::new();
-    stereo_params.is_stereo = is_stereo;
-    stereo_params.num_file_samples = file_samples.len();
-
-    match stereo_choice {
-        StereoChoice::Both => {
-            let filter_bank = query_filter_bank(stereo_choice, &db_path);
-            let ui_params = query_ui_params(stereo_choice, &db_path);
-            if filter_bank.is_err() || ui_params.is_err() {
-                // return something
-            }
-            let fb = filter_bank.unwrap();
-            stereo_params.left.filters = fb.clone().into();
-            stereo_params.right.filters = fb.into();
-            stereo_params.left.noise_spectrum = stereo_params.left.filters.parallel_transfer(256);
-            stereo_params.right.noise_spectrum = stereo_params.right.filters.parallel_transfer(256);
-            let p = ui_params.unwrap();
-            stereo_params.left.ui_params.noise_gain = from_log(p.noise_gain);
-            stereo_params.left.ui_params.output_gain = from_log(p.output_gain);
-            stereo_params.left.ui_params.pre_smooth_gain = p.pre_smooth_gain;
-            stereo_params.left.ui_params.post_smooth_gain = p.post_smooth_gain;
-            stereo_params.right.ui_params.noise_gain = from_log(p.noise_gain);
-            stereo_params.right.ui_params.output_gain = from_log(p.output_gain);
-            stereo_params.right.ui_params.pre_smooth_gain = p.pre_smooth_gain;
-            stereo_params.right.ui_params.post_smooth_gain = p.post_smooth_gain;
-        }
-        _ => {
-            let left_bank = query_filter_bank(StereoChoice::Left, &db_path);
-            let right_bank = query_filter_bank(StereoChoice::Right, &db_path);
-            let left_ui_params = query_ui_params(StereoChoice::Left, &db_path);
-            let right_ui_params = query_ui_params(StereoChoice::Right, &db_path);
-            if left_bank.is_err()
-                || left_ui_params.is_err()
-                || right_bank.is_err()
-                || right_ui_params.is_err()
-            {
-                // return something
-            }
-            stereo_params.left.filters = left_bank.unwrap().into();
-            stereo_params.right.filters = right_bank.unwrap().into();
-            stereo_params.left.noise_spectrum = stereo_params.left.filters.parallel_transfer(256);
-            stereo_params.right.noise_spectrum = stereo_params.right.filters.parallel_transfer(256);
-            let lu = left_ui_params.unwrap();
-            let ru = right_ui_params.unwrap();
-
-            stereo_params.left.ui_params.noise_gain = from_log(lu.noise_gain);
-            stereo_params.left.ui_params.output_gain = from_log(lu.output_gain);
-            stereo_params.left.ui_params.pre_smooth_gain = lu.pre_smooth_gain;
-            stereo_params.left.ui_params.post_smooth_gain = lu.post_smooth_gain;
-            stereo_params.right.ui_params.noise_gain = from_log(ru.noise_gain);
-            stereo_params.right.ui_params.output_gain = from_log(ru.output_gain);
-            stereo_params.right.ui_params.pre_smooth_gain = ru.pre_smooth_gain;
-            stereo_params.right.ui_params.post_smooth_gain = ru.post_smooth_gain;
-        }
-    }
-    // println!("{:?}", stereo_params);
-
     let _ = window.emit("update_processing_percentage", 0.0);
 
-    let num_samples = stereo_params.num_file_samples;
-
     let thread = tauri::async_runtime::spawn(async move {
-        let mut samples = vec![];
-        if !stereo_params.is_stereo {
-            for time in 0..num_samples {
-                let sample = file_samples[time] * stereo_params.left.ui_params.output_gain;
-                let filtered = stereo_params.left.sdft.spectral_subtraction(
-                    sample,
-                    &stereo_params.left.noise_spectrum,
-                    stereo_params.left.ui_params.noise_gain,
-                    stereo_params.left.ui_params.pre_smooth_gain,
-                    stereo_params.left.ui_params.post_smooth_gain,
-                );
-                samples.push(filtered);
-                samples.push(filtered);
-            }
-        }
-        // PROCESS STEREO
-        else {
-            for time in 0..num_samples / 2 - 1 {
-                if time % 4410 == 0 {
-                    let _r = window.emit(
-                        "update_processing_percentage",
-                        time as f32 / num_samples as f32 * 2.0 * 100.0,
-                    );
-                }
-
-                let left_sample = file_samples[2 * time] * stereo_params.left.ui_params.output_gain;
-                let left_filtered = stereo_params.left.sdft.spectral_subtraction(
-                    left_sample,
-                    &stereo_params.left.noise_spectrum,
-                    stereo_params.left.ui_params.noise_gain,
-                    stereo_params.left.ui_params.pre_smooth_gain,
-                    stereo_params.left.ui_params.post_smooth_gain,
-                );
-
-                samples.push(left_filtered);
-
-                let right_sample =
-                    file_samples[2 * time + 1] * stereo_params.right.ui_params.output_gain;
-                let right_filtered = stereo_params.right.sdft.spectral_subtraction(
-                    right_sample,
-                    &stereo_params.right.noise_spectrum,
-                    stereo_params.right.ui_params.noise_gain,
-                    stereo_params.right.ui_params.pre_smooth_gain,
-                    stereo_params.right.ui_params.post_smooth_gain,
-                );
-                samples.push(right_filtered);
-            }
-        };
-        samples
+        export_pipeline(
+            source.samples,
+            num_channels,
+            sample_rate,
+            stereo_choice,
+            &db_path,
+            |percentage| {
+                let _r = window.emit("update_processing_percentage", percentage);
+            },
+        )
     });
 
-    if let Ok(r) = thread.await {
-        let samples: Vec<f32> = r;
+    if let Ok((samples, mut manifest)) = thread.await {
         if samples.is_empty() {
             return Err("empty samples, failed to write to file".to_string());
         }
@@ -248,12 +200,323 @@ pub async fn process_export(
             .app_local_data_dir()
             .expect("~AppData\\Local\\denoiser should exist");
 
-        let header = wav_io::new_stereo_header();
-        if let Ok(mut file) = File::create(p.join("denoised.wav")) {
-            let _r = wav_io::write_to_file(&mut file, &header, &samples);
-        };
+        // same channel count and rate as the file
+        let _r = Source {
+            samples,
+            num_channels,
+            sample_rate,
+        }
+        .write(&p.join("denoised.wav"));
+
+        manifest.source = file_path;
+        manifest.output = p.join("denoised.wav").to_string_lossy().to_string();
+        if let Ok(json) = serde_json::to_string_pretty(&manifest) {
+            let _r = std::fs::write(p.join("denoised.json"), json);
+        }
         Ok(())
     } else {
         Err("failed to write to file".to_string())
     }
 }
+
+/// everything export does between reading the file and writing it, no app or audio device needed
+/// params come from the db, then declipping, hum detection, every frame through the chain and normalization
+/// progress gets the percentage done every 0.1 s of audio, the manifest paths are left for the caller
+pub fn export_pipeline(
+    mut file_samples: Vec<f32>,
+    num_channels: usize,
+    sample_rate: u32,
+    stereo_choice: StereoChoice,
+    db_path: &PathBuf,
+    mut progress: impl FnMut(f32),
+) -> (Vec<f32>, ExportManifest) {
+    let num_channels = num_channels.max(1);
+    // the manifest compares against the file as it was, before declipping
+    let original = file_samples.clone();
+
+    let mut stereo_params =
+        load_stereo_params(num_channels, sample_rate as f32, stereo_choice, db_path);
+    stereo_params.num_file_samples = file_samples.len();
+
+    // declip before anything else sees the file
+    let channel_choice = |i: usize| match stereo_choice {
+        StereoChoice::Both => StereoChoice::Both,
+        _ => StereoChoice::for_channel(i),
+    };
+    let declip_params: Vec<DeclipParams> = (0..num_channels)
+        .map(|i| query_declip(channel_choice(i), db_path).unwrap_or_default())
+        .collect();
+    let clips = declip(&mut file_samples, &declip_params);
+    resolve_hums(&mut stereo_params, &file_samples);
+
+    let num_frames = stereo_params.num_file_samples / num_channels;
+    let latencies: Vec<usize> = (0..num_channels)
+        .map(|i| stereo_params.latency(i))
+        .collect();
+    let delay = latencies.iter().cloned().max().unwrap_or(0);
+
+    let mut samples = Vec::with_capacity((num_frames + delay) * num_channels);
+    let mut processed = vec![0.0; num_channels];
+    for (time, frame) in file_samples.chunks_exact(num_channels).enumerate() {
+        if time % 4410 == 0 {
+            progress(time as f32 / num_frames as f32 * 100.0);
+        }
+
+        stereo_params.process_frame(frame, &mut processed);
+        stereo_params.monitor_frame(frame, &mut processed);
+        samples.extend_from_slice(&processed);
+    }
+    // flush what's still inside the declicker and sdft, then drop each channel's delay so the export lines up with the source
+    let silence = vec![0.0; num_channels];
+    for _ in 0..delay {
+        stereo_params.process_frame(&silence, &mut processed);
+        stereo_params.monitor_frame(&silence, &mut processed);
+        samples.extend_from_slice(&processed);
+    }
+    let mut samples: Vec<f32> = (0..num_frames * num_channels)
+        .map(|n| samples[n + latencies[n % num_channels] * num_channels])
+        .collect();
+
+    // metrics are against the source, so they're taken before the level changes
+    let metrics = channel_metrics(
+        &original,
+        &samples,
+        num_channels,
+        sample_rate as f32,
+        Some(&vec![0; num_channels]),
+    );
+    let normalize_params = query_normalize(db_path).unwrap_or_default();
+    let normalization = normalize_params.enabled.then(|| {
+        normalize(
+            &mut samples,
+            num_channels,
+            sample_rate as f32,
+            &normalize_params,
+        )
+    });
+
+    let manifest = ExportManifest {
+        source: "".to_string(),
+        output: "".to_string(),
+        created: chrono::Local::now().to_rfc3339(),
+        sample_rate,
+        num_channels,
+        stereo_choice,
+        stereo_mode: stereo_params.stereo_mode,
+        clips: clips.len(),
+        metrics,
+        loudness: Meter::measure(&samples, num_channels, sample_rate as f32),
+        normalization,
+    };
+    (samples, manifest)
+}
+
+/// written next to the exported file, what went in and how it came out
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct ExportManifest {
+    pub source: String,
+    pub output: String,
+    pub created: String,
+    pub sample_rate: u32,
+    pub num_channels: usize,
+    pub stereo_choice: StereoChoice,
+    pub stereo_mode: StereoModeParams,
+    /// clipped runs found in the source
+    pub clips: usize,
+    /// one per channel, the export against the source
+    pub metrics: Vec<QualityMetrics>,
+    /// levels of the whole export, peak and rms per channel
+    pub loudness: Metering,
+    /// only when normalization is on
+    pub normalization: Option<Normalization>,
+}
+
+/// compare a processed file with the original, processed defaults to the last export
+/// both need the same sample rate and channel count, the processing delay is estimated per channel
+#[tauri::command]
+pub async fn get_quality_metrics(
+    original: String,
+    processed: Option<String>,
+    app_handle: AppHandle,
+) -> Result<Vec<QualityMetrics>, String> {
+    let processed = match processed {
+        Some(p) => PathBuf::from(p),
+        None => app_handle
+            .path_resolver()
+            .app_local_data_dir()
+            .expect("app local data dir should exist")
+            .join("denoised.wav"),
+    };
+    let read = |p: PathBuf| {
+        File::open(p)
+            .ok()
+            .and_then(|f| wav_io::read_from_file(f).ok())
+            .ok_or("failed to read file".to_string())
+    };
+    let (head, original) = read(PathBuf::from(original))?;
+    let (processed_head, processed) = read(processed)?;
+    if head.sample_rate != processed_head.sample_rate || head.channels != processed_head.channels {
+        return Err("files don't match".to_string());
+    }
+
+    let thread = tauri::async_runtime::spawn(async move {
+        channel_metrics(
+            &original,
+            &processed,
+            head.channels as usize,
+            head.sample_rate as f32,
+            None,
+        )
+    });
+    thread
+        .await
+        .map_err(|_| "failed to compute metrics".to_string())
+}
+
+/// sliding dft and block denoiser on the same file and saved settings, each scored against the original
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct BlockComparison {
+    pub sliding: Vec<QualityMetrics>,
+    pub block: Vec<QualityMetrics>,
+}
+
+/// only the spectral stage is compared, hop and window are for the block denoiser
+#[tauri::command]
+pub async fn get_block_comparison(
+    file_path: String,
+    stereo_choice: StereoChoice,
+    hop: usize,
+    window: WindowKind,
+    app_handle: AppHandle,
+) -> Result<BlockComparison, String> {
+    let source = Source::load(&PathBuf::from(file_path)).map_err(|e| e.to_string())?;
+    let db_path = app_handle
+        .path_resolver()
+        .app_local_data_dir()
+        .expect("app local data dir should exist")
+        .join("db.sqlite");
+
+    let thread = tauri::async_runtime::spawn(async move {
+        compare_block_denoise(&source, stereo_choice, hop, window, &db_path)
+    });
+    thread
+        .await
+        .map_err(|_| "failed to compare".to_string())?
+        .map_err(|e| e.to_string())
+}
+
+pub fn compare_block_denoise(
+    source: &Source,
+    stereo_choice: StereoChoice,
+    hop: usize,
+    window: WindowKind,
+    db_path: &PathBuf,
+) -> DenoiserResult<BlockComparison> {
+    let num_channels = source.num_channels.max(1);
+    let sample_rate = source.sample_rate as f32;
+    let stereo_params = load_stereo_params(num_channels, sample_rate, stereo_choice, db_path);
+    let mut comparison = BlockComparison {
+        sliding: vec![],
+        block: vec![],
+    };
+    for i in 0..num_channels {
+        let channel: Vec<f32> = source
+            .samples
+            .iter()
+            .skip(i)
+            .step_by(num_channels)
+            .cloned()
+            .collect();
+        let params = stereo_params.channel(i);
+        comparison.sliding.push(quality_metrics(
+            &channel,
+            &sliding_denoise(&channel, params),
+            SLIDING_DELAY,
+            sample_rate,
+        ));
+        comparison.block.push(quality_metrics(
+            &channel,
+            &block_denoise(&channel, params, hop, window)?,
+            0,
+            sample_rate,
+        ));
+    }
+    Ok(comparison)
+}
+
+/// every saved param for a file with num_channels, with both every channel gets the same params
+/// otherwise each channel gets its own, auto hum is left for resolve_hums since it needs the file
+pub fn load_stereo_params(
+    num_channels: usize,
+    sample_rate: f32,
+    stereo_choice: StereoChoice,
+    db_path: &PathBuf,
+) -> StereoParams {
+    let mut stereo_params = StereoParams::new();
+    stereo_params.set_sample_rate(sample_rate);
+    stereo_params.set_num_channels(num_channels);
+
+    let channel_choice = |i: usize| match stereo_choice {
+        StereoChoice::Both => StereoChoice::Both,
+        _ => StereoChoice::for_channel(i),
+    };
+    for i in 0..num_channels {
+        load_channel_params(stereo_params.channel_mut(i), channel_choice(i), db_path);
+    }
+    if let Ok(m) = query_stereo_mode(db_path) {
+        stereo_params.stereo_mode = m;
+    }
+    if let Ok(a) = query_adaptive_cancel(db_path) {
+        stereo_params.canceller = Nlms::new(a);
+    }
+    stereo_params.mix = Mix::new(query_mix(db_path).unwrap_or_default(), sample_rate);
+    stereo_params
+}
+
+/// auto hum fundamental is found from the file, each channel could be on a different circuit
+pub fn resolve_hums(stereo_params: &mut StereoParams, file_samples: &[f32]) {
+    let num_channels = stereo_params.num_channels;
+    for i in 0..num_channels {
+        let params = stereo_params.channel_mut(i);
+        let sample_rate = params.sample_rate;
+        if params.dehum.params.enabled {
+            let channel: Vec<f32> = file_samples
+                .iter()
+                .skip(i)
+                .step_by(num_channels)
+                .cloned()
+                .collect();
+            let h = resolve_hum_params(params.dehum.params, &channel, sample_rate);
+            params.dehum = Dehum::new(h, sample_rate);
+        }
+    }
+}
+
+/// everything saved for one channel
+fn load_channel_params(params: &mut AudioParams, stereo_choice: StereoChoice, db_path: &PathBuf) {
+    if let Ok(fb) = query_filter_bank(stereo_choice, db_path) {
+        params.filters = fb.into();
+        params.noise_spectrum = params.filters.parallel_transfer(256);
+    }
+    if let Ok(p) = query_ui_params(stereo_choice, db_path) {
+        params.ui_params.noise_gain = from_log(p.noise_gain);
+        params.ui_params.output_gain = from_log(p.output_gain);
+        params.ui_params.pre_smooth_gain = p.pre_smooth_gain;
+        params.ui_params.post_smooth_gain = p.post_smooth_gain;
+    }
+    if let Ok(g) = query_gain_smoothing(stereo_choice, db_path) {
+        params.gain_smoothing = g;
+    }
+    if let Ok(g) = query_spectral_gate(stereo_choice, db_path) {
+        params.spectral_gate = g;
+    }
+    if let Ok(h) = query_hum(stereo_choice, db_path) {
+        params.dehum = Dehum::new(h, params.sample_rate);
+    }
+    if let Ok(d) = query_declick(stereo_choice, db_path) {
+        params.declicker = Declicker::new(d, params.sample_rate);
+    }
+    if let Ok(v) = query_vad(stereo_choice, db_path) {
+        params.vad = Vad::new(v, params.dft_size, params.sample_rate);
+    }
+}
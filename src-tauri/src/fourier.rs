@@ -1,5 +1,21 @@
-use rustfft::{num_complex::Complex, FftPlanner};
-use std::{fs::File, path::PathBuf, str::FromStr};
+use rustfft::{num_complex::Complex, Fft, FftPlanner};
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::VecDeque, fs::File, path::PathBuf, str::FromStr, sync::Arc, time::SystemTime,
+};
+use tauri::State;
+use ts_rs::TS;
+
+use crate::{
+    audio::get_wav_samples,
+    constants::from_log,
+    errors::{DenoiserError, DenoiserResult},
+    sdft::SDFT,
+    types::{AudioParams, GainSmoothing, MSpectrogramCache},
+};
+
+/// bytes of dB frames the spectrogram cache keeps, an hour of audio can be over a gigabyte
+const SPECTROGRAM_CACHE_BYTES: usize = 256 << 20;
 
 #[tauri::command]
 pub async fn get_time_onefft(path: &str) -> Result<(Vec<f32>, Vec<f32>), &str> {
@@ -37,117 +53,424 @@ pub async fn get_time_onefft(path: &str) -> Result<(Vec<f32>, Vec<f32>), &str> {
     Ok((time_data, freq_data))
 }
 
-#[tauri::command]
-pub async fn get_stft_data(path: &str) -> Result<(Vec<f32>, Vec<Vec<f32>>), &str> {
-    let mut vstft: Vec<Vec<f32>> = vec![];
-    let mut time_data = vec![];
+/// spectrogram of part of a file, one column per pixel and one row per pixel from the lowest frequency up
+#[derive(Clone, Debug, Serialize, Deserialize, TS)]
+#[ts(export)]
+pub struct Spectrogram {
+    /// frames of the file, same units as the playback time
+    pub start: usize,
+    pub len: usize,
+    pub db_min: f32,
+    pub db_max: f32,
+    /// dB, clamped to the range, columns[x][y]
+    pub columns: Vec<Vec<f32>>,
+}
 
-    let filepath = PathBuf::from_str(path).expect("bad path").join(path);
+/// stft of a whole file in dB, the part that is expensive and doesn't change with zoom
+pub struct SpectrogramFrames {
+    pub fft_size: usize,
+    pub hop: usize,
+    pub frames: Vec<Vec<f32>>,
+}
 
-    let thread = tauri::async_runtime::spawn(async move {
-        let file_in = File::open(filepath).unwrap();
-        let (head, w) = wav_io::read_from_file(file_in).unwrap();
-        return w;
-    });
+#[derive(Clone, Debug, PartialEq)]
+struct SpectrogramKey {
+    path: PathBuf,
+    modified: Option<SystemTime>,
+    fft_size: usize,
+    hop: usize,
+}
 
-    if let Ok(r) = thread.await {
-        time_data = r;
+/// the last few files and settings that were drawn, so zooming and scrolling only decimate
+#[derive(Default)]
+pub struct SpectrogramCache {
+    entries: VecDeque<(SpectrogramKey, Arc<SpectrogramFrames>)>,
+}
+
+impl SpectrogramCache {
+    fn get(&mut self, key: &SpectrogramKey) -> Option<Arc<SpectrogramFrames>> {
+        let i = self.entries.iter().position(|(k, _)| k == key)?;
+        // most recently used goes to the back
+        let entry = self.entries.remove(i)?;
+        let frames = entry.1.clone();
+        self.entries.push_back(entry);
+        Some(frames)
+    }
+
+    /// least recently used go first until the new frames fit, frames too big for the cache aren't kept
+    fn insert(&mut self, key: SpectrogramKey, frames: Arc<SpectrogramFrames>) {
+        let bytes = frames.bytes();
+        if bytes > SPECTROGRAM_CACHE_BYTES {
+            return;
+        }
+        while self.bytes() + bytes > SPECTROGRAM_CACHE_BYTES {
+            self.entries.pop_front();
+        }
+        self.entries.push_back((key, frames));
     }
 
-    // if let Ok(mut wav) = Wav::from_path(p + "/" + path) {
-    //     let itr: Vec<f32> = wav.read().unwrap().to_vec();
-    //     let mut buffer = vec![];
-    //     let len = itr.len();
+    fn bytes(&self) -> usize {
+        self.entries.iter().map(|(_, f)| f.bytes()).sum()
+    }
+}
 
-    //     for s in itr {
-    //         let x = s;
-    //         time_data.push(x.clone());
-    //         buffer.push(Complex { re: x, im: 0.0f32 })
-    //     }
+impl SpectrogramFrames {
+    /// dB stft of the channels averaged together, 0 dB is a full scale sine
+    pub fn new(
+        samples: &[f32],
+        num_channels: usize,
+        fft_size: usize,
+        hop: usize,
+    ) -> DenoiserResult<Self> {
+        let num_channels = num_channels.max(1);
+        let mono: Vec<f32> = samples
+            .chunks_exact(num_channels)
+            .map(|f| f.iter().sum::<f32>() / num_channels as f32)
+            .collect();
+        // hann halves a sine's peak, and half of it is in the negative frequencies
+        let full_scale = fft_size as f32 / 4.0;
+        let frames = stft(&mono, fft_size, hop)?
+            .into_iter()
+            .map(|frame| {
+                frame
+                    .iter()
+                    .map(|m| 20.0 * (m / full_scale + 1e-10).log10())
+                    .collect()
+            })
+            .collect();
+        Ok(Self {
+            fft_size,
+            hop,
+            frames,
+        })
+    }
 
-    //     let fftsize = 2048;
-    //     let vstft = stft(buffer.clone(), fftsize, fftsize / 2);
+    pub fn bytes(&self) -> usize {
+        self.frames.iter().map(|f| f.len()).sum::<usize>() * std::mem::size_of::<f32>()
+    }
 
-    //     return Ok((time_data, vstft));
-    // } else {
-    //     return Err("bad path");
-    // }
+    /// stft frame centered closest to a channel sample
+    fn frame_at(&self, sample: usize) -> usize {
+        let pad = (self.fft_size - self.hop) as f32;
+        let t = (sample as f32 + pad - self.fft_size as f32 / 2.0) / self.hop as f32;
+        (t.round().max(0.0) as usize).min(self.frames.len().saturating_sub(1))
+    }
 
-    Ok((time_data, vstft))
+    /// decimate part of the stft to a width x height image, each pixel is the loudest frame and bin it covers
+    pub fn render(
+        &self,
+        start: usize,
+        len: usize,
+        db_min: f32,
+        db_max: f32,
+        width: usize,
+        height: usize,
+    ) -> Spectrogram {
+        let mut columns = vec![];
+        let bins = self.fft_size / 2;
+        if !self.frames.is_empty() && width > 0 && height > 0 {
+            let (s0, s1) = (start, start + len);
+            for x in 0..width {
+                let t0 = self.frame_at(s0 + x * (s1 - s0) / width);
+                let t1 = self.frame_at(s0 + (x + 1) * (s1 - s0) / width).max(t0 + 1);
+                let frames = &self.frames[t0..t1.min(self.frames.len())];
+                let column = (0..height)
+                    .map(|y| {
+                        let b0 = y * bins / height;
+                        let b1 = ((y + 1) * bins / height).max(b0 + 1);
+                        frames
+                            .iter()
+                            .flat_map(|f| f[b0..b1].iter())
+                            .fold(f32::MIN, |m, v| m.max(*v))
+                            .clamp(db_min, db_max)
+                    })
+                    .collect();
+                columns.push(column);
+            }
+        }
+        Spectrogram {
+            start,
+            len,
+            db_min,
+            db_max,
+            columns,
+        }
+    }
 }
 
-pub fn stft(mut buffer: Vec<Complex<f32>>, size: usize, hop: usize) -> Vec<Vec<f32>> {
-    let mut planner = FftPlanner::new();
-    let fft = planner.plan_fft_forward(size);
-
-    // don't think this is right but also not using STFT, just getting rid of dsp and hound crates
-    let window = hamming_window(size);
-
-    let l = buffer.len();
-    let num_slices = l / (size + hop);
-    let mut spectra: Vec<Vec<f32>> = vec![];
-    let mut last_spectrum = vec![Complex { re: 0.0, im: 0.0 }; size];
-    for slice in 0..num_slices {
-        let mut x = vec![Complex { re: 0.0, im: 0.0 }; size];
-        for (i, samp) in buffer[slice * size + hop * slice..(slice + 1) * size + hop * slice]
-            .iter()
-            .enumerate()
-        {
-            x[i] = (samp * window[i] + last_spectrum[i]) / 2.0;
+/// spectrogram of a time range of the file, in playback time like the time plot
+/// the stft of the whole file is cached per file and settings, so zooming only decimates
+#[tauri::command]
+pub async fn get_stft_data(
+    path: String,
+    start: usize,
+    len: usize,
+    fft_size: usize,
+    hop: usize,
+    db_min: f32,
+    db_max: f32,
+    width: usize,
+    height: usize,
+    cache: State<'_, MSpectrogramCache>,
+) -> Result<Spectrogram, String> {
+    let filepath = PathBuf::from_str(&path).map_err(|_| "bad path".to_string())?;
+    if !filepath.is_file() {
+        return Err("file not found".to_string());
+    }
+    let key = SpectrogramKey {
+        modified: std::fs::metadata(&filepath).and_then(|m| m.modified()).ok(),
+        path: filepath.clone(),
+        fft_size,
+        hop,
+    };
+
+    let cached = cache.0.lock().unwrap().get(&key);
+    let frames = match cached {
+        Some(f) => f,
+        None => {
+            let thread = tauri::async_runtime::spawn(async move {
+                let (samples, num_channels) = get_wav_samples(filepath);
+                SpectrogramFrames::new(&samples, num_channels, fft_size, hop)
+            });
+            let frames = thread
+                .await
+                .map_err(|_| "failed to get stft data".to_string())?
+                .map_err(|e| e.to_string())?;
+            let frames = Arc::new(frames);
+            cache.0.lock().unwrap().insert(key, frames.clone());
+            frames
         }
+    };
 
-        // last_spectrum = x.clone();
+    Ok(frames.render(start, len, db_min, db_max, width, height))
+}
 
-        fft.process(&mut x);
+/// analysis/synthesis window for the stft, all periodic so shifted copies overlap-add evenly
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize, TS)]
+#[ts(export)]
+pub enum WindowKind {
+    Rectangular,
+    Hann,
+    /// hann split between analysis and synthesis, COLA at half overlap
+    SqrtHann,
+    Hamming,
+}
 
-        let mut v = vec![];
-        for i in x[0..size / 2].iter() {
-            v.push(i.norm());
-        }
-        spectra.push(v);
+pub fn window(kind: WindowKind, size: usize) -> Vec<f32> {
+    let two_pi = 2.0 * std::f32::consts::PI;
+    (0..size)
+        .map(|n| {
+            let c = (two_pi * n as f32 / size as f32).cos();
+            match kind {
+                WindowKind::Rectangular => 1.0,
+                WindowKind::Hann => 0.5 - 0.5 * c,
+                WindowKind::SqrtHann => (0.5 - 0.5 * c).sqrt(),
+                WindowKind::Hamming => 0.54 - 0.46 * c,
+            }
+        })
+        .collect()
+}
+
+/// sum of the squared window over every frame that overlaps each offset within a hop
+/// the window is applied on analysis and again on synthesis, so this is what overlap-add scales by
+fn overlap_sum(window: &[f32], hop: usize) -> Vec<f32> {
+    let mut sum = vec![0.0; hop];
+    for (n, w) in window.iter().enumerate() {
+        sum[n % hop] += w * w;
+    }
+    sum
+}
+
+/// true if the window with this hop reconstructs with a constant gain (weighted overlap-add)
+pub fn is_cola(window: &[f32], hop: usize) -> bool {
+    if hop == 0 || hop > window.len() {
+        return false;
     }
+    let sum = overlap_sum(window, hop);
+    let max = sum.iter().cloned().fold(0.0, f32::max);
+    let min = sum.iter().cloned().fold(f32::MAX, f32::min);
+    max > 0.0 && (max - min) / max < 1e-4
+}
 
-    spectra
+/// short time fourier transform with an inverse
+/// the signal is padded by size - hop on both ends so every sample is covered by the same number of frames,
+/// frame t starts at t * hop - (size - hop)
+pub struct Stft {
+    pub size: usize,
+    pub hop: usize,
+    pub window: Vec<f32>,
+    fft: Arc<dyn Fft<f32>>,
+    ifft: Arc<dyn Fft<f32>>,
 }
 
-/// return a single spectrum that is averaged from stft
-pub fn averaged_stft(buffer: Vec<Complex<f32>>, size: usize, hop: usize) -> Vec<f32> {
-    let mut planner = FftPlanner::new();
-    let fft = planner.plan_fft_forward(size);
-
-    // let win = dsp::window::hamming(size);
-    // let frame = vec![1.0; size];
-    // let mut output = vec![0.0; size];
-    let window = hamming_window(size);
-
-    let l = buffer.len();
-    println!("length {:?}", l);
-    let num_slices = l / (size + hop);
-    let mut average_spectrum: Vec<f32> = vec![0.0; size / 2];
-    println!("num slices {:?}", num_slices);
-    for slice in 0..num_slices {
-        let mut x = vec![Complex { re: 0.0, im: 0.0 }; size];
-        for (i, samp) in buffer[slice * size + hop * slice..(slice + 1) * size + hop * slice]
-            .iter()
-            .enumerate()
-        {
-            x[i] = (samp * window[i]) / 2.0;
+impl Stft {
+    pub fn new(size: usize, hop: usize, kind: WindowKind) -> DenoiserResult<Self> {
+        if size == 0 || hop == 0 || hop > size {
+            return Err(DenoiserError::Other(format!(
+                "bad stft size {} and hop {}",
+                size, hop
+            )));
+        }
+        let mut planner = FftPlanner::new();
+        Ok(Self {
+            size,
+            hop,
+            window: window(kind, size),
+            fft: planner.plan_fft_forward(size),
+            ifft: planner.plan_fft_inverse(size),
+        })
+    }
+
+    /// whether inverse can give back the signal
+    pub fn is_cola(&self) -> bool {
+        is_cola(&self.window, self.hop)
+    }
+
+    fn padding(&self) -> usize {
+        self.size - self.hop
+    }
+
+    pub fn num_frames(&self, len: usize) -> usize {
+        if len == 0 {
+            return 0;
+        }
+        (len - 1 + self.padding()) / self.hop + 1
+    }
+
+    /// full complex spectrum of every frame
+    pub fn forward(&self, signal: &[f32]) -> Vec<Vec<Complex<f32>>> {
+        let pad = self.padding() as isize;
+        (0..self.num_frames(signal.len()))
+            .map(|t| {
+                let start = (t * self.hop) as isize - pad;
+                let mut frame: Vec<Complex<f32>> = (0..self.size)
+                    .map(|i| {
+                        let n = start + i as isize;
+                        let x = if n >= 0 && (n as usize) < signal.len() {
+                            signal[n as usize]
+                        } else {
+                            0.0
+                        };
+                        Complex::new(x * self.window[i], 0.0)
+                    })
+                    .collect();
+                self.fft.process(&mut frame);
+                frame
+            })
+            .collect()
+    }
+
+    /// weighted overlap-add of the frames back to len samples, exact when nothing was changed in between
+    pub fn inverse(&self, frames: &[Vec<Complex<f32>>], len: usize) -> DenoiserResult<Vec<f32>> {
+        if !self.is_cola() {
+            return Err(DenoiserError::Other(format!(
+                "window is not COLA with size {} and hop {}",
+                self.size, self.hop
+            )));
         }
+        let pad = self.padding();
+        let mut out = vec![0.0; len + 2 * pad + self.size];
+        let mut buf = vec![Complex::new(0.0, 0.0); self.size];
+        for (t, frame) in frames.iter().enumerate() {
+            buf.copy_from_slice(frame);
+            self.ifft.process(&mut buf);
+            let start = t * self.hop;
+            for (i, x) in buf.iter().enumerate() {
+                out[start + i] += x.re * self.window[i] / self.size as f32;
+            }
+        }
+        let scale = overlap_sum(&self.window, self.hop)[0];
+        Ok(out[pad..pad + len].iter().map(|x| x / scale).collect())
+    }
+}
 
-        fft.process(&mut x);
+/// magnitudes of the positive frequencies of each hann windowed frame
+pub fn stft(signal: &[f32], size: usize, hop: usize) -> DenoiserResult<Vec<Vec<f32>>> {
+    let stft = Stft::new(size, hop, WindowKind::Hann)?;
+    Ok(stft
+        .forward(signal)
+        .iter()
+        .map(|frame| frame[0..size / 2].iter().map(|x| x.norm()).collect())
+        .collect())
+}
 
-        for (i, samp) in x[0..size / 2].iter().enumerate() {
-            average_spectrum[i] += samp.norm();
+/// offline block denoiser with the same noise profile, noise gain and floor as the sliding dft,
+/// for comparing the two on identical settings, only the spectral stage is done
+pub fn block_denoise(
+    signal: &[f32],
+    params: &AudioParams,
+    hop: usize,
+    kind: WindowKind,
+) -> DenoiserResult<Vec<f32>> {
+    let size = params.dft_size;
+    let stft = Stft::new(size, hop, kind)?;
+    // the sliding dft is unwindowed, scale magnitudes back to the same units
+    let coherent_gain = stft.window.iter().sum::<f32>() / size as f32;
+    let floor = if params.gain_smoothing.enabled {
+        from_log(params.gain_smoothing.floor).min(1.0)
+    } else {
+        0.0
+    };
+    let gain = params.ui_params.output_gain;
+
+    let mut frames = stft.forward(signal);
+    for frame in frames.iter_mut() {
+        for (freq, x) in frame.iter_mut().enumerate() {
+            let mag = gain * x.norm() / coherent_gain;
+            let noise = params.ui_params.noise_gain * (params.noise_spectrum[freq] - 1.0).abs();
+            let g = if mag > 1e-9 {
+                ((mag - noise) / mag).clamp(floor, 1.0)
+            } else {
+                floor
+            };
+            *x *= gain * g;
         }
     }
+    stft.inverse(&frames, signal.len())
+}
 
-    average_spectrum = average_spectrum
+/// samples the sliding dft output lags its input
+pub const SLIDING_DELAY: usize = 1;
+
+/// the sliding dft with the same gain law as block_denoise, what it gets compared against
+/// gain smoothing is used when it's on, the output is one sample late
+pub fn sliding_denoise(signal: &[f32], params: &AudioParams) -> Vec<f32> {
+    let mut sdft = SDFT::new(params.dft_size);
+    let smoothing = if params.gain_smoothing.enabled {
+        params.gain_smoothing
+    } else {
+        GainSmoothing::unsmoothed()
+    };
+    let gain = params.ui_params.output_gain;
+    signal
         .iter()
-        .map(|x| *x / num_slices as f32)
-        .collect();
+        .map(|x| {
+            sdft.spectral_subtraction_gain(
+                gain * x,
+                &params.noise_spectrum,
+                params.ui_params.noise_gain,
+                &smoothing,
+                params.sample_rate,
+            )
+        })
+        .collect()
+}
 
-    average_spectrum
+/// mean magnitude of the positive frequencies over hamming windowed stft frames
+/// scaled by half, the units the fingerprint thresholds were tuned in
+pub fn averaged_stft(signal: &[f32], size: usize, hop: usize) -> DenoiserResult<Vec<f32>> {
+    let stft = Stft::new(size, hop, WindowKind::Hamming)?;
+    let frames = stft.forward(signal);
+    let mut average = vec![0.0; size / 2];
+    for frame in frames.iter() {
+        for (a, x) in average.iter_mut().zip(frame) {
+            *a += x.norm() / 2.0;
+        }
+    }
+    let num_frames = frames.len().max(1) as f32;
+    Ok(average.iter().map(|x| x / num_frames).collect())
 }
+
 pub fn mfft(mut signal: Vec<f32>) -> Vec<f32> {
     let len = signal.len();
     let mut buffer = vec![];
@@ -163,21 +486,3 @@ pub fn mfft(mut signal: Vec<f32>) -> Vec<f32> {
         .map(|x| x.norm())
         .collect::<Vec<f32>>()
 }
-
-fn hamming_window(n_samples: usize) -> Vec<f32> {
-    if n_samples <= 1 {
-        return vec![1.0];
-    }
-    let mut window: Vec<f32> = Vec::with_capacity(n_samples);
-    let m = (n_samples - 1) as f32;
-    let two_pi = 2.0 * std::f32::consts::PI;
-
-    for n in 0..n_samples {
-        let f = n as f32;
-        let c = (two_pi * f) / m;
-        let w = 0.54 - 0.46 * c.cos();
-        window.push(w);
-    }
-
-    window
-}
@@ -0,0 +1,199 @@
+use std::path::PathBuf;
+use tauri::AppHandle;
+
+use crate::{
+    audio::device_sample_rate,
+    constants::SAMPLING_RATE,
+    settings::PlotScale,
+    sql::{query_filter_bank, query_settings},
+    types::{Filters, StereoChoice},
+};
+
+/// Hz, bottom of the log scale, same as MIN_FREQ in the ui
+pub const MIN_FREQ: f32 = 20.0;
+/// points in a mapped spectrum until the ui asks for something else
+pub const SPECTRUM_POINTS: usize = 256;
+
+impl PlotScale {
+    /// position of a frequency on the scale, same formulas the ui uses for its axes
+    pub fn warp(&self, hz: f32, nyquist: f32) -> f32 {
+        match self {
+            PlotScale::Linear => hz,
+            PlotScale::Mel => 2595.0 * (1.0 + hz / 700.0).log10(),
+            PlotScale::Log => {
+                (nyquist - MIN_FREQ) * (hz.max(1e-3) / MIN_FREQ).log10()
+                    / (nyquist / MIN_FREQ).log10()
+                    + MIN_FREQ
+            }
+            PlotScale::Bark => 13.0 * (0.00076 * hz).atan() + 3.5 * ((hz / 7500.0).powi(2)).atan(),
+        }
+    }
+
+    /// frequency at a position on the scale
+    pub fn unwarp(&self, y: f32, nyquist: f32) -> f32 {
+        match self {
+            PlotScale::Linear => y,
+            PlotScale::Mel => 700.0 * (10f32.powf(y / 2595.0) - 1.0),
+            PlotScale::Log => {
+                MIN_FREQ
+                    * 10f32
+                        .powf((y - MIN_FREQ) / (nyquist - MIN_FREQ) * (nyquist / MIN_FREQ).log10())
+            }
+            PlotScale::Bark => {
+                // no closed form, but it only ever increases
+                let (mut lo, mut hi) = (0.0f32, 2.0 * nyquist);
+                for _ in 0..40 {
+                    let mid = 0.5 * (lo + hi);
+                    if self.warp(mid, nyquist) < y {
+                        lo = mid;
+                    } else {
+                        hi = mid;
+                    }
+                }
+                0.5 * (lo + hi)
+            }
+        }
+    }
+}
+
+/// frequency of each point of a mapped spectrum, evenly spaced on the scale from 0 up to nyquist
+/// point i is drawn at i / points of the plot width
+pub fn scale_frequencies(scale: PlotScale, points: usize, nyquist: f32) -> Vec<f32> {
+    let top = scale.warp(nyquist, nyquist);
+    (0..points)
+        .map(|i| {
+            scale
+                .unwarp(i as f32 / points as f32 * top, nyquist)
+                .clamp(0.0, nyquist)
+        })
+        .collect()
+}
+
+/// resamples a linear spectrum onto a frequency scale
+/// each point is a triangular filter reaching to its neighbors, where the points are closer than the bins
+/// (the bottom of the log scale) the two nearest bins are interpolated instead
+#[derive(Clone, Debug)]
+pub struct FreqMapping {
+    pub scale: PlotScale,
+    pub points: usize,
+    pub bins: usize,
+    pub sample_rate: f32,
+    /// bin and weight pairs for each point, the weights add up to 1
+    weights: Vec<Vec<(usize, f32)>>,
+}
+
+impl FreqMapping {
+    /// bins covers 0 up to nyquist, like the first half of a dft
+    pub fn new(scale: PlotScale, points: usize, bins: usize, sample_rate: f32) -> Self {
+        let nyquist = sample_rate / 2.0;
+        let bin_hz = nyquist / bins.max(1) as f32;
+        let freqs = scale_frequencies(scale, points, nyquist);
+        let weights = (0..points)
+            .map(|i| {
+                // nothing to read from, every point maps to 0
+                if bins == 0 {
+                    return vec![];
+                }
+                let f = freqs[i];
+                let lo = if i > 0 { freqs[i - 1] } else { 0.0 };
+                let hi = if i + 1 < points {
+                    freqs[i + 1]
+                } else {
+                    nyquist
+                };
+                if hi - lo < 2.0 * bin_hz {
+                    let pos = (f / bin_hz).min((bins - 1) as f32);
+                    let k = pos.floor() as usize;
+                    let frac = pos - k as f32;
+                    return vec![(k, 1.0 - frac), ((k + 1).min(bins - 1), frac)];
+                }
+                let mut w: Vec<(usize, f32)> = (0..bins)
+                    .filter_map(|k| {
+                        let kf = k as f32 * bin_hz;
+                        let v = if kf <= lo || kf >= hi {
+                            0.0
+                        } else if kf <= f {
+                            (kf - lo) / (f - lo)
+                        } else {
+                            (hi - kf) / (hi - f)
+                        };
+                        (v > 0.0).then_some((k, v))
+                    })
+                    .collect();
+                let sum: f32 = w.iter().map(|(_, v)| v).sum();
+                w.iter_mut().for_each(|(_, v)| *v /= sum);
+                w
+            })
+            .collect();
+        Self {
+            scale,
+            points,
+            bins,
+            sample_rate,
+            weights,
+        }
+    }
+
+    /// same sample rate and bins, different scale or resolution
+    pub fn rescale(&mut self, scale: PlotScale, points: usize) {
+        *self = FreqMapping::new(scale, points, self.bins, self.sample_rate);
+    }
+
+    pub fn map(&self, spectrum: &[f32]) -> Vec<f32> {
+        self.weights
+            .iter()
+            .map(|w| w.iter().map(|(k, v)| spectrum[*k] * v).sum())
+            .collect()
+    }
+}
+
+impl Default for FreqMapping {
+    fn default() -> Self {
+        FreqMapping::new(PlotScale::Linear, SPECTRUM_POINTS, 128, SAMPLING_RATE)
+    }
+}
+
+/// plot scale saved in settings, linear if there isn't one yet
+pub fn saved_scale(app_handle: &AppHandle) -> PlotScale {
+    let p = app_handle
+        .path_resolver()
+        .app_local_data_dir()
+        .expect("app local data dir should exist")
+        .join("db.sqlite");
+    query_settings(p)
+        .map(|s| s.plot_scale)
+        .unwrap_or(PlotScale::Linear)
+}
+
+/// frequency of each point on the scale, for placing filter handles and labels when editing the noise profile
+#[tauri::command]
+pub fn get_scale_frequencies(scale: PlotScale, points: usize) -> Vec<f32> {
+    let sample_rate = device_sample_rate().map_or(SAMPLING_RATE, |s| s.0 as f32);
+    scale_frequencies(scale, points, sample_rate / 2.0)
+}
+
+/// the noise profile the filters make for a channel, mapped to the scale
+#[tauri::command]
+pub fn get_noise_profile(
+    stereo_choice: StereoChoice,
+    scale: PlotScale,
+    points: usize,
+    app_handle: AppHandle,
+) -> Vec<f32> {
+    let p: PathBuf = app_handle
+        .path_resolver()
+        .app_local_data_dir()
+        .expect("app local data dir should exist")
+        .join("db.sqlite");
+    let filters: Filters = match query_filter_bank(stereo_choice, &p) {
+        Ok(fb) => fb.into(),
+        Err(_) => Filters::new(),
+    };
+    let n = 256;
+    let profile: Vec<f32> = filters.parallel_transfer(n)[..n / 2]
+        .iter()
+        .map(|x| (x - 1.0).abs())
+        .collect();
+    let sample_rate = device_sample_rate().map_or(SAMPLING_RATE, |s| s.0 as f32);
+    FreqMapping::new(scale, points, n / 2, sample_rate).map(&profile)
+}
@@ -0,0 +1,303 @@
+use rand::{rngs::StdRng, Rng, SeedableRng};
+use serde::{Deserialize, Serialize};
+use std::{f32::consts::PI, fs::File, path::Path};
+use ts_rs::TS;
+
+use crate::errors::{DenoiserError, DenoiserResult};
+
+/// Hz, corners of the speech-shaped noise, roughly the long-term spectrum of speech
+const SPEECH_LOW: f32 = 100.0;
+const SPEECH_HIGH: f32 = 800.0;
+/// ms, length of a generated click
+const CLICK_LENGTH: f32 = 0.3;
+
+/// one kind of test material, amplitudes are linear peak values
+#[derive(Clone, Copy, Debug, Serialize, Deserialize, PartialEq, TS)]
+#[ts(export)]
+pub enum SignalKind {
+    Sine {
+        freq: f32,
+        amplitude: f32,
+    },
+    /// exponential sweep, the same time in every octave
+    Sweep {
+        start: f32,
+        end: f32,
+        amplitude: f32,
+    },
+    /// stationary noise with the long-term spectrum of speech
+    SpeechNoise {
+        amplitude: f32,
+    },
+    WhiteNoise {
+        amplitude: f32,
+    },
+    /// -3 dB per octave
+    PinkNoise {
+        amplitude: f32,
+    },
+    /// -6 dB per octave
+    BrownNoise {
+        amplitude: f32,
+    },
+    /// fundamental and harmonics falling off as 1 / n
+    Hum {
+        freq: f32,
+        harmonics: usize,
+        amplitude: f32,
+    },
+    /// short impulses of random sign at random times, rate is the average per second
+    Clicks {
+        rate: f32,
+        amplitude: f32,
+    },
+}
+
+/// seeded generator, the same seed always gives the same material
+pub struct Generator {
+    pub sample_rate: f32,
+    rng: StdRng,
+}
+
+impl Generator {
+    pub fn new(seed: u64, sample_rate: f32) -> Self {
+        Self {
+            sample_rate,
+            rng: StdRng::seed_from_u64(seed),
+        }
+    }
+
+    pub fn generate(&mut self, kind: SignalKind, len: usize) -> Vec<f32> {
+        use SignalKind::*;
+        match kind {
+            Sine { freq, amplitude } => self.sine(freq, amplitude, len),
+            Sweep {
+                start,
+                end,
+                amplitude,
+            } => self.sweep(start, end, amplitude, len),
+            SpeechNoise { amplitude } => self.speech_noise(amplitude, len),
+            WhiteNoise { amplitude } => self.white(amplitude, len),
+            PinkNoise { amplitude } => self.pink(amplitude, len),
+            BrownNoise { amplitude } => self.brown(amplitude, len),
+            Hum {
+                freq,
+                harmonics,
+                amplitude,
+            } => self.hum(freq, harmonics, amplitude, len),
+            Clicks { rate, amplitude } => self.clicks(rate, amplitude, len),
+        }
+    }
+
+    pub fn sine(&self, freq: f32, amplitude: f32, len: usize) -> Vec<f32> {
+        let w = 2.0 * PI * freq / self.sample_rate;
+        (0..len).map(|n| amplitude * (w * n as f32).sin()).collect()
+    }
+
+    pub fn sweep(&self, start: f32, end: f32, amplitude: f32, len: usize) -> Vec<f32> {
+        let start = start.max(1.0);
+        let end = end.max(1.0);
+        let duration = len as f32 / self.sample_rate;
+        let rate = (end / start).ln();
+        (0..len)
+            .map(|n| {
+                let t = n as f32 / self.sample_rate;
+                let phase = if rate.abs() < 1e-6 {
+                    2.0 * PI * start * t
+                } else {
+                    2.0 * PI * start * duration / rate * ((t / duration * rate).exp() - 1.0)
+                };
+                amplitude * phase.sin()
+            })
+            .collect()
+    }
+
+    pub fn white(&mut self, amplitude: f32, len: usize) -> Vec<f32> {
+        let a = amplitude.abs();
+        (0..len).map(|_| self.rng.gen_range(-a..=a)).collect()
+    }
+
+    /// Paul Kellet's filter on white noise
+    pub fn pink(&mut self, amplitude: f32, len: usize) -> Vec<f32> {
+        let mut b = [0.0; 7];
+        let x: Vec<f32> = (0..len)
+            .map(|_| {
+                let w: f32 = self.rng.gen_range(-1.0..1.0);
+                b[0] = 0.99886 * b[0] + w * 0.0555179;
+                b[1] = 0.99332 * b[1] + w * 0.0750759;
+                b[2] = 0.96900 * b[2] + w * 0.153852;
+                b[3] = 0.86650 * b[3] + w * 0.3104856;
+                b[4] = 0.55000 * b[4] + w * 0.5329522;
+                b[5] = -0.7616 * b[5] - w * 0.0168980;
+                let p = b.iter().sum::<f32>() + w * 0.5362;
+                b[6] = w * 0.115926;
+                p
+            })
+            .collect();
+        normalize(x, amplitude)
+    }
+
+    /// leaky integral of white noise, the leak keeps it from wandering off
+    pub fn brown(&mut self, amplitude: f32, len: usize) -> Vec<f32> {
+        let mut y = 0.0;
+        let x: Vec<f32> = (0..len)
+            .map(|_| {
+                y = 0.998 * y + self.rng.gen_range(-1.0..1.0);
+                y
+            })
+            .collect();
+        normalize(x, amplitude)
+    }
+
+    /// white noise through a high pass at SPEECH_LOW and two low passes at SPEECH_HIGH
+    pub fn speech_noise(&mut self, amplitude: f32, len: usize) -> Vec<f32> {
+        let coeff = |hz: f32| (-2.0 * PI * hz / self.sample_rate).exp();
+        let (hp, lp) = (coeff(SPEECH_LOW), coeff(SPEECH_HIGH));
+        let (mut low, mut lp1, mut lp2) = (0.0, 0.0, 0.0);
+        let x: Vec<f32> = (0..len)
+            .map(|_| {
+                let w: f32 = self.rng.gen_range(-1.0..1.0);
+                low = hp * low + (1.0 - hp) * w;
+                lp1 = lp * lp1 + (1.0 - lp) * (w - low);
+                lp2 = lp * lp2 + (1.0 - lp) * lp1;
+                lp2
+            })
+            .collect();
+        normalize(x, amplitude)
+    }
+
+    pub fn hum(&self, freq: f32, harmonics: usize, amplitude: f32, len: usize) -> Vec<f32> {
+        let nyquist = self.sample_rate / 2.0;
+        let mut x = vec![0.0; len];
+        for k in 1..=harmonics.max(1) {
+            let f = freq * k as f32;
+            if f >= nyquist {
+                break;
+            }
+            let w = 2.0 * PI * f / self.sample_rate;
+            for (n, x) in x.iter_mut().enumerate() {
+                *x += (w * n as f32).sin() / k as f32;
+            }
+        }
+        normalize(x, amplitude)
+    }
+
+    pub fn clicks(&mut self, rate: f32, amplitude: f32, len: usize) -> Vec<f32> {
+        let mut x = vec![0.0; len];
+        let width = ((CLICK_LENGTH / 1000.0 * self.sample_rate) as usize).max(1);
+        let p = (rate / self.sample_rate).clamp(0.0, 1.0);
+        let mut n = 0;
+        while n + width < len {
+            if self.rng.gen_bool(p as f64) {
+                let sign = if self.rng.gen_bool(0.5) { 1.0 } else { -1.0 };
+                for i in 0..width {
+                    x[n + i] += sign * amplitude * (1.0 - i as f32 / width as f32);
+                }
+                n += width;
+            } else {
+                n += 1;
+            }
+        }
+        x
+    }
+}
+
+/// scale to the given peak, silence stays silent
+pub fn normalize(mut x: Vec<f32>, peak: f32) -> Vec<f32> {
+    let max = x.iter().fold(0.0f32, |m, x| m.max(x.abs()));
+    if max > 0.0 {
+        for x in x.iter_mut() {
+            *x *= peak / max;
+        }
+    }
+    x
+}
+
+/// signal plus noise scaled so the mix has the given snr in dB, the signal is left as it is
+pub fn mix_at_snr(signal: &[f32], noise: &[f32], snr: f32) -> Vec<f32> {
+    let energy = |x: &[f32]| x.iter().map(|x| x * x).sum::<f32>();
+    let noise_energy = energy(noise);
+    let scale = if noise_energy > 0.0 {
+        (energy(signal) / noise_energy / 10f32.powf(snr / 10.0)).sqrt()
+    } else {
+        0.0
+    };
+    signal
+        .iter()
+        .zip(noise.iter().chain(std::iter::repeat(&0.0)))
+        .map(|(s, n)| s + scale * n)
+        .collect()
+}
+
+/// interleaved samples held in memory, stands in for a file for tests and demos
+#[derive(Clone, Debug)]
+pub struct Source {
+    pub samples: Vec<f32>,
+    pub num_channels: usize,
+    pub sample_rate: u32,
+}
+
+impl Source {
+    pub fn mono(samples: Vec<f32>, sample_rate: u32) -> Self {
+        Self {
+            samples,
+            num_channels: 1,
+            sample_rate,
+        }
+    }
+
+    /// read a wav file as it is, no resampling to the device
+    pub fn load(path: &Path) -> DenoiserResult<Self> {
+        let f = File::open(path).map_err(|e| DenoiserError::Other(e.to_string()))?;
+        let (head, samples) = wav_io::read_from_file(f)
+            .map_err(|_| DenoiserError::Other("failed to read wav".to_string()))?;
+        Ok(Self {
+            samples,
+            num_channels: head.channels.max(1) as usize,
+            sample_rate: head.sample_rate,
+        })
+    }
+
+    /// 32 bit float wav
+    pub fn write(&self, path: &Path) -> DenoiserResult<()> {
+        let mut header = wav_io::new_header(self.sample_rate, 32, true, self.num_channels == 1);
+        header.channels = self.num_channels as u16;
+        let mut file = File::create(path).map_err(|e| DenoiserError::Other(e.to_string()))?;
+        wav_io::write_to_file(&mut file, &header, &self.samples)
+            .map_err(|_| DenoiserError::Other("failed to write wav".to_string()))
+    }
+}
+
+/// write a test signal for demos, optionally mixed with noise at the given snr
+/// returns the path so the file can be loaded like any other
+#[tauri::command]
+pub fn generate_test_signal(
+    signal: SignalKind,
+    noise: Option<SignalKind>,
+    snr: f32,
+    seconds: f32,
+    seed: Option<u64>,
+    app_handle: tauri::AppHandle,
+) -> Result<String, String> {
+    let sample_rate = crate::constants::SAMPLING_RATE;
+    let len = (seconds.max(0.0) * sample_rate) as usize;
+    let mut generator = Generator::new(seed.unwrap_or(0), sample_rate);
+    let s = generator.generate(signal, len);
+    let samples = match noise {
+        Some(noise) => {
+            let n = generator.generate(noise, len);
+            mix_at_snr(&s, &n, snr)
+        }
+        None => s,
+    };
+
+    let path = app_handle
+        .path_resolver()
+        .app_local_data_dir()
+        .expect("app local data dir should exist")
+        .join("test_signal.wav");
+    Source::mono(samples, sample_rate as u32)
+        .write(&path)
+        .map_err(|e| e.to_string())?;
+    Ok(path.to_string_lossy().to_string())
+}
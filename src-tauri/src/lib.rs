@@ -1,18 +1,33 @@
+pub mod ab;
 pub mod audio;
+pub mod compare;
 pub mod constants;
+pub mod declick;
+pub mod declip;
+pub mod dehum;
 pub mod errors;
 pub mod file_io;
 pub mod fourier;
+pub mod freq_scale;
+pub mod generator;
 pub mod messages;
+pub mod metering;
+pub mod metrics;
+pub mod mix;
+pub mod nlms;
+pub mod noise_regions;
+pub mod normalize;
+pub mod report;
 pub mod sdft;
 pub mod settings;
+pub mod speed;
 pub mod sql;
+pub mod transport;
 pub mod types;
+pub mod vad;
 
 #[cfg(test)]
 mod tests {
-    use std::{path::PathBuf, str::FromStr};
-
     use super::*;
     use constants::from_log;
     // use crate::sdft::*;
@@ -21,45 +36,1738 @@ mod tests {
     use sql::{query_filter_bank, query_ui_params};
     use types::*;
 
+    /// counts allocations and frees on each thread, so a test can check that the audio path never does either
+    #[cfg(debug_assertions)]
+    mod alloc_guard {
+        use std::alloc::{GlobalAlloc, Layout, System};
+        use std::cell::Cell;
+
+        thread_local! {
+            static ALLOCATIONS: Cell<usize> = const { Cell::new(0) };
+            static DEALLOCATIONS: Cell<usize> = const { Cell::new(0) };
+        }
+
+        struct CountingAllocator;
+
+        fn count() {
+            let _ = ALLOCATIONS.try_with(|a| a.set(a.get() + 1));
+        }
+
+        unsafe impl GlobalAlloc for CountingAllocator {
+            unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+                count();
+                System.alloc(layout)
+            }
+
+            unsafe fn alloc_zeroed(&self, layout: Layout) -> *mut u8 {
+                count();
+                System.alloc_zeroed(layout)
+            }
+
+            unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+                count();
+                System.realloc(ptr, layout, new_size)
+            }
+
+            unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+                let _ = DEALLOCATIONS.try_with(|d| d.set(d.get() + 1));
+                System.dealloc(ptr, layout)
+            }
+        }
+
+        #[global_allocator]
+        static ALLOCATOR: CountingAllocator = CountingAllocator;
+
+        /// fails the test if f allocates or frees on this thread
+        pub fn assert_no_alloc<T>(name: &str, f: impl FnOnce() -> T) -> T {
+            let before = ALLOCATIONS.with(|a| a.get());
+            let freed_before = DEALLOCATIONS.with(|d| d.get());
+            let out = f();
+            let allocations = ALLOCATIONS.with(|a| a.get()) - before;
+            let deallocations = DEALLOCATIONS.with(|d| d.get()) - freed_before;
+            assert_eq!(allocations, 0, "{} allocated {} times", name, allocations);
+            assert_eq!(deallocations, 0, "{} freed {} times", name, deallocations);
+            out
+        }
+    }
+
     #[test]
     fn sdft() {
+        use rand::{Rng, SeedableRng};
+        // the history is a fifo, get(0) is the sample pushed n pushes ago, which the sliding dft subtracts
         let n = 10;
         let mut x = ringbuff::Fixed::from(vec![Complex { re: 0.0, im: 0.0 }; n]);
-        for i in 0..n {
+        for i in 0..3 * n {
+            let oldest = x.get(0).re;
+            let expected = if i >= n { (i - n) as f32 } else { 0.0 };
+            assert_eq!(oldest, expected);
             x.push(Complex {
                 re: i as f32,
                 im: 0.0,
             });
         }
-        for _i in 0..n {
-            println!("{:?}", x.get(0));
-            x.push(Complex { re: 1.0, im: 0.0 });
+
+        // after every sample the magnitudes match a dft of the last size samples
+        let mut rng = rand::rngs::StdRng::seed_from_u64(42);
+        let signal: Vec<f32> = (0..600).map(|_| rng.gen_range(-1.0..1.0)).collect();
+        let mut sdft = sdft::SDFT::new(64);
+        for (i, s) in signal.iter().enumerate() {
+            sdft.analyze(*s);
+            if i < 64 || i % 50 != 0 {
+                continue;
+            }
+            let frame = &signal[i + 1 - 64..=i];
+            for k in 0..64 {
+                let bin: Complex<f32> = frame
+                    .iter()
+                    .enumerate()
+                    .map(|(t, x)| {
+                        Complex::new(0.0, -2.0 * std::f32::consts::PI * (k * t) as f32 / 64.0).exp()
+                            * x
+                    })
+                    .sum();
+                assert!(
+                    (sdft.mags[k] - bin.norm()).abs() < 1e-3,
+                    "{} {} {} {}",
+                    i,
+                    k,
+                    sdft.mags[k],
+                    bin.norm()
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn gain_smoothing_passthrough() {
+        // with no noise subtracted every gain is 1, the output is the input delayed by one sample
+        let mut sdft = sdft::SDFT::new(64);
+        let smoothing = GainSmoothing {
+            enabled: true,
+            ..Default::default()
+        };
+        let noise = vec![1.0; 64];
+        let mut last = 0.0;
+        for i in 0..512 {
+            let x = (i as f32 * 0.05).sin();
+            let y = sdft.spectral_subtraction_gain(
+                x,
+                &noise,
+                0.0,
+                &smoothing,
+                constants::SAMPLING_RATE,
+            );
+            assert!((last - y).abs() < 1e-3, "{} {} {}", i, last, y);
+            last = x;
+        }
+    }
+
+    #[test]
+    fn gain_smoothing_median() {
+        // every bin is under the noise except the ones set loud, gains are instant so only the median acts
+        let smoothing = |freq_width| GainSmoothing {
+            enabled: true,
+            attack: 0.0,
+            release: 0.0,
+            freq_width,
+            floor: -30.0,
+        };
+        let noise = vec![2.0; 64];
+        let floor = from_log(-30.0);
+        let gain = |loud: &[usize], freq_width| {
+            let mut sdft = sdft::SDFT::new(64);
+            sdft.mags.fill(0.5);
+            for k in loud {
+                sdft.mags[*k] = 10.0;
+            }
+            sdft.subtraction_gain(&noise, 1.0, &smoothing(freq_width), 48000.0);
+            sdft.gain_history[20]
+        };
+        // an isolated bin is musical noise, the median pulls it down to the floor
+        assert!((gain(&[20], 0) - 0.9).abs() < 1e-4);
+        assert!((gain(&[20], 1) - floor).abs() < 1e-4);
+        // a peak wider than the median goes through
+        assert!((gain(&[19, 20, 21], 1) - 0.9).abs() < 1e-4);
+    }
+
+    #[test]
+    fn gain_smoothing_step() {
+        // the gain reaches 1 - 1/e of a step after the time constant, counted at the rate it runs at
+        for sample_rate in [44100.0, 48000.0] {
+            let smoothing = GainSmoothing {
+                enabled: true,
+                attack: 5.0,
+                release: 20.0,
+                freq_width: 0,
+                floor: -60.0,
+            };
+            let noise = vec![2.0; 64];
+            let floor = from_log(-60.0);
+            let mut sdft = sdft::SDFT::new(64);
+            let step = |sdft: &mut sdft::SDFT, mag: f32, ms: f32| {
+                sdft.mags.fill(mag);
+                for _ in 0..(ms * 0.001 * sample_rate).round() as usize {
+                    sdft.subtraction_gain(&noise, 1.0, &smoothing, sample_rate);
+                }
+                sdft.gain_history[10]
+            };
+            // closing is the release, from 1 down to the floor
+            let g = step(&mut sdft, 0.5, smoothing.release);
+            let expected = floor + (1.0 - floor) * (-1.0f32).exp();
+            assert!((g - expected).abs() < 1e-3, "{} {}", g, expected);
+            // the rest of the way down, then opening is the attack, back up to 1
+            let g0 = step(&mut sdft, 0.5, 10.0 * smoothing.release);
+            let g = step(&mut sdft, 1e6, smoothing.attack);
+            let expected = 1.0 - (1.0 - g0) * (-1.0f32).exp();
+            assert!((g - expected).abs() < 1e-3, "{} {}", g, expected);
+        }
+    }
+
+    #[test]
+    fn spectral_gate() {
+        use rand::Rng;
+        let mut rng = rand::thread_rng();
+        let gate = SpectralGate {
+            enabled: true,
+            ..Default::default()
+        };
+        // flat noise floor, well above the noise and well below the tone
+        let noise_spectrum = vec![1.3; 256];
+        let rms = |v: &[f32]| (v.iter().map(|x| x * x).sum::<f32>() / v.len() as f32).sqrt();
+
+        let noise: Vec<f32> = (0..22050).map(|_| rng.gen_range(-0.01..0.01)).collect();
+        let mut sdft = sdft::SDFT::new(256);
+        let out: Vec<f32> = noise
+            .iter()
+            .map(|x| sdft.spectral_gate(*x, &noise_spectrum, 1.0, &gate, constants::SAMPLING_RATE))
+            .collect();
+        assert!(rms(&out[11025..]) < 0.2 * rms(&noise[11025..]));
+
+        // tone sits on bin 16, it should go through the open gate untouched
+        let tone: Vec<f32> = (0..22050)
+            .map(|i| 0.5 * (2.0 * std::f32::consts::PI * 16.0 * i as f32 / 256.0).sin())
+            .collect();
+        let mut sdft = sdft::SDFT::new(256);
+        let out: Vec<f32> = tone
+            .iter()
+            .zip(noise.iter())
+            .map(|(t, n)| {
+                sdft.spectral_gate(t + n, &noise_spectrum, 1.0, &gate, constants::SAMPLING_RATE)
+            })
+            .collect();
+        assert!((rms(&out[11025..]) / rms(&tone[11025..]) - 1.0).abs() < 0.05);
+        // hold is in ms whatever the rate
+        assert_eq!(gate.hold_samples(44100.0), 882);
+        assert_eq!(gate.hold_samples(48000.0), 960);
+    }
+
+    #[test]
+    fn adaptive_cancel() {
+        use rand::{Rng, SeedableRng};
+        let mut rng = rand::rngs::StdRng::seed_from_u64(31);
+        let noise: Vec<f32> = (0..44100).map(|_| rng.gen_range(-0.5..0.5)).collect();
+        let speech: Vec<f32> = (0..44100)
+            .map(|i| 0.1 * (2.0 * std::f32::consts::PI * 300.0 * i as f32 / 44100.0).sin())
+            .collect();
+        // the primary mic hears the noise through a short path, delayed and filtered
+        let primary: Vec<f32> = (0..44100)
+            .map(|i| {
+                let n = if i >= 5 {
+                    0.6 * noise[i - 3] - 0.3 * noise[i - 5]
+                } else {
+                    0.0
+                };
+                speech[i] + n
+            })
+            .collect();
+
+        let params = AdaptiveCancel {
+            enabled: true,
+            filter_length: 32,
+            step_size: 0.01,
+            ..Default::default()
+        };
+        let mut nlms = nlms::Nlms::new(params);
+        let out: Vec<f32> = primary
+            .iter()
+            .zip(noise.iter())
+            .map(|(p, r)| nlms.process(*p, *r))
+            .collect();
+        // output is the primary delayed by half the filter length
+        let residual: Vec<f32> = (22050..44100).map(|i| out[i] - speech[i - 16]).collect();
+        let rms = |v: &[f32]| (v.iter().map(|x| x * x).sum::<f32>() / v.len() as f32).sqrt();
+        assert!(rms(&residual) < 0.1 * rms(&speech), "{}", rms(&residual));
+    }
+
+    #[test]
+    fn stereo_modes() {
+        let x: Vec<f32> = (0..4096)
+            .map(|i| (i as f32 * 0.05).sin() + 0.3 * (i as f32 * 0.31).sin())
+            .collect();
+
+        // linked uses one gain for both sides, so a quieter copy stays exactly in proportion
+        let mut params = StereoParams::new();
+        params.stereo_mode.mode = StereoMode::Linked;
+        for side in [&mut params.left, &mut params.right] {
+            side.ui_params.noise_gain = 1.0;
+            side.noise_spectrum = vec![1.5; side.sdft.size];
+        }
+        for s in x.iter() {
+            let (l, r) = params.process(*s, 0.5 * s);
+            assert!((0.5 * l - r).abs() < 1e-4, "{} {}", l, r);
+        }
+
+        // the same input on both sides gives what one channel does on its own, for every smoothing path
+        for (gate, smoothing) in [(false, false), (false, true), (true, false)] {
+            let mut params = StereoParams::new();
+            params.stereo_mode.mode = StereoMode::Linked;
+            for side in [&mut params.left, &mut params.right] {
+                side.ui_params.noise_gain = 1.0;
+                side.ui_params.pre_smooth_gain = 0.5;
+                side.ui_params.post_smooth_gain = 0.5;
+                side.noise_spectrum = vec![1.5; side.sdft.size];
+                side.spectral_gate.enabled = gate;
+                side.gain_smoothing.enabled = smoothing;
+            }
+            let mut single = params.left.clone();
+            for s in x.iter() {
+                let (l, r) = params.process(*s, *s);
+                let y = single.process(*s);
+                assert!(
+                    (l - y).abs() < 1e-4 && (r - y).abs() < 1e-4,
+                    "{} {} {}",
+                    l,
+                    r,
+                    y
+                );
+            }
+        }
+
+        // with nothing subtracted mid/side gives back left and right, one sample late
+        let mut params = StereoParams::new();
+        params.stereo_mode.mode = StereoMode::MidSide;
+        for side in [&mut params.left, &mut params.right] {
+            side.ui_params.pre_smooth_gain = 0.0;
+            side.ui_params.post_smooth_gain = 0.0;
+        }
+        let mut last = (0.0, 0.0);
+        for s in x.iter() {
+            let (l, r) = params.process(*s, -0.25 * s);
+            assert!((l - last.0).abs() < 1e-3 && (r - last.1).abs() < 1e-3);
+            last = (*s, -0.25 * s);
+        }
+    }
+
+    #[test]
+    fn vad() {
+        use rand::{Rng, SeedableRng};
+        let mut rng = rand::rngs::StdRng::seed_from_u64(34);
+        // one second of noise, then half a second of a voiced harmonic stack, then noise again
+        let n = 2 * 44100;
+        let x: Vec<f32> = (0..n)
+            .map(|i| {
+                let t = i as f32 / constants::SAMPLING_RATE;
+                let voiced = if (44100..66150).contains(&i) {
+                    (1..=6)
+                        .map(|h| {
+                            (2.0 * std::f32::consts::PI * 150.0 * h as f32 * t).sin() / h as f32
+                        })
+                        .sum::<f32>()
+                        * 0.3
+                } else {
+                    0.0
+                };
+                voiced + 0.01 * (rng.gen::<f32>() - 0.5)
+            })
+            .collect();
+
+        let params = VadParams {
+            enabled: true,
+            hangover: 0.0,
+            ..Default::default()
+        };
+        let segments = vad::vad_segments(&x, 1, Some(0), &params, 256, constants::SAMPLING_RATE);
+        let speech: Vec<_> = segments.iter().filter(|s| s.speech).collect();
+        assert_eq!(speech.len(), 1, "{:?}", segments);
+        assert!(
+            (speech[0].start as i64 - 44100).abs() < 512,
+            "{:?}",
+            speech[0]
+        );
+        assert!(
+            (speech[0].length as i64 - 22050).abs() < 1024,
+            "{:?}",
+            speech[0]
+        );
+        // the hangover is in ms, so at twice the rate it holds on for twice the samples
+        let held = |sample_rate: f32| {
+            let params = VadParams {
+                hangover: 100.0,
+                ..params
+            };
+            let segments = vad::vad_segments(&x, 1, Some(0), &params, 256, sample_rate);
+            segments.iter().find(|s| s.speech).unwrap().length as f32 - speech[0].length as f32
+        };
+        let (single, double) = (held(44100.0), held(88200.0));
+        assert!((double / single - 2.0).abs() < 0.1, "{} {}", single, double);
+
+        // the learned profile only sees the noise, so it stays near the noise level through the speech
+        let mut p = AudioParams::new();
+        p.vad.set_params(params);
+        for s in x[..66150].iter() {
+            p.process(*s);
+        }
+        let noise: f32 = p.vad.noise_spectrum[1..128]
+            .iter()
+            .map(|m| m - 1.0)
+            .sum::<f32>()
+            / 127.0;
+        // white noise of that level has an expected bin magnitude around 0.01 * sqrt(256 / 12) * sqrt(pi) / 2
+        assert!(noise > 0.02 && noise < 0.06, "{}", noise);
+    }
+
+    #[test]
+    fn noise_regions() {
+        use rand::{Rng, SeedableRng};
+        let mut rng = rand::rngs::StdRng::seed_from_u64(35);
+        // four seconds of a wobbling tone over noise, except for a noise-only second in the middle
+        // and a quiet stretch of clicks, which is low in level but not stationary
+        let x: Vec<f32> = (0..4 * 44100)
+            .map(|i| {
+                let t = i as f32 / constants::SAMPLING_RATE;
+                let noise = 0.01 * (rng.gen::<f32>() - 0.5);
+                if (88200..132300).contains(&i) {
+                    noise
+                } else if (22050..44100).contains(&i) {
+                    noise + if i % 2000 < 20 { 0.05 } else { 0.0 }
+                } else {
+                    let f = 300.0 + 50.0 * (2.0 * std::f32::consts::PI * 3.0 * t).sin();
+                    noise + 0.3 * (2.0 * std::f32::consts::PI * f * t).sin()
+                }
+            })
+            .collect();
+
+        let regions = noise_regions::find_noise_regions(&x, 1, constants::SAMPLING_RATE, 3);
+        assert_eq!(regions.len(), 3);
+        let best = regions[0];
+        assert!(
+            best.start >= 88200 && best.start + best.len <= 132300,
+            "{:?}",
+            regions
+        );
+        assert!(regions.windows(2).all(|r| r[0].score <= r[1].score));
+
+        // stereo regions are in frames, same as mono
+        let stereo: Vec<f32> = x.iter().flat_map(|s| [*s, *s]).collect();
+        let best = noise_regions::find_noise_regions(&stereo, 2, constants::SAMPLING_RATE, 1)[0];
+        assert!(best.start >= 88200 && best.start + best.len <= 132300);
+
+        // regions are REGION_LENGTH long in seconds at whatever rate the file is
+        for sample_rate in [44100.0, 96000.0] {
+            let best = noise_regions::find_noise_regions(&x, 1, sample_rate, 1)[0];
+            let seconds = best.len as f32 / sample_rate;
+            assert!(
+                (seconds - noise_regions::REGION_LENGTH).abs() < 0.02,
+                "{}",
+                seconds
+            );
+        }
+    }
+
+    #[test]
+    fn stft() {
+        use fourier::{is_cola, window, Stft, WindowKind};
+        use rand::{Rng, SeedableRng};
+        let mut rng = rand::rngs::StdRng::seed_from_u64(36);
+        let x: Vec<f32> = (0..10000).map(|_| rng.gen::<f32>() - 0.5).collect();
+
+        assert!(is_cola(&window(WindowKind::Hann, 512), 128));
+        assert!(is_cola(&window(WindowKind::SqrtHann, 512), 256));
+        assert!(is_cola(&window(WindowKind::Hamming, 512), 128));
+        assert!(!is_cola(&window(WindowKind::Hann, 512), 256));
+
+        for (kind, size, hop) in [
+            (WindowKind::Hann, 512, 128),
+            (WindowKind::SqrtHann, 256, 128),
+            (WindowKind::Rectangular, 256, 256),
+            (WindowKind::Hamming, 1024, 256),
+        ] {
+            let s = Stft::new(size, hop, kind).unwrap();
+            let frames = s.forward(&x);
+            assert_eq!(frames.len(), s.num_frames(x.len()));
+            let y = s.inverse(&frames, x.len()).unwrap();
+            let err = x
+                .iter()
+                .zip(y.iter())
+                .fold(0.0f32, |m, (a, b)| m.max((a - b).abs()));
+            assert!(err < 1e-4, "{:?} {} {} {}", kind, size, hop, err);
+        }
+        assert!(Stft::new(512, 256, WindowKind::Hann)
+            .unwrap()
+            .inverse(&[], 0)
+            .is_err());
+
+        // with no noise gain the block denoiser only applies the output gain
+        let mut params = AudioParams::new();
+        params.ui_params.noise_gain = 0.0;
+        params.ui_params.output_gain = 0.5;
+        let y = fourier::block_denoise(&x, &params, 64, WindowKind::Hann).unwrap();
+        assert!(x
+            .iter()
+            .zip(y.iter())
+            .all(|(a, b)| (0.5 * a - b).abs() < 1e-4));
+        // and so does the sliding dft it's compared with, one sample late
+        let y = fourier::sliding_denoise(&x, &params);
+        assert!(x
+            .iter()
+            .zip(y[fourier::SLIDING_DELAY..].iter())
+            .all(|(a, b)| (0.5 * a - b).abs() < 1e-3));
+    }
+
+    #[test]
+    fn spectrogram() {
+        // silence, then a full scale sine in the second half, in stereo
+        let f = 2756.25;
+        let x: Vec<f32> = (0..44100)
+            .flat_map(|i| {
+                let t = i as f32 / constants::SAMPLING_RATE;
+                let s = if i >= 22050 {
+                    (2.0 * std::f32::consts::PI * f * t).sin()
+                } else {
+                    0.0
+                };
+                [s, s]
+            })
+            .collect();
+        let frames = fourier::SpectrogramFrames::new(&x, 2, 1024, 256).unwrap();
+        let image = frames.render(0, 44100, -120.0, 0.0, 100, 64);
+        assert_eq!(image.columns.len(), 100);
+        assert!(image.columns.iter().all(|c| c.len() == 64));
+
+        // 2756.25 Hz is bin 64 of 512, which is row 8 of 64
+        let loudest = |c: &Vec<f32>| (0..c.len()).max_by(|a, b| c[*a].total_cmp(&c[*b])).unwrap();
+        assert_eq!(loudest(&image.columns[75]), 8);
+        assert!(image.columns[75][8] > -1.0, "{}", image.columns[75][8]);
+        assert!(image.columns[25].iter().all(|v| *v == -120.0));
+
+        // zooming into the second half only sees the sine
+        let zoom = frames.render(22050 + 2048, 11025, -120.0, 0.0, 10, 64);
+        assert!(zoom.columns.iter().all(|c| loudest(c) == 8));
+    }
+
+    #[test]
+    fn freq_scale() {
+        use freq_scale::{scale_frequencies, FreqMapping};
+        use settings::PlotScale;
+        let nyquist = 22050.0;
+        for scale in [
+            PlotScale::Linear,
+            PlotScale::Mel,
+            PlotScale::Log,
+            PlotScale::Bark,
+        ] {
+            for hz in [30.0, 440.0, 5000.0, 20000.0] {
+                let back = scale.unwarp(scale.warp(hz, nyquist), nyquist);
+                assert!((back - hz).abs() / hz < 1e-3, "{:?} {} {}", scale, hz, back);
+            }
+            // a flat spectrum stays flat whatever the scale
+            let flat = FreqMapping::new(scale, 200, 128, 44100.0).map(&vec![2.0; 128]);
+            assert!(flat.iter().all(|x| (x - 2.0).abs() < 1e-4), "{:?}", scale);
+        }
+
+        // an empty spectrum maps to silence instead of reading past it
+        assert_eq!(
+            FreqMapping::new(PlotScale::Log, 16, 0, 44100.0).map(&[]),
+            vec![0.0; 16]
+        );
+
+        // linear at the bin resolution is the spectrum itself
+        let spectrum: Vec<f32> = (0..128).map(|i| i as f32).collect();
+        let mapped = FreqMapping::new(PlotScale::Linear, 128, 128, 44100.0).map(&spectrum);
+        assert!(spectrum
+            .iter()
+            .zip(mapped.iter())
+            .all(|(a, b)| (a - b).abs() < 1e-4));
+
+        // a peak lands on the point nearest its frequency
+        let mut peak = vec![0.0; 128];
+        peak[20] = 1.0;
+        let bin_hz = 22050.0 / 128.0;
+        for scale in [PlotScale::Mel, PlotScale::Log, PlotScale::Bark] {
+            let mapped = FreqMapping::new(scale, 64, 128, 44100.0).map(&peak);
+            let loudest = (0..64)
+                .max_by(|a, b| mapped[*a].total_cmp(&mapped[*b]))
+                .unwrap();
+            let freqs = scale_frequencies(scale, 64, nyquist);
+            let nearest = (0..64)
+                .min_by(|a, b| {
+                    (freqs[*a] - 20.0 * bin_hz)
+                        .abs()
+                        .total_cmp(&(freqs[*b] - 20.0 * bin_hz).abs())
+                })
+                .unwrap();
+            assert!(
+                loudest.abs_diff(nearest) <= 1,
+                "{:?} {} {}",
+                scale,
+                loudest,
+                nearest
+            );
+        }
+    }
+
+    #[test]
+    fn compare() {
+        use compare::Audition;
+        use freq_scale::FreqMapping;
+        use rand::{Rng, SeedableRng};
+        use settings::PlotScale;
+        let mut rng = rand::rngs::StdRng::seed_from_u64(39);
+        let x: Vec<f32> = (0..8192).map(|_| rng.gen_range(-0.5..0.5)).collect();
+
+        // with nothing subtracted the residual is silent once the input is lined up
+        let mut params = StereoParams::new();
+        params.left.ui_params.pre_smooth_gain = 0.0;
+        params.left.ui_params.post_smooth_gain = 0.0;
+        params.monitor.audition = Audition::Residual;
+        let mut out = [0.0];
+        for (n, s) in x.iter().enumerate() {
+            params.process_frame(&[*s], &mut out);
+            params.monitor_frame(&[*s], &mut out);
+            if n > 0 {
+                assert!(out[0].abs() < 1e-3, "{} {}", n, out[0]);
+            }
+        }
+
+        // the declicker's latency is accounted for, auditioning the input plays it back exactly
+        let mut params = StereoParams::new();
+        params.left.declicker.params.enabled = true;
+        params.monitor.audition = Audition::Input;
+        let latency = params.latency(0);
+        assert_eq!(latency, 1 + 2 * declick::DECLICK_HOP);
+        for (n, s) in x.iter().enumerate() {
+            params.process_frame(&[*s], &mut out);
+            params.monitor_frame(&[*s], &mut out);
+            let expected = if n >= latency { x[n - latency] } else { 0.0 };
+            assert_eq!(out[0], expected);
+        }
+
+        // output and residual add back up to the input, and every channel gets a block
+        let mut params = StereoParams::new();
+        params.set_num_channels(2);
+        params.monitor.compare = true;
+        for side in [&mut params.left, &mut params.right] {
+            side.ui_params.noise_gain = 1.0;
+            side.noise_spectrum = vec![0.2; side.sdft.size];
+        }
+        let mut out = [0.0; 2];
+        for s in x.iter().take(1000) {
+            params.process_frame(&[*s, 0.5 * s], &mut out);
+            params.monitor_frame(&[*s, 0.5 * s], &mut out);
+        }
+        let mapping = FreqMapping::new(PlotScale::Linear, 64, 128, 44100.0);
+        let mut comparer = compare::Comparer::new();
+        let mut recent = vec![];
+        params.monitor.copy_recent(&mut recent);
+        let blocks = comparer.take(&recent, &mapping);
+        assert_eq!(blocks.len(), 2);
+        for b in blocks.iter() {
+            assert_eq!(b.input.len(), 1000);
+            assert_eq!(b.input_spectrum.len(), 64);
+            assert!(b
+                .input
+                .iter()
+                .zip(b.output.iter().zip(b.residual.iter()))
+                .all(|(i, (o, r))| (i - o - r).abs() < 1e-6));
+            // something was taken out
+            assert!(b.residual_spectrum.iter().sum::<f32>() > 0.0);
+        }
+        // taken samples aren't sent again
+        assert!(comparer.take(&recent, &mapping)[0].input.is_empty());
+    }
+
+    #[test]
+    fn quality_metrics() {
+        use metrics::{channel_metrics, estimate_delay, quality_metrics};
+        use rand::{Rng, SeedableRng};
+        let mut rng = rand::rngs::StdRng::seed_from_u64(40);
+        use constants::SAMPLING_RATE;
+        use std::f32::consts::PI;
+        let sr = SAMPLING_RATE;
+        // syllable-rate modulated tones, so the envelopes have something to follow
+        let clean: Vec<f32> = (0..2 * SAMPLING_RATE as usize)
+            .map(|i| {
+                let t = i as f32 / sr;
+                let env = 0.5 + 0.5 * (2.0 * PI * 4.0 * t).sin();
+                env * (0.3 * (2.0 * PI * 440.0 * t).sin() + 0.2 * (2.0 * PI * 1250.0 * t).sin())
+            })
+            .collect();
+        let noise: Vec<f32> = (0..clean.len()).map(|_| rng.gen_range(-1.0..1.0)).collect();
+        let mix = |snr: f32| -> Vec<f32> {
+            let scale = (clean.iter().map(|x| x * x).sum::<f32>()
+                / noise.iter().map(|x| x * x).sum::<f32>()
+                / 10f32.powf(snr / 10.0))
+            .sqrt();
+            clean
+                .iter()
+                .zip(noise.iter())
+                .map(|(c, n)| c + scale * n)
+                .collect()
+        };
+
+        let same = quality_metrics(&clean, &clean, 0, sr);
+        assert!(same.stoi > 0.99, "{:?}", same);
+        assert!(same.log_spectral_distance < 1e-3, "{:?}", same);
+        assert_eq!(same.segmental_snr, 35.0);
+        assert!(same.noise_floor_reduction.abs() < 1e-4);
+
+        let noisy = quality_metrics(&clean, &mix(10.0), 0, sr);
+        assert!((noisy.snr - 10.0).abs() < 0.1, "{:?}", noisy);
+        let noisier = quality_metrics(&clean, &mix(-5.0), 0, sr);
+        assert!(noisier.stoi < noisy.stoi && noisy.stoi < same.stoi);
+        assert!(noisier.log_spectral_distance > noisy.log_spectral_distance);
+
+        // half as loud is a 6 dB lower floor
+        let half: Vec<f32> = noise.iter().map(|x| 0.5 * x).collect();
+        let m = quality_metrics(&noise, &half, 0, sr);
+        assert!((m.noise_floor_reduction - 6.02).abs() < 0.01, "{:?}", m);
+
+        // a processing delay is found and taken out, interleaved channels are measured separately
+        let mut delayed = vec![0.0; 37];
+        delayed.extend_from_slice(&noise);
+        assert_eq!(estimate_delay(&noise, &delayed, 4096), 37);
+        let stereo: Vec<f32> = noise.iter().flat_map(|x| [*x, 0.5 * x]).collect();
+        let delayed_stereo: Vec<f32> = delayed.iter().flat_map(|x| [*x, 0.5 * x]).collect();
+        let m = channel_metrics(&stereo, &delayed_stereo, 2, sr, None);
+        assert_eq!(m.len(), 2);
+        assert!(m.iter().all(|m| m.snr > 60.0), "{:?}", m);
+    }
+
+    #[test]
+    fn generator() {
+        use fourier::stft;
+        use generator::{mix_at_snr, Generator, SignalKind, Source};
+        use metrics::quality_metrics;
+        let sr = 44100.0;
+        let len = 44100;
+        let mut g = Generator::new(41, sr);
+
+        // the same seed gives the same material
+        let white = g.white(0.5, len);
+        assert_eq!(white, Generator::new(41, sr).white(0.5, len));
+
+        // slopes between 500 Hz and 4 kHz, three octaves apart
+        let slope = |x: &[f32]| {
+            let frames = stft(x, 1024, 512).unwrap();
+            let band = |hz: f32| {
+                let k = hz / sr * 1024.0;
+                let bins = (0.9 * k) as usize..(1.1 * k) as usize;
+                let n = (frames.len() * bins.len()) as f32;
+                let p: f32 = frames
+                    .iter()
+                    .map(|f| f[bins.clone()].iter().map(|m| m * m).sum::<f32>())
+                    .sum();
+                10.0 * (p / n).log10()
+            };
+            band(4000.0) - band(500.0)
+        };
+        assert!(slope(&white).abs() < 1.5, "{}", slope(&white));
+        let pink = g.generate(SignalKind::PinkNoise { amplitude: 0.5 }, len);
+        assert!((slope(&pink) + 9.0).abs() < 2.0, "{}", slope(&pink));
+        let brown = g.generate(SignalKind::BrownNoise { amplitude: 0.5 }, len);
+        assert!((slope(&brown) + 18.0).abs() < 3.0, "{}", slope(&brown));
+        let speech = g.generate(SignalKind::SpeechNoise { amplitude: 0.5 }, len);
+        assert!(slope(&speech) < -20.0, "{}", slope(&speech));
+
+        // harmonics past nyquist are left out, peaks are the asked for amplitude
+        let hum = g.hum(60.0, 1000, 0.25, len);
+        assert!((hum.iter().fold(0.0f32, |m, x| m.max(x.abs())) - 0.25).abs() < 1e-6);
+        let clicks = g.clicks(20.0, 1.0, len);
+        let starts = clicks.iter().filter(|x| x.abs() == 1.0).count();
+        assert!((5..60).contains(&starts), "{}", starts);
+
+        // mixed at the asked for snr
+        let tone = g.sine(440.0, 0.5, len);
+        let mix = mix_at_snr(&tone, &pink, 5.0);
+        assert!((quality_metrics(&tone, &mix, 0, sr).snr - 5.0).abs() < 0.05);
+
+        // sources go through a file unchanged
+        let source = Source {
+            samples: tone.iter().zip(&mix).flat_map(|(t, m)| [*t, *m]).collect(),
+            num_channels: 2,
+            sample_rate: 44100,
+        };
+        let path = std::env::temp_dir().join("denoiser_generator_test.wav");
+        source.write(&path).unwrap();
+        let loaded = Source::load(&path).unwrap();
+        let _ = std::fs::remove_file(&path);
+        assert_eq!(loaded.num_channels, 2);
+        assert_eq!(loaded.samples, source.samples);
+
+        // a noise floor above the generated noise takes most of it out
+        let noise = Source::mono(g.white(0.01, len), 44100);
+        let mut params = StereoParams::new();
+        params.left.ui_params.noise_gain = 1.0;
+        params.left.noise_spectrum = vec![1.3; 256];
+        let mut out = vec![0.0; len];
+        for (o, x) in out.iter_mut().zip(&noise.samples) {
+            params.process_frame(&[*x], std::slice::from_mut(o));
+        }
+        let rms = |v: &[f32]| (v.iter().map(|x| x * x).sum::<f32>() / v.len() as f32).sqrt();
+        assert!(rms(&out[len / 2..]) < 0.2 * rms(&noise.samples[len / 2..]));
+    }
+
+    #[test]
+    fn metering() {
+        use metering::{Meter, METER_FLOOR};
+        use std::f32::consts::PI;
+        let sr = 48000.0;
+        let sine = |freq: f32, amplitude: f32, len: usize| -> Vec<f32> {
+            (0..len)
+                .map(|n| amplitude * (2.0 * PI * freq * n as f32 / sr).sin())
+                .collect()
+        };
+
+        // BS.1770 calibration, a full scale 997 Hz sine in one channel is -3.01 LUFS
+        let tone = sine(997.0, 1.0, 5 * sr as usize);
+        let m = Meter::measure(&tone, 1, sr);
+        assert!((m.integrated + 3.01).abs() < 0.05, "{:?}", m);
+        assert!((m.momentary + 3.01).abs() < 0.05, "{:?}", m);
+        assert!((m.short_term + 3.01).abs() < 0.05, "{:?}", m);
+        assert!(m.peak[0].abs() < 0.01 && (m.rms[0] + 3.01).abs() < 0.01);
+
+        // the same in both channels sums to 0 LUFS
+        let stereo: Vec<f32> = tone.iter().flat_map(|x| [*x, *x]).collect();
+        let m = Meter::measure(&stereo, 2, sr);
+        assert!(m.integrated.abs() < 0.05, "{:?}", m);
+        // silence before it is gated out, only the few blocks straddling the start pull it down
+        let mut delayed = vec![0.0; 4 * sr as usize];
+        delayed.extend_from_slice(&stereo);
+        let m = Meter::measure(&delayed, 2, sr);
+        assert!(m.integrated.abs() < 0.2, "{:?}", m);
+
+        // a quarter sample rate sine sampled off its peaks, true peak finds the peak between samples
+        let tone: Vec<f32> = (0..sr as usize)
+            .map(|n| (PI / 2.0 * (n % 4) as f32 + PI / 4.0).sin())
+            .collect();
+        let m = Meter::measure(&tone, 1, sr);
+        assert!((m.peak[0] + 3.01).abs() < 0.01, "{:?}", m);
+        assert!(m.true_peak[0].abs() < 0.5, "{:?}", m);
+
+        // readings are throttled and peaks start over after each one
+        let mut meter = Meter::new(1, sr);
+        assert!(!meter.ready());
+        for x in sine(997.0, 0.5, (0.1 * sr) as usize) {
+            meter.process_frame(&[x]);
+        }
+        assert!(meter.ready());
+        assert!((meter.take().peak[0] + 6.02).abs() < 0.01);
+        meter.process_frame(&[0.0]);
+        assert_eq!(meter.take().peak[0], METER_FLOOR);
+    }
+
+    #[test]
+    fn normalize() {
+        use generator::Generator;
+        use metering::Meter;
+        use normalize::normalize;
+        let sr = 44100.0;
+        let mut g = Generator::new(44, sr);
+        // quiet material with loud clicks, reaching the target needs the limiter
+        let noise = g.pink(0.05, 10 * sr as usize);
+        let clicks = g.clicks(2.0, 0.5, noise.len());
+        let mut samples: Vec<f32> = noise
+            .iter()
+            .zip(clicks.iter())
+            .map(|(n, c)| n + c)
+            .collect();
+        let params = NormalizeParams {
+            enabled: true,
+            target: -16.0,
+            ceiling: -1.0,
+        };
+        let n = normalize(&mut samples, 1, sr, &params);
+        let m = Meter::measure(&samples, 1, sr);
+        assert!(n.gain > 10.0 && n.limited > 0.0, "{:?}", n);
+        assert!((m.integrated + 16.0).abs() < 0.5, "{:?} {:?}", n, m);
+        assert!(m.true_peak[0] < -0.9, "{:?}", m);
+
+        // a quiet file that needs no limiting comes out exactly on target
+        let mut samples = g.pink(0.01, 10 * sr as usize);
+        let n = normalize(&mut samples, 1, sr, &params);
+        let m = Meter::measure(&samples, 1, sr);
+        assert_eq!(n.limited, 0.0);
+        assert!((m.integrated + 16.0).abs() < 0.01, "{:?}", m);
+
+        // silence stays silent
+        let mut silence = vec![0.0; sr as usize];
+        let n = normalize(&mut silence, 1, sr, &params);
+        assert_eq!(n.gain, 0.0);
+        assert!(silence.iter().all(|x| *x == 0.0));
+    }
+
+    #[test]
+    fn snapshot_buffer() {
+        use metering::Metering;
+        use report::{snapshot_buffer, Snapshot};
+
+        // the reader only sees published values, and only the newest
+        let (mut writer, mut reader) = snapshot_buffer(0);
+        assert!(!reader.update());
+        for i in 1..=3 {
+            *writer.get() = i;
+            writer.publish();
         }
-        for _i in 0..n {
-            println!("{:?}", x.get(0));
+        assert!(reader.update());
+        assert_eq!(*reader.get(), 3);
+        assert!(!reader.update());
+        assert_eq!(*reader.get(), 3);
+
+        // a slow reader never sees a half written value
+        let (mut writer, mut reader) = snapshot_buffer(vec![0usize; 256]);
+        let handle = std::thread::spawn(move || {
+            for i in 1..=20000 {
+                writer.get().fill(i);
+                writer.publish();
+            }
+        });
+        let mut last = 0;
+        while !reader.is_closed() || reader.update() {
+            reader.update();
+            let v = reader.get();
+            assert!(v.iter().all(|x| *x == v[0]), "torn snapshot");
+            assert!(v[0] >= last);
+            last = v[0];
         }
+        handle.join().unwrap();
+        assert_eq!(last, 20000);
+
+        // updating a snapshot reuses its buffers
+        let mut params = StereoParams::new();
+        params.monitor.compare = true;
+        let mut snapshot = Snapshot::new(&params);
+        let meter = Metering {
+            peak: vec![-6.0],
+            rms: vec![-12.0],
+            true_peak: vec![-5.0],
+            ..Default::default()
+        };
+        snapshot.update(&params, &meter, 1);
+        let pointers = (
+            snapshot.spectrum.as_ptr(),
+            snapshot.meter.peak.as_ptr(),
+            snapshot.recent[0].samples[0].as_ptr(),
+        );
+        params.transport.frame = 100;
+        snapshot.update(&params, &meter, 2);
+        assert_eq!(
+            pointers,
+            (
+                snapshot.spectrum.as_ptr(),
+                snapshot.meter.peak.as_ptr(),
+                snapshot.recent[0].samples[0].as_ptr(),
+            )
+        );
+        assert_eq!(snapshot.transport.frame, 100);
+        assert_eq!(snapshot.meter.peak, vec![-6.0]);
+    }
+
+    #[test]
+    fn multichannel() {
+        // 5.1 onto stereo folds L C Ls and R LFE Rs, averaged
+        let mut out = vec![0.0; 2];
+        audio::map_channels(&[1.0, 2.0, 3.0, 4.0, 5.0, 6.0], &mut out);
+        assert_eq!(out, vec![3.0, 4.0]);
+        // stereo onto a quad device leaves the rear silent, mono plays everywhere
+        let mut out = vec![1.0; 4];
+        audio::map_channels(&[0.5, -0.5], &mut out);
+        assert_eq!(out, vec![0.5, -0.5, 0.0, 0.0]);
+        audio::map_channels(&[0.25], &mut out);
+        assert_eq!(out, vec![0.25; 4]);
+
+        // every channel of a six channel file gets its own chain
+        let mut params = StereoParams::new();
+        params.set_num_channels(6);
+        for i in 0..6 {
+            let c = params.channel_mut(i);
+            c.ui_params.pre_smooth_gain = 0.0;
+            c.ui_params.post_smooth_gain = 0.0;
+            c.ui_params.output_gain = (i + 1) as f32;
+        }
+        let mut frame = vec![0.0; 6];
+        let mut last = 0.0;
+        for i in 0..1024 {
+            let x = (i as f32 * 0.05).sin();
+            params.process_frame(&[x; 6], &mut frame);
+            for (c, y) in frame.iter().enumerate() {
+                assert!((y - (c + 1) as f32 * last).abs() < 1e-3, "{} {}", c, y);
+            }
+            last = x;
+        }
+
+        // a message for one channel past the first two only reaches that one, both reaches them all
+        let gain = |g: f32| messages::ChannelMessage {
+            output_gain: Some(g),
+            ..Default::default()
+        };
+        messages::UIAudioMessage {
+            channel: Some((3, gain(0.5))),
+            ..Default::default()
+        }
+        .receive(&mut params);
+        let gains = |params: &StereoParams| -> Vec<f32> {
+            (0..6)
+                .map(|i| params.channel(i).ui_params.output_gain)
+                .collect()
+        };
+        assert_eq!(gains(&params), vec![1.0, 2.0, 3.0, 0.5, 5.0, 6.0]);
+        messages::UIAudioMessage {
+            left_channel: Some(gain(0.25)),
+            ..Default::default()
+        }
+        .receive(&mut params);
+        assert_eq!(gains(&params), vec![0.25, 2.0, 3.0, 0.5, 5.0, 6.0]);
+        messages::UIAudioMessage {
+            left_channel: Some(gain(0.1)),
+            right_channel: Some(gain(0.1)),
+            extra_channels: Some(gain(0.1)),
+            ..Default::default()
+        }
+        .receive(&mut params);
+        assert_eq!(gains(&params), vec![0.1; 6]);
+
+        // each channel past the first two keeps its own rows and mute, starting from both
+        let dir =
+            std::env::temp_dir().join(format!("denoiser_channel_test_{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let p = dir.join("db.sqlite");
+        let _ = std::fs::remove_file(&p);
+        sql::create_db(p.clone()).unwrap();
+        sql::update_db(&p).unwrap();
+        let mut both = UIParams::new();
+        both.noise_gain = -6.0;
+        sql::update_ui_params(StereoChoice::Both, both, p.clone()).unwrap();
+        let mut own = UIParams::new();
+        own.noise_gain = -12.0;
+        sql::update_ui_params(StereoChoice::Channel(3), own, p.clone()).unwrap();
+        sql::update_mute(StereoChoice::Channel(3), true, p.clone()).unwrap();
+        let noise = |c| query_ui_params(c, &p).unwrap().noise_gain;
+        assert_eq!(noise(StereoChoice::Channel(2)), -6.0);
+        assert_eq!(noise(StereoChoice::Channel(3)), -12.0);
+        assert!(sql::query_mute(StereoChoice::Channel(3), &p).unwrap());
+        assert!(!sql::query_mute(StereoChoice::Channel(2), &p).unwrap());
+        let mut extra = sql::query_extra_channels(&p).unwrap();
+        extra.sort_by_key(|c| c.name());
+        assert_eq!(
+            extra,
+            vec![StereoChoice::Channel(2), StereoChoice::Channel(3)]
+        );
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn dehum() {
+        // 60 Hz mains running a little fast, with a few harmonics, found and removed at the rate it was made at
+        let f0 = 60.4;
+        for sample_rate in [44100.0, 48000.0] {
+            let hum: Vec<f32> = (0..2 * sample_rate as usize)
+                .map(|i| {
+                    let t = i as f32 / sample_rate;
+                    (1..=4)
+                        .map(|h| (2.0 * std::f32::consts::PI * h as f32 * f0 * t).sin() / h as f32)
+                        .sum()
+                })
+                .collect();
+            let detected = dehum::detect_hum_fundamental(&hum, 4, sample_rate).unwrap();
+            assert!((detected - f0).abs() < 0.3, "{} {}", sample_rate, detected);
+
+            let params = dehum::resolve_hum_params(HumParams::new(), &hum, sample_rate);
+            let mut d = dehum::Dehum::new(params, sample_rate);
+            let out: Vec<f32> = hum.iter().map(|x| d.process(*x)).collect();
+            let rms = |v: &[f32]| (v.iter().map(|x| x * x).sum::<f32>() / v.len() as f32).sqrt();
+            // skip the notches ringing in
+            let settled = sample_rate as usize;
+            assert!(rms(&out[settled..]) < 0.1 * rms(&hum[settled..]));
+        }
+    }
+
+    #[test]
+    fn declick() {
+        let clean: Vec<f32> = (0..8 * declick::DECLICK_HOP)
+            .map(|i| {
+                let t = i as f32 / constants::SAMPLING_RATE;
+                0.5 * (2.0 * std::f32::consts::PI * 440.0 * t).sin()
+                    + 0.2 * (2.0 * std::f32::consts::PI * 1230.0 * t).sin()
+            })
+            .collect();
+        let mut clicked = clean.clone();
+        for c in [1500, 2100, 2900] {
+            clicked[c] += 0.8;
+            clicked[c + 1] -= 0.6;
+        }
+
+        let mut params = DeclickParams::new();
+        params.enabled = true;
+        let mut d = declick::Declicker::new(params, constants::SAMPLING_RATE);
+        let out: Vec<f32> = clicked.iter().map(|x| d.process(*x)).collect();
+        let delay = 2 * declick::DECLICK_HOP;
+        for c in [1500, 2100, 2900] {
+            for n in c - 4..c + 4 {
+                assert!(
+                    (out[n + delay] - clean[n]).abs() < 0.05,
+                    "{} {}",
+                    n,
+                    out[n + delay]
+                );
+            }
+        }
+
+        // long clicks are left alone instead of stalling the callback
+        params.max_click_length = 1000.0;
+        for sample_rate in [44100.0, 96000.0] {
+            assert_eq!(
+                params.max_samples(sample_rate),
+                (declick::MAX_CLICK_LENGTH * 0.001 * sample_rate) as usize
+            );
+        }
+    }
+
+    #[test]
+    fn declip() {
+        let clean: Vec<f32> = (0..8192)
+            .map(|i| {
+                let t = i as f32 / constants::SAMPLING_RATE;
+                (2.0 * std::f32::consts::PI * 220.0 * t).sin()
+                    + 0.1 * (2.0 * std::f32::consts::PI * 660.0 * t).sin()
+            })
+            .collect();
+        let clipped: Vec<f32> = clean.iter().map(|x| x.clamp(-0.8, 0.8)).collect();
+
+        let mut params = DeclipParams::new();
+        params.enabled = true;
+        let mut repaired = clipped.clone();
+        let clips = declip::declip(&mut repaired, &[params]);
+        // runs right at the start or end don't have enough context to repair
+        let middle = 1024..7168;
+        assert!(clips
+            .iter()
+            .filter(|c| middle.contains(&c.start))
+            .all(|c| c.repaired));
+
+        let max_err = |v: &[f32]| {
+            v[middle.clone()]
+                .iter()
+                .zip(clean[middle.clone()].iter())
+                .fold(0.0f32, |m, (a, b)| m.max((a - b).abs()))
+        };
+        assert!(
+            max_err(&repaired) < 0.25 * max_err(&clipped),
+            "{}",
+            max_err(&repaired)
+        );
     }
 
     // need to make sure values are not NaN...that's happening somehow with IIR2
     #[test]
     fn make_freq_response() {
+        // temporary db set up the same way as on app startup
+        let dir = std::env::temp_dir().join(format!("denoiser_lib_test_{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let p = dir.join("db.sqlite");
+        let _ = std::fs::remove_file(&p);
+        sql::create_db(p.clone()).unwrap();
+        sql::update_db(&p).unwrap();
+
+        let mut ui_params = UIParams::new();
+        ui_params.noise_gain = -6.0;
+        ui_params.filters.bank[0] = BPF {
+            gain: 12.0,
+            freq: 500.0,
+            Q: 2.0,
+        };
+        sql::update_ui_params(StereoChoice::Both, ui_params, p.clone()).unwrap();
+
         let mut params = StereoParams::new();
-        let p = PathBuf::from_str("C:\\Users\\eric\\denoiser\\src-tauri\\target\\debug")
-            .expect("failed to make path from str")
-            .join("db.sqlite");
-        let left_ui_params = query_ui_params(StereoChoice::Both, &p);
-        let lu = left_ui_params.unwrap();
-        let filter_bank = query_filter_bank(StereoChoice::Both, &p);
-        let fb = filter_bank.unwrap();
+        let lu = query_ui_params(StereoChoice::Both, &p).unwrap();
+        let fb = query_filter_bank(StereoChoice::Both, &p).unwrap();
+        let _ = std::fs::remove_dir_all(&dir);
         let filters: Filters = fb.into();
 
-        println!("{:?}", fb.bank);
-        println!("{:?}", filters);
-
         params.left.ui_params.noise_gain = from_log(lu.noise_gain);
         params.left.ui_params.output_gain = from_log(lu.output_gain);
         params.left.ui_params.pre_smooth_gain = lu.pre_smooth_gain;
         params.left.ui_params.post_smooth_gain = lu.post_smooth_gain;
-        // println!("{:?}", params);
+        assert!((params.left.ui_params.noise_gain - 0.5012).abs() < 1e-3);
+        assert_eq!(fb.bank[0].freq, 500.0);
+
+        // the boosted filter lifts the response around its center, the others are flat
+        let response = filters.parallel_transfer(256);
+        let bin = (500.0 / (constants::SAMPLING_RATE / 2.0) * 256.0).round() as usize;
+        assert!(response[bin] > response[100], "{:?}", response);
+    }
+
+    #[test]
+    #[cfg(debug_assertions)]
+    fn allocation_free() {
+        use ab::{AbCompare, AbMessage, AbSlot};
+        use alloc_guard::assert_no_alloc;
+        use compare::Audition;
+        use generator::Generator;
+        use messages::{ChannelBuffers, ChannelMessage, UIAudioMessage};
+        use metering::Meter;
+        use report::{snapshot_buffer, Snapshot};
+        use speed::Speed;
+        use transport::{EndAction, Seek, Transport, TransportCommand};
+
+        let sr = constants::SAMPLING_RATE;
+        let len = 3 * sr as usize;
+        let mut g = Generator::new(46, sr);
+        let noise = g.white(0.1, len);
+        let clicks = g.clicks(20.0, 0.5, len);
+        let hum = g.hum(50.0, 4, 0.05, len);
+        let samples: Vec<f32> = (0..len)
+            .flat_map(|n| {
+                let x = noise[n] + clicks[n] + hum[n];
+                [x, 0.5 * x]
+            })
+            .collect();
+
+        let modes = [
+            (StereoMode::Independent, false),
+            (StereoMode::Linked, false),
+            (StereoMode::MidSide, false),
+            (StereoMode::Independent, true),
+        ];
+        for (mode, cancel) in modes {
+            let name = format!("{:?} cancel {}", mode, cancel);
+            // every stage that can run in the callback
+            let mut params = StereoParams::new();
+            params.set_num_channels(2);
+            params.stereo_mode.mode = mode;
+            params.canceller.params.enabled = cancel;
+            for side in [&mut params.left, &mut params.right] {
+                side.declicker.params.enabled = true;
+                side.dehum.params.enabled = true;
+                side.dehum.params.track = true;
+                side.vad.params.enabled = true;
+                side.gain_smoothing.enabled = true;
+            }
+            params.right.spectral_gate.enabled = true;
+            params.monitor.compare = true;
+            params.monitor.audition = Audition::Residual;
+            params.num_file_samples = samples.len();
+            params.transport = Transport::new(samples.len() / 2, sr);
+            params
+                .transport
+                .apply(TransportCommand::EndAction(EndAction::Loop));
+            params.transport.apply(TransportCommand::Play);
+
+            let mut meter = Meter::new(2, sr);
+            let mut reading = meter.take();
+            let mut readings = 0;
+            let (mut writer, _reader) = snapshot_buffer(Snapshot::new(&params));
+            let mut speed = Speed::new(2);
+            let mut ab = AbCompare::new(2, sr);
+            let mut input = [0.0; 2];
+            let mut processed = [0.0; 2];
+
+            // every kind of message, built the way the senders build them, by the buffer they arrive before
+            let msg = |i: usize, m: UIAudioMessage| (i, m);
+            let every_channel = |ch: ChannelMessage| {
+                let mut m = UIAudioMessage {
+                    left_channel: Some(ch),
+                    right_channel: Some(ch),
+                    extra_channels: Some(ch),
+                    channel: Some((2, ch)),
+                    ..Default::default()
+                };
+                m.buffers = (0..2).map(|i| (i, ChannelBuffers::new(&ch, sr))).collect();
+                m
+            };
+            let transport = |t: TransportCommand| UIAudioMessage {
+                transport: Some(t),
+                ..Default::default()
+            };
+            let ab_msg = |a: AbMessage| UIAudioMessage {
+                ab: Some(a),
+                ..Default::default()
+            };
+            // sets are built before they're sent, the callback only moves them in
+            let set = |noise_gain: f32, sample_rate: f32| {
+                let mut set = Box::new(params.clone());
+                set.left.ui_params.noise_gain = noise_gain;
+                set.set_sample_rate(sample_rate);
+                Some(set)
+            };
+            let mut hum_params = params.left.dehum.params;
+            hum_params.harmonics -= 2;
+            let mut declick = params.left.declicker.params;
+            declick.max_click_length = declick::MAX_CLICK_LENGTH;
+            let mut cancel_params = params.canceller.params;
+            cancel_params.filter_length /= 2;
+            let mut stereo_mode = params.stereo_mode;
+            stereo_mode.link = LinkDetection::Average;
+            let mut messages = vec![
+                msg(
+                    5,
+                    every_channel(ChannelMessage {
+                        clean: Some(false),
+                        mute: Some(false),
+                        output_gain: Some(1.0),
+                        noise_gain: Some(0.5),
+                        pre_smooth_gain: Some(0.5),
+                        post_smooth_gain: Some(0.5),
+                        filters: Some([Some(BPF::new()); constants::NUM_FILTERS]),
+                        gain_smoothing: Some(params.left.gain_smoothing),
+                        spectral_gate: Some(params.right.spectral_gate),
+                        hum: Some(hum_params),
+                        declick: Some(declick),
+                        vad: Some(params.left.vad.params),
+                        ..Default::default()
+                    }),
+                ),
+                msg(
+                    10,
+                    UIAudioMessage {
+                        adaptive_cancel: Some(nlms::Nlms::new(cancel_params)),
+                        stereo_mode: Some(stereo_mode),
+                        freq_scale: Some((settings::PlotScale::Log, 64)),
+                        audition: Some(Audition::Residual),
+                        compare: Some(true),
+                        ui_rate: Some(60.0),
+                        file_path: Some("unused.wav".into()),
+                        ..Default::default()
+                    },
+                ),
+                // bypassed and mixed, then compared
+                msg(
+                    20,
+                    every_channel(ChannelMessage {
+                        clean: Some(true),
+                        ..Default::default()
+                    }),
+                ),
+                msg(
+                    25,
+                    every_channel(ChannelMessage {
+                        clean: Some(false),
+                        ..Default::default()
+                    }),
+                ),
+                msg(
+                    30,
+                    UIAudioMessage {
+                        mix: Some(MixParams { wet: 0.5 }),
+                        ..Default::default()
+                    },
+                ),
+                msg(35, ab_msg(AbMessage::Store(AbSlot::A, set(1.0, sr)))),
+                msg(35, ab_msg(AbMessage::Store(AbSlot::B, set(1.0, sr)))),
+                msg(35, ab_msg(AbMessage::Enabled(true))),
+                // one that replaces a set and one that's ignored for its rate
+                msg(38, ab_msg(AbMessage::Store(AbSlot::A, set(0.5, sr)))),
+                msg(38, ab_msg(AbMessage::Store(AbSlot::B, set(0.5, 2.0 * sr)))),
+                msg(40, ab_msg(AbMessage::Select(AbSlot::B))),
+                msg(45, ab_msg(AbMessage::Enabled(false))),
+                msg(50, transport(TransportCommand::Seek(Seek::Frames(0)))),
+                // past the end, so looping is covered too, then round a loop region
+                msg(
+                    samples.len() / 2048 + 20,
+                    transport(TransportCommand::Loop(Some((
+                        Seek::Seconds(1.0),
+                        Seek::Seconds(1.2),
+                    )))),
+                ),
+            ];
+            // and faster then slower
+            for (i, rate, mode) in [
+                (70, 1.5, SpeedMode::PreservePitch),
+                (90, 0.75, SpeedMode::Varispeed),
+                (110, 3.0, SpeedMode::PreservePitch),
+            ] {
+                messages.push(msg(
+                    i,
+                    transport(TransportCommand::Speed(SpeedParams { rate, mode })),
+                ));
+            }
+
+            // the same work as the callback, a buffer at a time
+            let mut callback = |params: &mut StereoParams, ab: &mut AbCompare| {
+                speed.sync(&params.transport);
+                ab.sync(&params.transport);
+                for _ in 0..1024 {
+                    speed.next(&mut processed, |processed| {
+                        let Some(playhead) = params.transport.advance() else {
+                            return false;
+                        };
+                        playhead.read(&samples, &mut input);
+                        params.process_frame(&input, processed);
+                        params.monitor_frame(&input, processed);
+                        if ab.is_active() {
+                            ab.process_frame(&input, processed);
+                        }
+                        meter.process_frame(processed);
+                        true
+                    });
+                }
+                if meter.ready() {
+                    meter.take_into(&mut reading);
+                    readings += 1;
+                }
+                writer.get().update(params, &reading, readings);
+                writer.publish();
+            };
+            assert_no_alloc(&name, || {
+                for i in 0..samples.len() / 2048 + 60 {
+                    // what they replace is left in them, freed after like the stream's dropper thread does
+                    for (_, m) in messages.iter_mut().filter(|(at, _)| *at == i) {
+                        if let Some(a) = m.ab.as_mut() {
+                            ab.apply(a);
+                        }
+                        m.receive(&mut params);
+                    }
+                    callback(&mut params, &mut ab);
+                }
+            });
+            // the first sets moved in, the replaced and the ignored ones are left to be freed
+            let stored: Vec<bool> = messages
+                .iter()
+                .filter_map(|(_, m)| match &m.ab {
+                    Some(AbMessage::Store(_, set)) => Some(set.is_some()),
+                    _ => None,
+                })
+                .collect();
+            assert_eq!(stored, vec![false, false, true, true], "{}", name);
+            assert_eq!(
+                params.canceller.params.filter_length,
+                cancel_params.filter_length
+            );
+            assert_eq!(params.left.dehum.notches.len(), hum_params.harmonics);
+            assert!(readings > 20, "{}", name);
+        }
+    }
+
+    #[test]
+    fn transport() {
+        use transport::*;
+        let frame = |t: &mut Transport| t.advance().map(|p| p.frame);
+        let mut t = Transport::new(100, 10.0);
+        assert_eq!(frame(&mut t), None);
+        t.apply(TransportCommand::Play);
+        assert_eq!(frame(&mut t), Some(0));
+        assert_eq!(frame(&mut t), Some(1));
+
+        // paused holds its place, stop goes back to the start
+        t.apply(TransportCommand::Pause);
+        assert_eq!(frame(&mut t), None);
+        assert_eq!(t.frame, 2);
+        t.apply(TransportCommand::Play);
+        assert_eq!(frame(&mut t), Some(2));
+        assert!(t.apply(TransportCommand::Stop));
+        assert_eq!((t.state, t.frame), (TransportState::Stopped, 0));
+        t.apply(TransportCommand::Pause);
+        assert_eq!(t.state, TransportState::Stopped);
+
+        // seeks are in frames or seconds whatever the channel count, and clamp to the file
+        assert!(t.apply(TransportCommand::Seek(Seek::Seconds(2.5))));
+        assert_eq!(t.frame, 25);
+        t.apply(TransportCommand::Seek(Seek::Frames(40)));
+        assert_eq!(t.frame, 40);
+        assert_eq!(t.seconds(), 4.0);
+        t.apply(TransportCommand::Seek(Seek::Seconds(-1.0)));
+        assert_eq!(t.frame, 0);
+        t.apply(TransportCommand::Seek(Seek::Frames(1000)));
+        assert_eq!(t.frame, 100);
+
+        // the end stops and goes back to the start once
+        t.apply(TransportCommand::Seek(Seek::Frames(98)));
+        t.apply(TransportCommand::Play);
+        let played: Vec<_> = (0..4).map(|_| frame(&mut t)).collect();
+        assert_eq!(played, vec![Some(98), Some(99), None, None]);
+        assert_eq!((t.state, t.frame, t.ends), (TransportState::Stopped, 0, 1));
+
+        // or carries on from the start
+        t.apply(TransportCommand::EndAction(EndAction::Loop));
+        t.apply(TransportCommand::Seek(Seek::Frames(99)));
+        t.apply(TransportCommand::Play);
+        let played: Vec<_> = (0..3).map(|_| frame(&mut t)).collect();
+        assert_eq!(played, vec![Some(99), Some(0), Some(1)]);
+        assert_eq!((t.state, t.ends), (TransportState::Playing, 2));
+
+        // a loop region jumps to its start and wraps inside it, leaving it plays on
+        let region = TransportCommand::Loop(Some((Seek::Frames(10), Seek::Seconds(1.3))));
+        assert!(t.apply(region));
+        let played: Vec<_> = (0..5).map(|_| frame(&mut t).unwrap()).collect();
+        assert_eq!(played, vec![10, 11, 12, 10, 11]);
+        assert_eq!(t.loop_seconds(), Some((1.0, 1.3)));
+        assert!(!t.apply(TransportCommand::Loop(None)));
+        assert_eq!(frame(&mut t), Some(12));
+        assert_eq!(frame(&mut t), Some(13));
+
+        // a region running past the end is cut to the file and loops instead of ending
+        t.apply(TransportCommand::EndAction(EndAction::Stop));
+        t.apply(TransportCommand::Loop(Some((
+            Seek::Frames(98),
+            Seek::Frames(108),
+        ))));
+        let played: Vec<_> = (0..3).map(|_| frame(&mut t).unwrap()).collect();
+        assert_eq!(played, vec![98, 99, 98]);
+        assert_eq!(t.ends, 2);
+    }
+
+    #[test]
+    fn loop_crossfade() {
+        use std::f32::consts::PI;
+        use transport::*;
+        let sr = constants::SAMPLING_RATE;
+        // stereo sine that doesn't fit the loop a whole number of times, so a plain jump clicks
+        let samples: Vec<f32> = (0..sr as usize)
+            .flat_map(|n| {
+                let x = (2.0 * PI * 441.3 * n as f32 / sr).sin();
+                [x, -x]
+            })
+            .collect();
+        let step = 2.0 * PI * 441.3 / sr;
+
+        // biggest change between output frames going round the loop a few times
+        let max_step = |region: LoopRegion| {
+            let mut t = Transport::new(samples.len() / 2, sr);
+            t.apply(TransportCommand::Play);
+            t.loop_region = Some(region);
+            t.frame = region.start;
+            let (mut out, mut last) = ([0.0; 2], [0.0; 2]);
+            let mut max: f32 = 0.0;
+            for n in 0..3 * (region.end - region.start) {
+                t.advance().unwrap().read(&samples, &mut out);
+                if n > 0 {
+                    max = max.max((out[0] - last[0]).abs());
+                }
+                assert_eq!(out[0], -out[1]);
+                last = out;
+            }
+            max
+        };
+        let (start, end) = (10000, 20143);
+        assert!(max_step(LoopRegion::new(start, end, 0)) > 0.5);
+        let fade = (LOOP_FADE_MS / 1000.0 * sr) as usize;
+        let faded = LoopRegion::new(start, end, fade);
+        assert_eq!(faded.fade, fade);
+        let faded_step = max_step(faded);
+        assert!(faded_step < 2.0 * step, "{}", faded_step);
+
+        // the fade is as long as there is room for
+        assert_eq!(LoopRegion::new(100, 20000, fade).fade, 100);
+        let short = LoopRegion::new(1000, 1010, fade);
+        assert_eq!(short.fade, 5);
+        assert_eq!(short.blend(1004), None);
+        let (other, a, b) = short.blend(1009).unwrap();
+        assert_eq!(other, 999);
+        assert!(b > a && (a * a + b * b - 1.0).abs() < 1e-6);
+    }
+
+    #[test]
+    fn speed() {
+        use speed::Speed;
+        use std::f32::consts::PI;
+        let sr = constants::SAMPLING_RATE;
+        let len = 4 * sr as usize;
+        let f = 220.0;
+        let samples: Vec<f32> = (0..len)
+            .flat_map(|n| {
+                let x = 0.5 * (2.0 * PI * f * n as f32 / sr).sin();
+                [x, 0.5 * x]
+            })
+            .collect();
+
+        // frames taken from the file for each one played, and the pitch of what was played
+        let play = |rate: f32, mode: SpeedMode, frames: usize| {
+            let mut transport = transport::Transport::new(len, sr);
+            transport.apply(transport::TransportCommand::Play);
+            transport.apply(transport::TransportCommand::Speed(SpeedParams {
+                rate,
+                mode,
+            }));
+            let mut speed = Speed::new(2);
+            speed.sync(&transport);
+            let mut out = vec![];
+            let mut frame = [0.0; 2];
+            for _ in 0..frames {
+                let playing = speed.next(&mut frame, |x| {
+                    let Some(playhead) = transport.advance() else {
+                        return false;
+                    };
+                    playhead.read(&samples, x);
+                    true
+                });
+                assert!(playing);
+                // channels stay in step
+                assert!((frame[1] - 0.5 * frame[0]).abs() < 1e-6);
+                out.push(frame[0]);
+            }
+            // skip the fade in at the start
+            let settled = &out[2048..];
+            let crossings = settled
+                .windows(2)
+                .filter(|w| w[0] < 0.0 && w[1] >= 0.0)
+                .count();
+            let pitch = crossings as f32 / settled.len() as f32 * sr;
+            // segments line up in phase so they don't cancel
+            let peak = settled.iter().fold(0.0f32, |m, x| m.max(x.abs()));
+            assert!((peak - 0.5).abs() < 0.03, "{} {:?} {}", rate, mode, peak);
+            (transport.frame as f32 / frames as f32, pitch)
+        };
+
+        // at 1 it's the same frames
+        let (taken, pitch) = play(1.0, SpeedMode::PreservePitch, 20000);
+        assert_eq!(taken, 1.0);
+        assert!((pitch - f).abs() < 2.0, "{}", pitch);
+
+        for rate in [0.5, 1.5, 3.0] {
+            // the file goes by at the rate either way
+            let (taken, pitch) = play(rate, SpeedMode::Varispeed, 20000);
+            assert!((taken - rate).abs() < 0.01, "{} {}", rate, taken);
+            assert!(
+                (pitch - f * rate).abs() < 0.02 * f * rate,
+                "{} {}",
+                rate,
+                pitch
+            );
+
+            // the stretch keeps the pitch, it reads a couple of segments ahead
+            let (taken, pitch) = play(rate, SpeedMode::PreservePitch, 40000);
+            assert!(
+                (taken - rate).abs() < 2048.0 / 40000.0,
+                "{} {}",
+                rate,
+                taken
+            );
+            assert!((pitch - f).abs() < 0.02 * f, "{} {}", rate, pitch);
+        }
+    }
+
+    #[test]
+    fn mix() {
+        use rand::{Rng, SeedableRng};
+        let sr = constants::SAMPLING_RATE;
+        let mut rng = rand::rngs::StdRng::seed_from_u64(50);
+        let x: Vec<f32> = (0..3 * sr as usize)
+            .map(|_| rng.gen_range(-0.5..0.5))
+            .collect();
+
+        // something is taken out so wet and dry differ
+        let mut params = StereoParams::new();
+        params.left.ui_params.noise_gain = 1.0;
+        params.left.noise_spectrum = vec![0.2; params.left.sdft.size];
+        let latency = params.latency(0);
+        let fade = (mix::FADE_MS / 1000.0 * sr) as usize;
+        let bypass = sr as usize;
+        let half = 2 * sr as usize;
+
+        let mut out = [0.0];
+        let mut wet = 1.0f32;
+        for (n, s) in x.iter().enumerate() {
+            if n == bypass {
+                params.clean = true;
+            }
+            if n == half {
+                params.clean = false;
+                params.mix.params.wet = 0.5;
+            }
+            params.process_frame(&[*s], &mut out);
+            let processed = out[0];
+            params.monitor_frame(&[*s], &mut out);
+            let aligned = if n >= latency { x[n - latency] } else { 0.0 };
+
+            // a straight line over the fade, from wherever it was
+            let target = if n >= half {
+                0.5
+            } else if n >= bypass {
+                0.0
+            } else {
+                1.0
+            };
+            let step = 1.0 / fade as f32;
+            wet = if wet < target {
+                (wet + step).min(target)
+            } else {
+                (wet - step).max(target)
+            };
+            let expected = wet * processed + (1.0 - wet) * aligned;
+            assert!(
+                (out[0] - expected).abs() < 1e-4,
+                "{} {} {}",
+                n,
+                out[0],
+                expected
+            );
+
+            // exact at the ends, fully wet is the processing untouched
+            if n < bypass {
+                assert_eq!(out[0], processed);
+            }
+            if (bypass + fade..half).contains(&n) {
+                assert_eq!(out[0], aligned);
+            }
+        }
+        // the fade takes about FADE_MS
+        assert!((fade as f32 / sr * 1000.0 - 20.0).abs() < 0.1);
+    }
+
+    #[test]
+    fn ab_compare() {
+        use ab::{AbCompare, AbMessage, AbSlot};
+        use std::f32::consts::PI;
+        let sr = constants::SAMPLING_RATE;
+        let x: Vec<f32> = (0..4 * sr as usize)
+            .map(|n| 0.5 * (2.0 * PI * 100.0 * n as f32 / sr).sin())
+            .collect();
+
+        // b is 12 dB quieter and has something taken out
+        let a = StereoParams::new();
+        let mut b = StereoParams::new();
+        b.left.ui_params.output_gain = 0.25;
+        b.left.ui_params.noise_gain = 1.0;
+        b.left.noise_spectrum = vec![0.02; b.left.sdft.size];
+
+        let mut ab = AbCompare::new(1, sr);
+        // a set made for another rate doesn't go in
+        let mut other = StereoParams::new();
+        other.set_sample_rate(2.0 * sr);
+        let mut ignored = AbMessage::Store(AbSlot::B, Some(Box::new(other)));
+        ab.apply(&mut ignored);
+        assert!(matches!(ignored, AbMessage::Store(_, Some(_))));
+        ab.apply(&mut AbMessage::Store(AbSlot::A, Some(Box::new(a))));
+        ab.apply(&mut AbMessage::Enabled(true));
+        // nothing to compare with yet
+        assert!(!ab.is_active());
+        ab.apply(&mut AbMessage::Store(AbSlot::B, Some(Box::new(b))));
+        assert!(ab.is_active());
+
+        let rms = |y: &[f32]| (y.iter().map(|s| s * s).sum::<f32>() / y.len() as f32).sqrt();
+        let switch = 3 * sr as usize;
+        let mut out = vec![0.0; x.len()];
+        for (n, s) in x.iter().enumerate() {
+            if n == switch {
+                ab.apply(&mut AbMessage::Select(AbSlot::B));
+            }
+            let mut o = [0.0];
+            ab.process_frame(&[*s], &mut o);
+            out[n] = o[0];
+        }
+
+        // the louder one is turned down to the quieter
+        let gains = ab.gains();
+        assert!(gains[0] < 0.3 && gains[1] == 1.0, "{:?}", gains);
+        let before = rms(&out[switch - sr as usize / 2..switch]);
+        let after = rms(&out[switch + sr as usize / 2..]);
+        assert!((before / after - 1.0).abs() < 0.03, "{} {}", before, after);
+        assert!(after < 0.5 * 0.25);
+
+        // no step at the switch, nothing bigger than the sine moves in a frame
+        let slope = 0.5 * 2.0 * PI * 100.0 / sr;
+        let largest = out[switch - 100..switch + 1000]
+            .windows(2)
+            .fold(0.0f32, |m, w| m.max((w[1] - w[0]).abs()));
+        assert!(largest < 1.5 * slope, "{} {}", largest, slope);
     }
 }
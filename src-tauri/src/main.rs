@@ -3,19 +3,39 @@
 #![allow(non_snake_case)]
 // #![allow(dead_code)]
 use cpal::traits::StreamTrait;
-use denoiser::{audio::device_sample_rate, sql::create_db};
+use denoiser::{
+    audio::device_sample_rate,
+    sql::{create_db, update_db},
+};
 use std::{fs::File, sync::Mutex};
 use tauri::{AppHandle, Manager, State};
+mod ab;
 mod audio;
 use audio::*;
 mod types;
 use types::*;
+mod compare;
 mod constants;
+mod declick;
+mod declip;
+mod dehum;
 mod fourier;
+mod freq_scale;
+mod generator;
 use fourier::*;
 mod errors;
 mod messages;
+mod metering;
+mod metrics;
+mod mix;
+mod nlms;
+mod noise_regions;
+mod normalize;
+mod report;
 mod sdft;
+mod speed;
+mod transport;
+mod vad;
 use messages::*;
 mod file_io;
 mod settings;
@@ -31,22 +51,47 @@ fn main() {
             windows_explorer,
             play_stream,
             pause_stream,
+            stop_stream,
             get_stft_data,
             get_time_onefft,
             get_time_data,
+            get_vad_segments,
+            get_quality_metrics,
+            get_block_comparison,
+            generator::generate_test_signal,
             constants::get_num_filters,
             message_filters,
-            message_time,
+            message_seek,
             message_clean,
             message_output_gain,
             message_noise_gain,
             message_pre_smooth_gain,
             message_post_smooth_gain,
+            message_gain_smoothing,
+            message_spectral_gate,
+            message_adaptive_cancel,
+            message_stereo_mode,
+            message_hum,
+            message_declick,
+            message_vad,
+            message_declip,
             message_file_path,
-            message_left_mute,
-            message_right_mute,
-            message_loop_time,
+            message_mute,
+            message_loop_region,
+            message_end_action,
+            message_speed,
+            message_mix,
+            message_ab_store,
+            message_ab_select,
+            message_ab,
             message_fingerprint,
+            message_freq_scale,
+            message_audition,
+            message_compare,
+            message_ui_rate,
+            freq_scale::get_scale_frequencies,
+            freq_scale::get_noise_profile,
+            message_noise_regions,
             message_all,
             process_export,
             sql_theme,
@@ -60,9 +105,31 @@ fn main() {
             sql_update_output_gain,
             sql_update_pre_smooth_gain,
             sql_update_post_smooth_gain,
+            sql_gain_smoothing,
+            sql_update_gain_smoothing,
+            sql_spectral_gate,
+            sql_update_spectral_gate,
+            sql_adaptive_cancel,
+            sql_update_adaptive_cancel,
+            sql_stereo_mode,
+            sql_update_stereo_mode,
+            sql_normalize,
+            sql_update_normalize,
+            sql_ui_rate,
+            sql_update_ui_rate,
+            sql_mix,
+            sql_update_mix,
+            sql_hum,
+            sql_update_hum,
+            sql_declick,
+            sql_update_declick,
+            sql_vad,
+            sql_update_vad,
+            sql_declip,
+            sql_update_declip,
             sql_update_clean,
-            sql_update_left_mute,
-            sql_update_right_mute,
+            sql_mute,
+            sql_update_mute,
         ])
         .setup(|app| {
             let mainwindow = app.get_window("main").unwrap();
@@ -78,8 +145,9 @@ fn main() {
             let exists = std::fs::exists(&db_path);
             if exists.is_err() || !exists.unwrap() {
                 println!("CREATE DATABASE");
-                let _r = create_db(db_path);
+                let _r = create_db(db_path.clone());
             }
+            let _r = update_db(&db_path);
 
             // let c = ComponentColors::as_slice();
             // println!("{:?}", c);
@@ -103,7 +171,7 @@ fn main() {
 
             let mss = MStreamSend({
                 let (tx_ui, rx_ui) = tauri::async_runtime::channel::<AudioUIMessage>(2);
-                let (stream, tx) =
+                let (stream, tx, format) =
                     setup_stream(tx_ui.clone(), app_handle, None, window.clone()).unwrap();
                 let _ = stream.pause();
                 let mtx = Mutex::new(tx);
@@ -113,6 +181,7 @@ fn main() {
                     msender: MSender(mtx),
                     mreceiver: MUIReceiver(Mutex::new(rx_ui)),
                     mtx_ui: MAudioSender(Mutex::new(tx_ui)),
+                    format,
                 })
             });
 
@@ -120,6 +189,7 @@ fn main() {
             let _ = window.clone().emit("update_sampling_rate", sr);
 
             let _ = app.manage(mss);
+            let _ = app.manage(MSpectrogramCache(Mutex::new(Default::default())));
 
             Ok(())
         })
@@ -136,19 +206,18 @@ fn main() {
 #[tauri::command]
 fn play_stream(streamsend: State<MStreamSend>) {
     let _ = streamsend.0.lock().unwrap().stream.0.lock().unwrap().play();
+    transport_message(transport::TransportCommand::Play, streamsend);
 }
 
+/// the device keeps running so the position and events still come through
 #[tauri::command]
 fn pause_stream(streamsend: State<MStreamSend>) {
-    let _ = streamsend
-        .0
-        .lock()
-        .unwrap()
-        .stream
-        .0
-        .lock()
-        .unwrap()
-        .pause();
+    transport_message(transport::TransportCommand::Pause, streamsend);
+}
+
+#[tauri::command]
+fn stop_stream(streamsend: State<MStreamSend>) {
+    transport_message(transport::TransportCommand::Stop, streamsend);
 }
 
 #[tauri::command]
@@ -2,16 +2,30 @@
 use std::{path::PathBuf, str::FromStr, sync::Mutex};
 
 use crate::{
-    audio::{calculate_fingerprint, setup_stream},
-    constants::{czerov, from_log, NUM_FILTERS},
+    ab::{AbMessage, AbSlot},
+    audio::{calculate_fingerprint, get_wav_samples, playback_sample_rate, setup_stream},
+    compare::{Audition, ChannelComparison},
+    constants::{from_log, NUM_FILTERS},
+    declick::{ArScratch, Declicker},
+    declip::Clip,
+    dehum::{resolve_hum_params, Dehum},
+    file_io::{load_stereo_params, resolve_hums},
+    freq_scale::{saved_scale, SPECTRUM_POINTS},
+    metering::Metering,
+    nlms::Nlms,
+    noise_regions::{find_noise_regions, NoiseRegion},
+    report::{MAX_UI_RATE, MIN_UI_RATE},
+    settings::PlotScale,
+    speed::{MAX_RATE, MIN_RATE},
+    sql::{query_extra_channels, update_declip},
+    transport::{EndAction, Seek, TransportCommand},
     types::{
-        AudioParams, MSender, MStream, MStreamSend, MUIReceiver, StereoChoice, StereoParams, BPF,
-        IIR2,
+        AdaptiveCancel, AudioParams, DeclickParams, DeclipParams, GainSmoothing, HumFundamental,
+        HumParams, MSender, MStream, MStreamSend, MUIReceiver, MixParams, SpectralGate,
+        SpeedParams, StereoChoice, StereoModeParams, StereoParams, VadParams, BPF, IIR2,
     },
 };
 use cpal::traits::StreamTrait;
-use dasp_ring_buffer::Fixed;
-use rustfft::num_complex::Complex;
 use serde::{Deserialize, Serialize};
 use tauri::{AppHandle, Manager, State, Window};
 
@@ -23,8 +37,6 @@ pub async fn message_all(
     streamsend: State<'_, MStreamSend>,
     stereo_choice: StereoChoice,
     clean: bool,
-    left_mute: bool,
-    right_mute: bool,
     output_gain: f32,
     noise_gain: f32,
     pre_smooth_gain: f32,
@@ -36,8 +48,6 @@ pub async fn message_all(
         streamsend,
         Some(ChannelMessage {
             clean: Some(clean),
-            left_mute: Some(left_mute),
-            right_mute: Some(right_mute),
             output_gain: Some(from_log(output_gain)),
             noise_gain: Some(from_log(noise_gain)),
             pre_smooth_gain: Some(pre_smooth_gain),
@@ -82,9 +92,129 @@ pub fn message_filters(
     );
 }
 
-/// this only works after stream has started, because the time variable created when stream is setup...something like that? maybe it isn't, i forgot
+/// jump to a position, applied when the stream is running
 #[tauri::command]
-pub fn message_time(time: f32, streamsend: State<MStreamSend>) {
+pub fn message_seek(seek: Seek, streamsend: State<MStreamSend>) {
+    transport_message(TransportCommand::Seek(seek), streamsend);
+}
+
+/// start and end, in seconds or frames, None leaves the loop and plays on from where it is
+#[tauri::command]
+pub fn message_loop_region(region: Option<(Seek, Seek)>, streamsend: State<MStreamSend>) {
+    transport_message(TransportCommand::Loop(region), streamsend);
+}
+
+/// stop or start over when playback reaches the end of the file
+#[tauri::command]
+pub fn message_end_action(end_action: EndAction, streamsend: State<MStreamSend>) {
+    transport_message(TransportCommand::EndAction(end_action), streamsend);
+}
+
+/// playback rate, clamped to the range the stretch is made for
+#[tauri::command]
+pub fn message_speed(speed: SpeedParams, streamsend: State<MStreamSend>) {
+    let speed = SpeedParams {
+        rate: speed.rate.clamp(MIN_RATE, MAX_RATE),
+        ..speed
+    };
+    transport_message(TransportCommand::Speed(speed), streamsend);
+}
+
+/// wet/dry balance, changes are crossfaded so the slider can be dragged while playing
+#[tauri::command]
+pub fn message_mix(mix: MixParams, streamsend: State<MStreamSend>) {
+    let mix = MixParams {
+        wet: mix.wet.clamp(0.0, 1.0),
+    };
+    let _ = streamsend
+        .0
+        .lock()
+        .unwrap()
+        .msender
+        .0
+        .lock()
+        .unwrap()
+        .try_send(UIAudioMessage {
+            mix: Some(mix),
+            ..Default::default()
+        });
+}
+
+/// store the saved params as set a or b, built here from the db so the callback only swaps them in
+/// auto hum is found from the file the same way export does, so that runs off the command thread
+/// the file is only read when a channel needs it
+#[tauri::command]
+pub async fn message_ab_store(
+    slot: AbSlot,
+    file_path: String,
+    stereo_choice: StereoChoice,
+    app_handle: AppHandle,
+    streamsend: State<'_, MStreamSend>,
+) -> Result<(), String> {
+    let p = PathBuf::from_str(file_path.as_str()).map_err(|_| "bad path".to_string())?;
+    if !p.is_file() {
+        return Err("file not found".to_string());
+    }
+    let db_path = app_handle
+        .path_resolver()
+        .app_local_data_dir()
+        .expect("app local data dir should exist")
+        .join("db.sqlite");
+    // built for the stream it's going to, a set that doesn't match it is never played
+    let format = streamsend.0.lock().unwrap().format;
+
+    let thread = tauri::async_runtime::spawn(async move {
+        let mut set = load_stereo_params(
+            format.num_channels,
+            format.sample_rate,
+            stereo_choice,
+            &db_path,
+        );
+        let auto_hum = (0..set.num_channels).any(|i| {
+            let hum = set.channel(i).dehum.params;
+            hum.enabled && hum.fundamental == HumFundamental::Auto
+        });
+        if auto_hum {
+            let (samples, _num_channels) = get_wav_samples(p);
+            resolve_hums(&mut set, &samples);
+        }
+        set
+    });
+    let set = thread
+        .await
+        .map_err(|_| "failed to store the set".to_string())?;
+    ab_message(AbMessage::Store(slot, Some(Box::new(set))), streamsend);
+    Ok(())
+}
+
+/// which stored set plays, the switch is crossfaded
+#[tauri::command]
+pub fn message_ab_select(slot: AbSlot, streamsend: State<MStreamSend>) {
+    ab_message(AbMessage::Select(slot), streamsend);
+}
+
+/// play the stored sets instead of the live params, needs both stored
+#[tauri::command]
+pub fn message_ab(enabled: bool, streamsend: State<MStreamSend>) {
+    ab_message(AbMessage::Enabled(enabled), streamsend);
+}
+
+fn ab_message(message: AbMessage, streamsend: State<MStreamSend>) {
+    let _ = streamsend
+        .0
+        .lock()
+        .unwrap()
+        .msender
+        .0
+        .lock()
+        .unwrap()
+        .try_send(UIAudioMessage {
+            ab: Some(message),
+            ..Default::default()
+        });
+}
+
+pub fn transport_message(command: TransportCommand, streamsend: State<MStreamSend>) {
     let _ = streamsend
         .0
         .lock()
@@ -94,14 +224,14 @@ pub fn message_time(time: f32, streamsend: State<MStreamSend>) {
         .lock()
         .unwrap()
         .try_send(UIAudioMessage {
-            time: Some(time),
-            is_looping: Some(false),
+            transport: Some(command),
             ..Default::default()
         });
 }
 
+/// not a channel message, the canceller works on both channels at once
 #[tauri::command]
-pub fn message_loop_time(loop_time: usize, loop_length: usize, streamsend: State<MStreamSend>) {
+pub fn message_adaptive_cancel(adaptive_cancel: AdaptiveCancel, streamsend: State<MStreamSend>) {
     let _ = streamsend
         .0
         .lock()
@@ -111,12 +241,96 @@ pub fn message_loop_time(loop_time: usize, loop_length: usize, streamsend: State
         .lock()
         .unwrap()
         .try_send(UIAudioMessage {
-            loop_start_time: Some(loop_time),
-            loop_length: Some(loop_length),
-            is_looping: Some(true),
+            adaptive_cancel: Some(Nlms::new(adaptive_cancel)),
             ..Default::default()
         });
 }
+
+/// the ui loads the new mode's params and sends them with message_all after this
+#[tauri::command]
+pub fn message_stereo_mode(stereo_mode: StereoModeParams, streamsend: State<MStreamSend>) {
+    let _ = streamsend
+        .0
+        .lock()
+        .unwrap()
+        .msender
+        .0
+        .lock()
+        .unwrap()
+        .try_send(UIAudioMessage {
+            stereo_mode: Some(stereo_mode),
+            ..Default::default()
+        });
+}
+
+/// the spectrum in AudioUIMessage comes mapped to this scale and number of points
+#[tauri::command]
+pub fn message_freq_scale(scale: PlotScale, points: usize, streamsend: State<MStreamSend>) {
+    let _ = streamsend
+        .0
+        .lock()
+        .unwrap()
+        .msender
+        .0
+        .lock()
+        .unwrap()
+        .try_send(UIAudioMessage {
+            freq_scale: Some((scale, points.max(1))),
+            ..Default::default()
+        });
+}
+
+/// play the output, the lined up input or only what's being removed
+#[tauri::command]
+pub fn message_audition(audition: Audition, streamsend: State<MStreamSend>) {
+    let _ = streamsend
+        .0
+        .lock()
+        .unwrap()
+        .msender
+        .0
+        .lock()
+        .unwrap()
+        .try_send(UIAudioMessage {
+            audition: Some(audition),
+            ..Default::default()
+        });
+}
+
+#[tauri::command]
+pub fn message_compare(enabled: bool, streamsend: State<MStreamSend>) {
+    let _ = streamsend
+        .0
+        .lock()
+        .unwrap()
+        .msender
+        .0
+        .lock()
+        .unwrap()
+        .try_send(UIAudioMessage {
+            compare: Some(enabled),
+            ..Default::default()
+        });
+}
+
+/// Hz, clamped to what the ui can reasonably draw
+#[tauri::command]
+pub fn message_ui_rate(rate: f32, streamsend: State<MStreamSend>) {
+    let _ = streamsend
+        .0
+        .lock()
+        .unwrap()
+        .msender
+        .0
+        .lock()
+        .unwrap()
+        .try_send(UIAudioMessage {
+            ui_rate: Some(rate.clamp(MIN_UI_RATE, MAX_UI_RATE)),
+            ..Default::default()
+        });
+}
+
+/// scale and points default to the saved plot scale
 #[tauri::command]
 pub fn message_fingerprint(
     streamsend: State<MStreamSend>,
@@ -124,6 +338,8 @@ pub fn message_fingerprint(
     len: usize,
     app_handle: AppHandle,
     file_name: &str,
+    scale: Option<PlotScale>,
+    points: Option<usize>,
 ) {
     let file = app_handle
         .path_resolver()
@@ -156,40 +372,87 @@ pub fn message_fingerprint(
         .lock()
         .unwrap()
         .pause();
+    let scale = scale.unwrap_or_else(|| saved_scale(&app_handle));
     let w = app_handle.get_window("main").unwrap();
-    calculate_fingerprint(file, start, len, w);
+    calculate_fingerprint(
+        file,
+        start,
+        len,
+        scale,
+        points.unwrap_or(SPECTRUM_POINTS),
+        w,
+    );
 }
 
+/// ranked noise-only regions of the file, so the user doesn't have to find one by hand
+/// with learn the best region is fingerprinted right away, same as message_fingerprint on it
+/// the scan reads the whole file, so it runs off the command thread
 #[tauri::command]
-pub fn message_clean(clean: bool, streamsend: State<MStreamSend>, stereo_choice: StereoChoice) {
-    stereo_message(
-        stereo_choice,
-        streamsend,
-        Some(ChannelMessage {
-            clean: Some(clean),
-            ..Default::default()
-        }),
-    );
+pub async fn message_noise_regions(
+    streamsend: State<'_, MStreamSend>,
+    max_regions: usize,
+    learn: bool,
+    app_handle: AppHandle,
+    file_name: &str,
+) -> Result<Vec<NoiseRegion>, String> {
+    let file = app_handle
+        .path_resolver()
+        .resource_dir()
+        .expect("failed to open resource dir")
+        .join("assets")
+        .join(file_name);
+    if !file.is_file() {
+        return Ok(vec![]);
+    }
+    if learn {
+        let _ = streamsend
+            .0
+            .lock()
+            .unwrap()
+            .stream
+            .0
+            .lock()
+            .unwrap()
+            .pause();
+    }
+    let scale = saved_scale(&app_handle);
+    let w = app_handle.get_window("main").unwrap();
+
+    let thread = tauri::async_runtime::spawn(async move {
+        let (samples, num_channels) = get_wav_samples(file.clone());
+        let regions =
+            find_noise_regions(&samples, num_channels, playback_sample_rate(), max_regions);
+        if learn {
+            if let Some(best) = regions.first() {
+                calculate_fingerprint(file, best.start, best.len, scale, SPECTRUM_POINTS, w);
+            }
+        }
+        regions
+    });
+    thread
+        .await
+        .map_err(|_| "failed to find noise regions".to_string())
 }
+
 #[tauri::command]
-pub fn message_left_mute(mute: bool, streamsend: State<MStreamSend>, stereo_choice: StereoChoice) {
+pub fn message_clean(clean: bool, streamsend: State<MStreamSend>, stereo_choice: StereoChoice) {
     stereo_message(
         stereo_choice,
         streamsend,
         Some(ChannelMessage {
-            left_mute: Some(mute),
+            clean: Some(clean),
             ..Default::default()
         }),
     );
 }
-
+/// stereo_choice is the channel that's muted, both mutes all of them
 #[tauri::command]
-pub fn message_right_mute(mute: bool, streamsend: State<MStreamSend>, stereo_choice: StereoChoice) {
+pub fn message_mute(mute: bool, streamsend: State<MStreamSend>, stereo_choice: StereoChoice) {
     stereo_message(
         stereo_choice,
         streamsend,
         Some(ChannelMessage {
-            right_mute: Some(mute),
+            mute: Some(mute),
             ..Default::default()
         }),
     );
@@ -254,6 +517,135 @@ pub fn message_post_smooth_gain(
     );
 }
 
+#[tauri::command]
+pub fn message_gain_smoothing(
+    gain_smoothing: GainSmoothing,
+    streamsend: State<MStreamSend>,
+    stereo_choice: StereoChoice,
+) {
+    stereo_message(
+        stereo_choice,
+        streamsend,
+        Some(ChannelMessage {
+            gain_smoothing: Some(gain_smoothing),
+            ..Default::default()
+        }),
+    );
+}
+
+#[tauri::command]
+pub fn message_spectral_gate(
+    spectral_gate: SpectralGate,
+    streamsend: State<MStreamSend>,
+    stereo_choice: StereoChoice,
+) {
+    stereo_message(
+        stereo_choice,
+        streamsend,
+        Some(ChannelMessage {
+            spectral_gate: Some(spectral_gate),
+            ..Default::default()
+        }),
+    );
+}
+
+/// auto fundamental is detected from the file before sending, returns the params with the detected frequency
+/// detection reads the whole file, so it runs off the command thread
+#[tauri::command]
+pub async fn message_hum(
+    hum_params: HumParams,
+    file_path: String,
+    streamsend: State<'_, MStreamSend>,
+    stereo_choice: StereoChoice,
+) -> Result<HumParams, String> {
+    let p = PathBuf::from_str(file_path.as_str()).map_err(|_| "bad path".to_string())?;
+    let hum_params = if hum_params.fundamental == HumFundamental::Auto && p.is_file() {
+        let thread = tauri::async_runtime::spawn(async move {
+            let (samples, num_channels) = get_wav_samples(p);
+            // left channel is enough to find the fundamental
+            let channel: Vec<f32> = samples.iter().step_by(num_channels).cloned().collect();
+            resolve_hum_params(hum_params, &channel, playback_sample_rate())
+        });
+        thread
+            .await
+            .map_err(|_| "failed to find the hum".to_string())?
+    } else {
+        hum_params
+    };
+
+    stereo_message(
+        stereo_choice,
+        streamsend,
+        Some(ChannelMessage {
+            hum: Some(hum_params),
+            ..Default::default()
+        }),
+    );
+    Ok(hum_params)
+}
+
+#[tauri::command]
+pub fn message_declick(
+    declick_params: DeclickParams,
+    streamsend: State<MStreamSend>,
+    stereo_choice: StereoChoice,
+) {
+    stereo_message(
+        stereo_choice,
+        streamsend,
+        Some(ChannelMessage {
+            declick: Some(declick_params),
+            ..Default::default()
+        }),
+    );
+}
+
+#[tauri::command]
+pub fn message_vad(
+    vad_params: VadParams,
+    streamsend: State<MStreamSend>,
+    stereo_choice: StereoChoice,
+) {
+    stereo_message(
+        stereo_choice,
+        streamsend,
+        Some(ChannelMessage {
+            vad: Some(vad_params),
+            ..Default::default()
+        }),
+    );
+}
+
+/// declipping works on the whole file before playback, so the params are saved and the stream is remade from them
+#[tauri::command]
+pub fn message_declip(
+    declip_params: DeclipParams,
+    stereo_choice: StereoChoice,
+    path: String,
+    streamsend: State<MStreamSend>,
+    app_handle: AppHandle,
+    window: Window,
+) {
+    let db_path = app_handle
+        .path_resolver()
+        .app_local_data_dir()
+        .expect("app local data dir should exist")
+        .join("db.sqlite");
+    // the stream reads every channel's own rows, both is kept in sync for export
+    let choices = match stereo_choice {
+        StereoChoice::Both => [StereoChoice::Left, StereoChoice::Right, StereoChoice::Both]
+            .into_iter()
+            .chain(query_extra_channels(&db_path).unwrap_or_default())
+            .collect(),
+        c => vec![c],
+    };
+    for c in choices {
+        let _ = update_declip(c, declip_params, db_path.clone());
+    }
+
+    message_file_path(path, streamsend, app_handle, window);
+}
+
 #[tauri::command]
 pub fn message_file_path(
     path: String,
@@ -272,7 +664,7 @@ pub fn message_file_path(
         .pause();
     let (ui_tx, rx) = tauri::async_runtime::channel::<AudioUIMessage>(2);
     let path = PathBuf::from_str(path.as_str()).expect("bad path");
-    let (stream, tx) = setup_stream(ui_tx, app_handle, Some(path), window).unwrap();
+    let (stream, tx, format) = setup_stream(ui_tx, app_handle, Some(path), window).unwrap();
     let _ = stream.pause();
     let mtx = Mutex::new(tx);
 
@@ -280,75 +672,90 @@ pub fn message_file_path(
     ss.stream = MStream(Mutex::new(stream));
     ss.msender = MSender(mtx);
     ss.mreceiver = MUIReceiver(Mutex::new(rx));
+    ss.format = format;
 }
 
 /// the channel message is applied to left, right, or both
+/// anything it needs allocated is built here for each channel it reaches, so the callback only swaps it in
 fn stereo_message(
     stereo_choice: StereoChoice,
     streamsend: State<MStreamSend>,
     channel_message: Option<ChannelMessage>,
 ) {
     use StereoChoice::*;
-    match stereo_choice {
-        Left => {
-            let _ = streamsend
-                .0
-                .lock()
-                .unwrap()
-                .msender
-                .0
-                .lock()
-                .unwrap()
-                .try_send(UIAudioMessage {
-                    left_channel: channel_message,
-                    ..Default::default()
-                });
-        }
-        Right => {
-            let _ = streamsend
-                .0
-                .lock()
-                .unwrap()
-                .msender
-                .0
-                .lock()
-                .unwrap()
-                .try_send(UIAudioMessage {
-                    right_channel: channel_message,
-                    ..Default::default()
-                });
-        }
-        Both => {
-            let _ = streamsend
-                .0
-                .lock()
-                .unwrap()
-                .msender
-                .0
-                .lock()
-                .unwrap()
-                .try_send(UIAudioMessage {
-                    left_channel: channel_message,
-                    right_channel: channel_message,
-                    ..Default::default()
-                });
-        }
+    let ss = streamsend.0.lock().unwrap();
+    let mut msg = match stereo_choice {
+        Left => UIAudioMessage {
+            left_channel: channel_message,
+            ..Default::default()
+        },
+        Right => UIAudioMessage {
+            right_channel: channel_message,
+            ..Default::default()
+        },
+        Both => UIAudioMessage {
+            left_channel: channel_message,
+            right_channel: channel_message,
+            extra_channels: channel_message,
+            ..Default::default()
+        },
+        Channel(i) => UIAudioMessage {
+            channel: channel_message.map(|m| (i, m)),
+            ..Default::default()
+        },
     };
+    if let Some(m) = channel_message.filter(|m| m.hum.is_some() || m.declick.is_some()) {
+        let channels = match stereo_choice {
+            Left => 0..1,
+            Right => 1..2,
+            Both => 0..ss.format.num_channels,
+            Channel(i) => i..i + 1,
+        };
+        msg.buffers = channels
+            .map(|i| (i, ChannelBuffers::new(&m, ss.format.sample_rate)))
+            .collect();
+    }
+    let _ = ss.msender.0.lock().unwrap().try_send(msg);
 }
 
-/// a message from a single channel (left or right)
+/// built on the sending side for one channel and swapped in by the callback
+/// what they replaced is left here and freed with the message off the audio thread
+#[derive(Clone, Debug, Default)]
+pub struct ChannelBuffers {
+    /// hum notches for the new params
+    pub comb: Option<Vec<IIR2>>,
+    /// room for the longest click the new params repair
+    pub scratch: Option<ArScratch>,
+}
+
+impl ChannelBuffers {
+    pub fn new(channel_message: &ChannelMessage, sample_rate: f32) -> Self {
+        Self {
+            comb: channel_message.hum.map(|h| Dehum::comb(h, sample_rate)),
+            scratch: channel_message
+                .declick
+                .map(|d| Declicker::scratch(d, sample_rate)),
+        }
+    }
+}
+
+/// a message for a single channel, the left and right ones or any past the first two
 #[derive(Clone, Debug, Copy)]
 pub struct ChannelMessage {
     pub time: Option<f32>,
     pub loop_length: Option<f32>,
     pub clean: Option<bool>,
-    pub left_mute: Option<bool>,
-    pub right_mute: Option<bool>,
+    pub mute: Option<bool>,
     pub output_gain: Option<f32>,
     pub noise_gain: Option<f32>,
     pub pre_smooth_gain: Option<f32>,
     pub post_smooth_gain: Option<f32>,
     pub filters: Option<[Option<BPF>; NUM_FILTERS]>,
+    pub gain_smoothing: Option<GainSmoothing>,
+    pub spectral_gate: Option<SpectralGate>,
+    pub hum: Option<HumParams>,
+    pub declick: Option<DeclickParams>,
+    pub vad: Option<VadParams>,
 }
 
 impl Default for ChannelMessage {
@@ -358,13 +765,17 @@ impl Default for ChannelMessage {
             time: None,
             loop_length: None,
             clean: None,
-            left_mute: None,
-            right_mute: None,
+            mute: None,
             output_gain: None,
             noise_gain: None,
             pre_smooth_gain: None,
             post_smooth_gain: None,
             filters: None,
+            gain_smoothing: None,
+            spectral_gate: None,
+            hum: None,
+            declick: None,
+            vad: None,
         }
     }
 }
@@ -375,115 +786,146 @@ impl Default for ChannelMessage {
 pub struct UIAudioMessage {
     pub left_channel: Option<ChannelMessage>,
     pub right_channel: Option<ChannelMessage>,
+    /// every channel past the first two, sent along with left and right for both
+    pub extra_channels: Option<ChannelMessage>,
+    /// one channel past the first two, by its index in the file
+    pub channel: Option<(usize, ChannelMessage)>,
+    /// by channel index, for the channels the channel messages reach
+    pub buffers: Vec<(usize, ChannelBuffers)>,
     pub file_path: Option<PathBuf>,
     pub stereo_choice: Option<StereoChoice>,
     pub clean: Option<bool>,
     pub export: Option<bool>,
-    pub time: Option<f32>,
-    pub loop_start_time: Option<usize>,
-    pub loop_length: Option<usize>,
-    pub is_looping: Option<bool>,
+    pub transport: Option<TransportCommand>,
     // don't really need to send bool, it is true when message is received?
     pub fingerprint: Option<bool>,
     pub start_fingerprint: Option<usize>,
     pub length_fingerprint: Option<usize>,
+    /// built from the new params here, the callback only swaps it in if the filter length changed
+    pub adaptive_cancel: Option<Nlms>,
+    pub stereo_mode: Option<StereoModeParams>,
+    /// scale and number of points of the emitted spectrum
+    pub freq_scale: Option<(PlotScale, usize)>,
+    pub audition: Option<Audition>,
+    /// send the input, output and residual of every channel with each audio message
+    pub compare: Option<bool>,
+    /// Hz, how often audio messages are sent
+    pub ui_rate: Option<f32>,
+    pub mix: Option<MixParams>,
+    /// taken out by the callback, the compare sits beside the params rather than in them
+    pub ab: Option<AbMessage>,
 }
 
 // use all None for default message to shorten other functions that send one thing at a time
 impl Default for UIAudioMessage {
     fn default() -> Self {
         Self {
-            time: None,
+            transport: None,
             file_path: None,
             clean: None,
             stereo_choice: None,
             left_channel: None,
             right_channel: None,
+            extra_channels: None,
+            channel: None,
+            buffers: vec![],
             export: None,
-            loop_start_time: None,
-            loop_length: None,
-            is_looping: None,
             fingerprint: None,
             start_fingerprint: None,
             length_fingerprint: None,
+            adaptive_cancel: None,
+            stereo_mode: None,
+            freq_scale: None,
+            audition: None,
+            compare: None,
+            ui_rate: None,
+            mix: None,
+            ab: None,
         }
     }
 }
 
 impl UIAudioMessage {
-    pub fn receive(&self, params: &mut StereoParams) {
+    /// nothing here allocates or frees, whatever is replaced is left in the message for the caller to free
+    pub fn receive(&mut self, params: &mut StereoParams) {
         // apply controls to channels
         use StereoChoice::*;
+        let buffers = &mut self.buffers;
         match params.stereo_choice {
             Left => {
                 if let Some(ch) = self.left_channel {
-                    self.recv_channel(&mut params.left, ch);
+                    Self::recv_channel(&mut params.left, ch, channel_buffers(buffers, 0));
                     if let Some(c) = ch.clean {
                         params.clean = c;
-                        params.left.sdft.freq_history = czerov(params.left.dft_size);
-                        params.left.sdft.time_history =
-                            Fixed::from(vec![Complex::new(0.0, 0.0); params.left.dft_size]);
                     }
                 }
             }
             Right => {
                 if let Some(ch) = self.right_channel {
-                    self.recv_channel(&mut params.right, ch);
+                    Self::recv_channel(&mut params.right, ch, channel_buffers(buffers, 1));
                     if let Some(c) = ch.clean {
                         params.clean = c;
-                        params.right.sdft.freq_history = czerov(params.right.dft_size);
-                        params.right.sdft.time_history =
-                            Fixed::from(vec![Complex::new(0.0, 0.0); params.right.dft_size]);
                     }
                 }
             }
             Both => {
                 if let Some(ch) = self.left_channel {
-                    self.recv_channel(&mut params.left, ch);
+                    Self::recv_channel(&mut params.left, ch, channel_buffers(buffers, 0));
                     if let Some(c) = ch.clean {
                         params.clean = c;
-                        params.left.sdft.freq_history = czerov(params.left.dft_size);
-                        params.left.sdft.time_history =
-                            Fixed::from(vec![Complex::new(0.0, 0.0); params.left.dft_size]);
                     }
                 }
                 if let Some(ch) = self.right_channel {
-                    self.recv_channel(&mut params.right, ch);
+                    Self::recv_channel(&mut params.right, ch, channel_buffers(buffers, 1));
                     if let Some(c) = ch.clean {
                         params.clean = c;
-                        params.right.sdft.freq_history = czerov(params.right.dft_size);
-                        params.right.sdft.time_history =
-                            Fixed::from(vec![Complex::new(0.0, 0.0); params.right.dft_size]);
                     }
                 }
+                if let Some(ch) = self.extra_channels {
+                    for (i, extra) in params.extra.iter_mut().enumerate() {
+                        Self::recv_channel(extra, ch, channel_buffers(buffers, i + 2));
+                    }
+                }
+            }
+            Channel(_) => {}
+        }
+        if let Some((i, ch)) = self.channel {
+            if let Some(extra) = i.checked_sub(2).and_then(|i| params.extra.get_mut(i)) {
+                Self::recv_channel(extra, ch, channel_buffers(buffers, i));
+            }
+            if let Some(c) = ch.clean {
+                params.clean = c;
             }
         }
 
-        // this is where time is handled differently? was there a reason? forgot...
-        if let Some(t) = self.time {
-            params.time = (t) as usize;
-            if params.is_stereo {
-                params.left.sdft.freq_history = czerov(params.left.dft_size);
-                params.left.sdft.time_history =
-                    Fixed::from(vec![Complex::new(0.0, 0.0); params.left.dft_size]);
-                params.right.sdft.freq_history = czerov(params.right.dft_size);
-                params.right.sdft.time_history =
-                    Fixed::from(vec![Complex::new(0.0, 0.0); params.right.dft_size]);
-            } else {
-                params.left.sdft.freq_history = czerov(params.left.dft_size);
-                params.left.sdft.time_history =
-                    Fixed::from(vec![Complex::new(0.0, 0.0); params.left.dft_size]);
+        if let Some(t) = self.transport {
+            if params.transport.apply(t) {
+                params.reset();
             }
         }
-        if let Some(t) = self.loop_start_time {
-            params.loop_start_time = t;
-            params.time = t;
+        if let Some(a) = self.adaptive_cancel.as_mut() {
+            params.canceller.set_params(a);
+        }
+        if let Some(f) = self.freq_scale {
+            params.freq_scale = f;
+        }
+        if let Some(r) = self.ui_rate {
+            params.ui_rate = r;
+        }
+        if let Some(m) = self.mix {
+            params.mix.params = m;
         }
-        if let Some(t) = self.loop_length {
-            params.loop_length = t;
+        if let Some(a) = self.audition {
+            params.monitor.audition = a;
         }
-        if let Some(t) = self.is_looping {
-            params.is_looping = t;
+        if let Some(c) = self.compare {
+            params.monitor.compare = c;
+        }
+        if let Some(m) = self.stereo_mode {
+            if m.mode != params.stereo_mode.mode {
+                params.reset();
+            }
+            params.stereo_mode = m;
         }
         if self.fingerprint.is_some()
             && self.file_path.is_some()
@@ -498,23 +940,26 @@ impl UIAudioMessage {
         }
     }
 
-    pub fn recv_channel(&self, channel_params: &mut AudioParams, channel_message: ChannelMessage) {
+    /// without buffers the comb and scratch are built here, which allocates
+    pub fn recv_channel(
+        channel_params: &mut AudioParams,
+        channel_message: ChannelMessage,
+        buffers: Option<&mut ChannelBuffers>,
+    ) {
         if let Some(msg) = channel_message.filters {
             for (i, filter) in msg.iter().enumerate() {
                 if let Some(f) = filter {
                     let iir: IIR2 = Into::<IIR2>::into(*f);
                     channel_params.filters.bank[i].update_coeffs(iir);
-                    channel_params.noise_spectrum = channel_params
-                        .filters
-                        .parallel_transfer(channel_params.dft_size);
                 }
             }
+            // the rest of the bank is only known here, so the spectrum is redone in place
+            channel_params
+                .filters
+                .parallel_transfer_into(&mut channel_params.noise_spectrum);
         }
-        if let Some(m) = channel_message.left_mute {
-            channel_params.ui_params.left_mute = m;
-        }
-        if let Some(m) = channel_message.right_mute {
-            channel_params.ui_params.right_mute = m;
+        if let Some(m) = channel_message.mute {
+            channel_params.ui_params.mute = m;
         }
         if let Some(g) = channel_message.output_gain {
             channel_params.ui_params.output_gain = g;
@@ -528,17 +973,66 @@ impl UIAudioMessage {
         if let Some(g) = channel_message.post_smooth_gain {
             channel_params.ui_params.post_smooth_gain = g;
         }
+        if let Some(g) = channel_message.gain_smoothing {
+            // switching between complex and gain smoothing leaves stale history in the sdft
+            if g.enabled != channel_params.gain_smoothing.enabled {
+                channel_params.sdft.reset();
+            }
+            channel_params.gain_smoothing = g;
+        }
+        if let Some(g) = channel_message.spectral_gate {
+            // gate shares the gain history with gain smoothing
+            if g.enabled != channel_params.spectral_gate.enabled {
+                channel_params.sdft.reset();
+            }
+            channel_params.spectral_gate = g;
+        }
+        let sample_rate = channel_params.sample_rate;
+        let mut own = ChannelBuffers::default();
+        let buffers = buffers.unwrap_or(&mut own);
+        if let Some(h) = channel_message.hum {
+            let comb = buffers
+                .comb
+                .get_or_insert_with(|| Dehum::comb(h, sample_rate));
+            channel_params.dehum.set_params(h, comb);
+        }
+        if let Some(d) = channel_message.declick {
+            // don't play out whatever was buffered before it was switched off
+            if d.enabled != channel_params.declicker.params.enabled {
+                channel_params.declicker.reset();
+            }
+            let scratch = buffers
+                .scratch
+                .get_or_insert_with(|| Declicker::scratch(d, sample_rate));
+            channel_params.declicker.set_params(d, scratch);
+        }
+        if let Some(v) = channel_message.vad {
+            channel_params.vad.set_params(v);
+        }
     }
 }
 
+/// buffers built for channel i, if the message has any
+fn channel_buffers(
+    buffers: &mut [(usize, ChannelBuffers)],
+    i: usize,
+) -> Option<&mut ChannelBuffers> {
+    buffers.iter_mut().find(|(c, _)| *c == i).map(|(_, b)| b)
+}
+
 /// message sent from audio thread to ui
 #[derive(Clone, Debug, Serialize, Deserialize, Default)]
 pub struct AudioUIMessage {
     pub spectrum: Option<Vec<f32>>,
-    pub is_stereo: Option<bool>,
+    pub num_channels: Option<usize>,
+    /// voice activity of the left channel, only sent when the detector is on
+    pub speech: Option<bool>,
+    /// one per channel, only sent when the comparison is on
+    pub comparison: Option<Vec<ChannelComparison>>,
+    /// levels of the output, sent every METER_INTERVAL
+    pub meter: Option<Metering>,
     pub is_processing: Option<bool>,
     pub processing_percentage: Option<f32>,
-    pub time: Option<f32>,
 }
 
 impl AudioUIMessage {
@@ -558,3 +1052,16 @@ impl FingerprintMessage {
         "fingerprint_message"
     }
 }
+
+/// clipped runs found when a file is loaded, for marking on the time plot
+#[derive(Clone, Debug, Serialize, Deserialize, Default)]
+pub struct ClipMessage {
+    pub count: usize,
+    pub clips: Vec<Clip>,
+}
+
+impl ClipMessage {
+    pub fn name() -> &'static str {
+        "clip_message"
+    }
+}
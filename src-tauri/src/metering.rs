@@ -0,0 +1,346 @@
+use serde::{Deserialize, Serialize};
+use std::{collections::VecDeque, f64::consts::PI};
+use ts_rs::TS;
+
+use crate::types::IIR2;
+
+/// dB, what silence reads as, infinities don't survive json
+pub const METER_FLOOR: f32 = -120.0;
+/// s, loudness is summed in blocks this long, gating blocks and windows are made of them
+const SUB_BLOCK: f32 = 0.1;
+/// sub-blocks in the momentary (400 ms) and short-term (3 s) windows
+const MOMENTARY_BLOCKS: usize = 4;
+const SHORT_TERM_BLOCKS: usize = 30;
+/// LUFS, gating blocks below this never count toward integrated loudness
+const ABSOLUTE_GATE: f32 = -70.0;
+/// LU below the ungated loudness where the relative gate sits
+const RELATIVE_GATE: f32 = 10.0;
+/// LU per bin of the gating block histogram, blocks are gated by bin so this is the gate's resolution
+const HISTOGRAM_STEP: f32 = 0.01;
+/// bins from the absolute gate up to +10 LUFS, louder blocks go in the last one
+const HISTOGRAM_BINS: usize = 8000;
+/// true peak oversampling and taps per phase of the interpolator
+const OVERSAMPLING: usize = 4;
+const TAPS_PER_PHASE: usize = 12;
+/// s, how often the meter is ready to send to the ui
+pub const METER_INTERVAL: f32 = 0.1;
+
+/// levels of the output, peak and rms are since the last reading, loudness is EBU R128
+#[derive(Clone, Debug, Default, Serialize, Deserialize, PartialEq, TS)]
+#[ts(export)]
+pub struct Metering {
+    /// dBFS, per channel
+    pub peak: Vec<f32>,
+    /// dBFS, per channel
+    pub rms: Vec<f32>,
+    /// dBTP, per channel, peak of the 4x oversampled signal
+    pub true_peak: Vec<f32>,
+    /// LUFS, last 400 ms
+    pub momentary: f32,
+    /// LUFS, last 3 s
+    pub short_term: f32,
+    /// LUFS, gated over everything measured
+    pub integrated: f32,
+}
+
+pub fn to_db(amplitude: f32) -> f32 {
+    if amplitude > 0.0 {
+        (20.0 * amplitude.log10()).max(METER_FLOOR)
+    } else {
+        METER_FLOOR
+    }
+}
+
+/// BS.1770 loudness of a weighted mean square
+fn loudness(mean_square: f64) -> f32 {
+    if mean_square > 0.0 {
+        ((-0.691 + 10.0 * mean_square.log10()) as f32).max(METER_FLOOR)
+    } else {
+        METER_FLOOR
+    }
+}
+
+/// BS.1770 channel weights, surrounds count more and the LFE of 5.1 is left out
+fn channel_weight(channel: usize, num_channels: usize) -> f64 {
+    match (num_channels, channel) {
+        (6, 3) => 0.0,
+        (6, 4) | (6, 5) => 1.41,
+        _ => 1.0,
+    }
+}
+
+/// K-weighting as two biquads, the head shelf then the RLB high pass, for any sample rate
+fn k_weighting(sample_rate: f32) -> [IIR2; 2] {
+    let fs = sample_rate as f64;
+    let biquad = |b: [f64; 3], a: [f64; 3]| IIR2 {
+        b0: b[0] as f32,
+        b1: b[1] as f32,
+        b2: b[2] as f32,
+        a0: a[0] as f32,
+        a1: a[1] as f32,
+        a2: a[2] as f32,
+        x: [0.0, 0.0],
+        y: [0.0, 0.0],
+    };
+
+    let (f0, gain, q) = (1681.974450955533, 3.999843853973347, 0.7071752369554196);
+    let k = (PI * f0 / fs).tan();
+    let vh = 10f64.powf(gain / 20.0);
+    let vb = vh.powf(0.4996667741545416);
+    let a0 = 1.0 + k / q + k * k;
+    let shelf = biquad(
+        [
+            (vh + vb * k / q + k * k) / a0,
+            2.0 * (k * k - vh) / a0,
+            (vh - vb * k / q + k * k) / a0,
+        ],
+        [1.0, 2.0 * (k * k - 1.0) / a0, (1.0 - k / q + k * k) / a0],
+    );
+
+    let (f0, q) = (38.13547087602444, 0.5003270373238773);
+    let k = (PI * f0 / fs).tan();
+    let a0 = 1.0 + k / q + k * k;
+    let high_pass = biquad(
+        [1.0, -2.0, 1.0],
+        [1.0, 2.0 * (k * k - 1.0) / a0, (1.0 - k / q + k * k) / a0],
+    );
+    [shelf, high_pass]
+}
+
+/// hann windowed sinc, phase p of the output is sum of taps[p][k] * x[n - k]
+fn interpolator() -> Vec<Vec<f32>> {
+    let n = OVERSAMPLING * TAPS_PER_PHASE;
+    let center = (n - 1) as f64 / 2.0;
+    let h: Vec<f64> = (0..n)
+        .map(|i| {
+            let t = (i as f64 - center) / OVERSAMPLING as f64;
+            let sinc = if t.abs() < 1e-9 {
+                1.0
+            } else {
+                (PI * t).sin() / (PI * t)
+            };
+            let window = 0.5 - 0.5 * (2.0 * PI * (i as f64 + 0.5) / n as f64).cos();
+            sinc * window
+        })
+        .collect();
+    (0..OVERSAMPLING)
+        .map(|p| {
+            let phase: Vec<f64> = (0..TAPS_PER_PHASE)
+                .map(|k| h[p + OVERSAMPLING * k])
+                .collect();
+            // every phase passes dc at unity
+            let sum: f64 = phase.iter().sum();
+            phase.iter().map(|x| (x / sum) as f32).collect()
+        })
+        .collect()
+}
+
+/// largest of the sample and the interpolated points before it
+fn oversampled_peak(taps: &[Vec<f32>], history: &VecDeque<f32>) -> f32 {
+    let mut peak = history[0].abs();
+    for phase in taps.iter() {
+        let y: f32 = phase.iter().zip(history.iter()).map(|(h, x)| h * x).sum();
+        peak = peak.max(y.abs());
+    }
+    peak
+}
+
+/// true peak of each frame of interleaved samples, the loudest channel
+pub fn frame_true_peaks(samples: &[f32], num_channels: usize) -> Vec<f32> {
+    let num_channels = num_channels.max(1);
+    let taps = interpolator();
+    let mut history = vec![VecDeque::from(vec![0.0; TAPS_PER_PHASE]); num_channels];
+    samples
+        .chunks_exact(num_channels)
+        .map(|frame| {
+            let mut peak = 0.0f32;
+            for (h, x) in history.iter_mut().zip(frame.iter()) {
+                h.pop_back();
+                h.push_front(*x);
+                peak = peak.max(oversampled_peak(&taps, h));
+            }
+            peak
+        })
+        .collect()
+}
+
+#[derive(Clone, Debug)]
+struct ChannelMeter {
+    k_weighting: [IIR2; 2],
+    /// last TAPS_PER_PHASE samples, newest first
+    history: VecDeque<f32>,
+    peak: f32,
+    true_peak: f32,
+    sum_squares: f64,
+    /// K-weighted sum of squares in the current sub-block
+    weighted: f64,
+}
+
+/// per-channel peak, rms and true peak plus R128 loudness of the whole program
+#[derive(Clone, Debug)]
+pub struct Meter {
+    pub sample_rate: f32,
+    channels: Vec<ChannelMeter>,
+    taps: Vec<Vec<f32>>,
+    sub_block_len: usize,
+    sub_block_pos: usize,
+    /// weighted mean squares of the last SHORT_TERM_BLOCKS sub-blocks, newest last
+    sub_blocks: VecDeque<f64>,
+    /// count and summed weighted mean square of the 400 ms gating blocks (75% overlap) by loudness
+    /// fixed size so the meter never allocates however long it runs
+    gating_blocks: Vec<(usize, f64)>,
+    /// frames since the last take
+    count: usize,
+}
+
+impl Meter {
+    pub fn new(num_channels: usize, sample_rate: f32) -> Self {
+        let channel = ChannelMeter {
+            k_weighting: k_weighting(sample_rate),
+            history: VecDeque::from(vec![0.0; TAPS_PER_PHASE]),
+            peak: 0.0,
+            true_peak: 0.0,
+            sum_squares: 0.0,
+            weighted: 0.0,
+        };
+        Self {
+            sample_rate,
+            channels: vec![channel; num_channels.max(1)],
+            taps: interpolator(),
+            sub_block_len: ((SUB_BLOCK * sample_rate) as usize).max(1),
+            sub_block_pos: 0,
+            sub_blocks: VecDeque::with_capacity(SHORT_TERM_BLOCKS + 1),
+            gating_blocks: vec![(0, 0.0); HISTOGRAM_BINS],
+            count: 0,
+        }
+    }
+
+    /// meter a whole interleaved buffer, as for an export
+    pub fn measure(samples: &[f32], num_channels: usize, sample_rate: f32) -> Metering {
+        let mut meter = Meter::new(num_channels, sample_rate);
+        for frame in samples.chunks_exact(num_channels.max(1)) {
+            meter.process_frame(frame);
+        }
+        meter.take()
+    }
+
+    pub fn process_frame(&mut self, frame: &[f32]) {
+        for (c, x) in self.channels.iter_mut().zip(frame.iter()) {
+            c.peak = c.peak.max(x.abs());
+            c.sum_squares += (*x as f64) * (*x as f64);
+
+            c.history.pop_back();
+            c.history.push_front(*x);
+            c.true_peak = c.true_peak.max(oversampled_peak(&self.taps, &c.history));
+
+            let mut k = *x;
+            for f in c.k_weighting.iter_mut() {
+                k = f.process(k);
+            }
+            c.weighted += (k as f64) * (k as f64);
+        }
+        self.count += 1;
+
+        self.sub_block_pos += 1;
+        if self.sub_block_pos == self.sub_block_len {
+            self.end_sub_block();
+        }
+    }
+
+    fn end_sub_block(&mut self) {
+        let num_channels = self.channels.len();
+        let n = self.sub_block_len as f64;
+        let mut sum = 0.0;
+        for (i, c) in self.channels.iter_mut().enumerate() {
+            sum += channel_weight(i, num_channels) * c.weighted / n;
+            c.weighted = 0.0;
+        }
+        self.sub_block_pos = 0;
+        self.sub_blocks.push_back(sum);
+        if self.sub_blocks.len() > SHORT_TERM_BLOCKS {
+            self.sub_blocks.pop_front();
+        }
+        if self.sub_blocks.len() >= MOMENTARY_BLOCKS {
+            let block = self.window(MOMENTARY_BLOCKS);
+            let l = loudness(block);
+            if l > ABSOLUTE_GATE {
+                let bin = (((l - ABSOLUTE_GATE) / HISTOGRAM_STEP) as usize).min(HISTOGRAM_BINS - 1);
+                let (count, sum) = &mut self.gating_blocks[bin];
+                *count += 1;
+                *sum += block;
+            }
+        }
+    }
+
+    /// mean of the newest sub-blocks, fewer if that's all there is
+    fn window(&self, blocks: usize) -> f64 {
+        let n = blocks.min(self.sub_blocks.len());
+        if n == 0 {
+            return 0.0;
+        }
+        self.sub_blocks.iter().rev().take(n).sum::<f64>() / n as f64
+    }
+
+    pub fn momentary(&self) -> f32 {
+        loudness(self.window(MOMENTARY_BLOCKS))
+    }
+
+    pub fn short_term(&self) -> f32 {
+        loudness(self.window(SHORT_TERM_BLOCKS))
+    }
+
+    /// two pass gating, absolute at -70 LUFS then relative at 10 LU below what's left
+    /// only blocks above the absolute gate are kept, so that pass is every bin
+    pub fn integrated(&self) -> f32 {
+        let above = |gate: f32| {
+            let first = ((gate - ABSOLUTE_GATE) / HISTOGRAM_STEP).max(0.0) as usize;
+            let (count, sum) = self.gating_blocks[first.min(HISTOGRAM_BINS)..]
+                .iter()
+                .fold((0, 0.0), |(count, sum), (c, s)| (count + c, sum + s));
+            if count == 0 {
+                0.0
+            } else {
+                sum / count as f64
+            }
+        };
+        let ungated = above(ABSOLUTE_GATE);
+        if ungated <= 0.0 {
+            return METER_FLOOR;
+        }
+        let relative = loudness(ungated) - RELATIVE_GATE;
+        loudness(above(relative.max(ABSOLUTE_GATE)))
+    }
+
+    /// enough audio has gone by since the last take to send another reading
+    pub fn ready(&self) -> bool {
+        self.count as f32 >= METER_INTERVAL * self.sample_rate
+    }
+
+    /// current reading, peak, rms and true peak start over
+    pub fn take(&mut self) -> Metering {
+        let mut metering = Metering::default();
+        self.take_into(&mut metering);
+        metering
+    }
+
+    /// take into an existing reading, only allocates if the channel count changed
+    pub fn take_into(&mut self, metering: &mut Metering) {
+        let count = self.count.max(1) as f64;
+        let n = self.channels.len();
+        metering.peak.resize(n, METER_FLOOR);
+        metering.rms.resize(n, METER_FLOOR);
+        metering.true_peak.resize(n, METER_FLOOR);
+        for (i, c) in self.channels.iter_mut().enumerate() {
+            metering.peak[i] = to_db(c.peak);
+            metering.rms[i] = to_db((c.sum_squares / count).sqrt() as f32);
+            metering.true_peak[i] = to_db(c.true_peak);
+            c.peak = 0.0;
+            c.true_peak = 0.0;
+            c.sum_squares = 0.0;
+        }
+        metering.momentary = self.momentary();
+        metering.short_term = self.short_term();
+        metering.integrated = self.integrated();
+        self.count = 0;
+    }
+}
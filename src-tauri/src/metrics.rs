@@ -0,0 +1,301 @@
+use rustfft::{num_complex::Complex, FftPlanner};
+use serde::{Deserialize, Serialize};
+use ts_rs::TS;
+
+use crate::fourier::{Stft, WindowKind};
+
+/// keeps the logs finite when a buffer is silent
+const METRIC_EPS: f64 = 1e-10;
+/// dB, per-frame limits for segmental snr, silent and perfect frames would otherwise dominate the mean
+const SEG_SNR_MIN: f32 = -10.0;
+const SEG_SNR_MAX: f32 = 35.0;
+/// s, frame length for segmental snr and the noise floor
+const SEG_LENGTH: f32 = 0.02;
+/// frames of the reference quieter than this are skipped by segmental snr
+const SEG_SILENCE: f64 = 1e-8;
+/// lowest fraction of frame levels that counts as the noise floor
+const FLOOR_PERCENTILE: f32 = 0.1;
+/// dB below the loudest frame where a frame is left out of the spectral measures
+const SPECTRAL_RANGE: f32 = 40.0;
+/// one third octave bands from 150 Hz, as in STOI
+const STOI_BANDS: usize = 15;
+const STOI_LOW_BAND: f32 = 150.0;
+/// dB, lower bound on the signal to distortion ratio of a band before correlation
+const STOI_BETA: f32 = -15.0;
+/// s, envelope segment length
+const STOI_SEGMENT: f32 = 0.384;
+/// samples compared when estimating the processing delay
+const DELAY_WINDOW: usize = 1 << 15;
+/// longest delay estimate_delay looks for
+pub const MAX_DELAY: usize = 4096;
+
+/// objective comparison of a processed channel with its reference
+/// the reference is the original for real files, or the clean signal for synthetic mixes
+#[derive(Clone, Copy, Debug, Default, Serialize, Deserialize, PartialEq, TS)]
+#[ts(export)]
+pub struct QualityMetrics {
+    /// dB, reference energy over the energy of the difference
+    pub snr: f32,
+    /// dB, mean of the per-frame snr over frames that aren't silent
+    pub segmental_snr: f32,
+    /// dB, rms difference of the log power spectra, averaged over frames
+    pub log_spectral_distance: f32,
+    /// 0 - 1, short-time envelope correlation in third octave bands, higher is more intelligible
+    pub stoi: f32,
+    /// dB the quietest frames came down by, positive when the noise floor dropped
+    pub noise_floor_reduction: f32,
+}
+
+/// samples the processed signal lags the reference by, from the peak of the cross-correlation
+pub fn estimate_delay(reference: &[f32], processed: &[f32], max_delay: usize) -> usize {
+    let n = reference.len().min(processed.len()).min(DELAY_WINDOW);
+    if n == 0 {
+        return 0;
+    }
+    let size = (2 * n).next_power_of_two();
+    let mut planner = FftPlanner::new();
+    let fft = planner.plan_fft_forward(size);
+    let ifft = planner.plan_fft_inverse(size);
+
+    let padded = |x: &[f32]| {
+        let mut buf = vec![Complex::new(0.0, 0.0); size];
+        for (b, x) in buf.iter_mut().zip(x[..n].iter()) {
+            b.re = *x;
+        }
+        buf
+    };
+    let mut r = padded(reference);
+    let mut p = padded(processed);
+    fft.process(&mut r);
+    fft.process(&mut p);
+    let mut c: Vec<Complex<f32>> = r.iter().zip(p.iter()).map(|(r, p)| r.conj() * p).collect();
+    ifft.process(&mut c);
+
+    (0..=max_delay.min(n - 1))
+        .max_by(|a, b| c[*a].re.total_cmp(&c[*b].re))
+        .unwrap_or(0)
+}
+
+/// compare one channel, the processed signal is shifted back by delay before anything is measured
+pub fn quality_metrics(
+    reference: &[f32],
+    processed: &[f32],
+    delay: usize,
+    sample_rate: f32,
+) -> QualityMetrics {
+    let processed = &processed[delay.min(processed.len())..];
+    let len = reference.len().min(processed.len());
+    let (reference, processed) = (&reference[..len], &processed[..len]);
+
+    let seg = ((SEG_LENGTH * sample_rate) as usize).max(1);
+    QualityMetrics {
+        snr: snr(reference, processed),
+        segmental_snr: segmental_snr(reference, processed, seg),
+        log_spectral_distance: log_spectral_distance(reference, processed, sample_rate),
+        stoi: stoi(reference, processed, sample_rate),
+        noise_floor_reduction: noise_floor(reference, seg) - noise_floor(processed, seg),
+    }
+}
+
+/// per channel metrics of interleaved buffers, delays are estimated when not given
+pub fn channel_metrics(
+    reference: &[f32],
+    processed: &[f32],
+    num_channels: usize,
+    sample_rate: f32,
+    delays: Option<&[usize]>,
+) -> Vec<QualityMetrics> {
+    let num_channels = num_channels.max(1);
+    let channel = |x: &[f32], i: usize| -> Vec<f32> {
+        x.iter().skip(i).step_by(num_channels).cloned().collect()
+    };
+    (0..num_channels)
+        .map(|i| {
+            let r = channel(reference, i);
+            let p = channel(processed, i);
+            let delay = match delays {
+                Some(d) => d.get(i).cloned().unwrap_or(0),
+                None => estimate_delay(&r, &p, MAX_DELAY),
+            };
+            quality_metrics(&r, &p, delay, sample_rate)
+        })
+        .collect()
+}
+
+fn energy(x: &[f32]) -> f64 {
+    x.iter().map(|x| (*x as f64) * (*x as f64)).sum()
+}
+
+fn error_energy(reference: &[f32], processed: &[f32]) -> f64 {
+    reference
+        .iter()
+        .zip(processed.iter())
+        .map(|(r, p)| (*r as f64 - *p as f64).powi(2))
+        .sum()
+}
+
+fn snr(reference: &[f32], processed: &[f32]) -> f32 {
+    let s = energy(reference) + METRIC_EPS;
+    let e = error_energy(reference, processed) + METRIC_EPS;
+    (10.0 * (s / e).log10()) as f32
+}
+
+fn segmental_snr(reference: &[f32], processed: &[f32], seg: usize) -> f32 {
+    let snrs: Vec<f32> = reference
+        .chunks(seg)
+        .zip(processed.chunks(seg))
+        .filter(|(r, _)| energy(r) / (r.len() as f64) > SEG_SILENCE)
+        .map(|(r, p)| snr(r, p).clamp(SEG_SNR_MIN, SEG_SNR_MAX))
+        .collect();
+    if snrs.is_empty() {
+        return 0.0;
+    }
+    snrs.iter().sum::<f32>() / snrs.len() as f32
+}
+
+/// level of the quiet frames, dB
+fn noise_floor(x: &[f32], seg: usize) -> f32 {
+    let mut levels: Vec<f32> = x
+        .chunks_exact(seg)
+        .map(|c| (10.0 * (energy(c) / seg as f64 + METRIC_EPS).log10()) as f32)
+        .collect();
+    if levels.is_empty() {
+        return (10.0 * METRIC_EPS.log10()) as f32;
+    }
+    levels.sort_by(|a, b| a.total_cmp(b));
+    levels[((levels.len() - 1) as f32 * FLOOR_PERCENTILE) as usize]
+}
+
+/// power of two closest to n
+fn pow2_near(n: usize) -> usize {
+    let up = n.max(1).next_power_of_two();
+    if up - n > n - up / 2 {
+        up / 2
+    } else {
+        up
+    }
+}
+
+/// one sided power spectra, Hann frames of about 25 ms at half overlap
+fn power_frames(x: &[f32], sample_rate: f32) -> (usize, Vec<Vec<f64>>) {
+    let size = pow2_near((0.0256 * sample_rate) as usize).max(16);
+    let stft = Stft::new(size, size / 2, WindowKind::Hann).unwrap();
+    let frames = stft
+        .forward(x)
+        .iter()
+        .map(|f| {
+            f[..size / 2 + 1]
+                .iter()
+                .map(|c| c.norm_sqr() as f64)
+                .collect()
+        })
+        .collect();
+    (size, frames)
+}
+
+/// frames of the reference within SPECTRAL_RANGE of its loudest frame
+fn loud_frames(frames: &[Vec<f64>]) -> Vec<bool> {
+    let levels: Vec<f64> = frames.iter().map(|f| f.iter().sum::<f64>()).collect();
+    let max = levels.iter().cloned().fold(0.0, f64::max);
+    let floor = max * 10f64.powf(-SPECTRAL_RANGE as f64 / 10.0);
+    levels.iter().map(|l| *l > floor && *l > 0.0).collect()
+}
+
+fn log_spectral_distance(reference: &[f32], processed: &[f32], sample_rate: f32) -> f32 {
+    let (_, r) = power_frames(reference, sample_rate);
+    let (_, p) = power_frames(processed, sample_rate);
+    let keep = loud_frames(&r);
+    let distances: Vec<f64> = r
+        .iter()
+        .zip(p.iter())
+        .zip(keep.iter())
+        .filter(|(_, k)| **k)
+        .map(|((r, p), _)| {
+            let sum: f64 = r
+                .iter()
+                .zip(p.iter())
+                .map(|(r, p)| (10.0 * ((r + METRIC_EPS) / (p + METRIC_EPS)).log10()).powi(2))
+                .sum();
+            (sum / r.len() as f64).sqrt()
+        })
+        .collect();
+    if distances.is_empty() {
+        return 0.0;
+    }
+    (distances.iter().sum::<f64>() / distances.len() as f64) as f32
+}
+
+/// STOI-style estimate at the file's own rate: third octave envelopes over ~384 ms segments,
+/// the processed envelope normalized and clipped against the reference, then correlated
+fn stoi(reference: &[f32], processed: &[f32], sample_rate: f32) -> f32 {
+    let (size, r) = power_frames(reference, sample_rate);
+    let (_, p) = power_frames(processed, sample_rate);
+    let keep = loud_frames(&r);
+
+    let bin_hz = sample_rate / size as f32;
+    let bands: Vec<(usize, usize)> = (0..STOI_BANDS)
+        .map(|k| {
+            let center = STOI_LOW_BAND * 2f32.powf(k as f32 / 3.0);
+            let lo = (center * 2f32.powf(-1.0 / 6.0) / bin_hz).round() as usize;
+            let hi = (center * 2f32.powf(1.0 / 6.0) / bin_hz).round() as usize;
+            (lo, hi.min(size / 2 + 1))
+        })
+        .filter(|(lo, hi)| lo < hi)
+        .collect();
+    let envelope = |frames: &[Vec<f64>]| -> Vec<Vec<f64>> {
+        bands
+            .iter()
+            .map(|(lo, hi)| {
+                frames
+                    .iter()
+                    .zip(keep.iter())
+                    .filter(|(_, k)| **k)
+                    .map(|(f, _)| f[*lo..*hi].iter().sum::<f64>().sqrt())
+                    .collect()
+            })
+            .collect()
+    };
+    let r_env = envelope(&r);
+    let p_env = envelope(&p);
+
+    let hop_time = (size / 2) as f32 / sample_rate;
+    let n = ((STOI_SEGMENT / hop_time).round() as usize).max(2);
+    let clip = 1.0 + 10f64.powf(-STOI_BETA as f64 / 20.0);
+    let mut total = 0.0;
+    let mut count = 0;
+    for (x, y) in r_env.iter().zip(p_env.iter()) {
+        if x.len() < n {
+            continue;
+        }
+        for m in n..=x.len() {
+            let x = &x[m - n..m];
+            let y = &y[m - n..m];
+            let alpha = (x.iter().map(|v| v * v).sum::<f64>()
+                / (y.iter().map(|v| v * v).sum::<f64>() + METRIC_EPS))
+                .sqrt();
+            let y: Vec<f64> = y
+                .iter()
+                .zip(x.iter())
+                .map(|(y, x)| (alpha * y).min(clip * x))
+                .collect();
+            total += correlation(x, &y);
+            count += 1;
+        }
+    }
+    if count == 0 {
+        return 0.0;
+    }
+    (total / count as f64) as f32
+}
+
+fn correlation(x: &[f64], y: &[f64]) -> f64 {
+    let mx = x.iter().sum::<f64>() / x.len() as f64;
+    let my = y.iter().sum::<f64>() / y.len() as f64;
+    let (mut xy, mut xx, mut yy) = (0.0, 0.0, 0.0);
+    for (x, y) in x.iter().zip(y.iter()) {
+        xy += (x - mx) * (y - my);
+        xx += (x - mx) * (x - mx);
+        yy += (y - my) * (y - my);
+    }
+    xy / ((xx * yy).sqrt() + METRIC_EPS)
+}
@@ -0,0 +1,61 @@
+use crate::{constants::SAMPLING_RATE, types::MixParams};
+
+/// ms, how long the bypass, a change of mix and a switch between a/b sets take
+pub const FADE_MS: f32 = 20.0;
+
+/// a value that moves to its target in a straight line over FADE_MS, one step a frame
+#[derive(Clone, Copy, Debug)]
+pub struct Ramp {
+    pub value: f32,
+    step: f32,
+}
+
+impl Ramp {
+    pub fn new(value: f32, sample_rate: f32) -> Self {
+        Self {
+            value,
+            step: 1.0 / (FADE_MS / 1000.0 * sample_rate),
+        }
+    }
+
+    pub fn next(&mut self, target: f32) -> f32 {
+        self.value = if self.value < target {
+            (self.value + self.step).min(target)
+        } else {
+            (self.value - self.step).max(target)
+        };
+        self.value
+    }
+}
+
+/// wet/dry balance of the output, the bypass is a fade all the way dry so the processing keeps running under it
+#[derive(Clone, Debug)]
+pub struct Mix {
+    pub params: MixParams,
+    wet: Ramp,
+}
+
+impl Mix {
+    pub fn new(params: MixParams, sample_rate: f32) -> Self {
+        Self {
+            params,
+            wet: Ramp::new(params.wet, sample_rate),
+        }
+    }
+
+    /// the share of the processed signal for this frame
+    pub fn next(&mut self, bypass: bool) -> f32 {
+        let target = if bypass {
+            0.0
+        } else {
+            self.params.wet.clamp(0.0, 1.0)
+        };
+        self.wet.next(target)
+    }
+}
+
+impl Default for Mix {
+    fn default() -> Self {
+        Mix::new(MixParams::new(), SAMPLING_RATE)
+    }
+}
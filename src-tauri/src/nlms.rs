@@ -0,0 +1,89 @@
+use crate::types::AdaptiveCancel;
+
+/// keeps the update from blowing up when the reference goes quiet
+const NLMS_EPS: f32 = 1e-6;
+
+/// adaptive noise canceller, predicts the noise in the primary channel from the reference and subtracts it
+/// the primary is delayed by half the filter length so the filter can model noise reaching either mic first
+#[derive(Clone, Debug)]
+pub struct Nlms {
+    pub params: AdaptiveCancel,
+    weights: Vec<f32>,
+    /// reference history written twice, so the last filter_length samples are always one slice
+    reference: Vec<f32>,
+    pos: usize,
+    primary: Vec<f32>,
+    primary_pos: usize,
+    /// energy of the reference history
+    power: f32,
+}
+
+impl Nlms {
+    pub fn new(params: AdaptiveCancel) -> Self {
+        let n = params.filter_length.max(1);
+        Self {
+            params,
+            weights: vec![0.0; n],
+            reference: vec![0.0; 2 * n],
+            pos: 0,
+            primary: vec![0.0; n / 2],
+            primary_pos: 0,
+            power: 0.0,
+        }
+    }
+
+    /// new params from the ui in a canceller built from them before they were sent
+    /// the filter only starts over with it if the length changed, either way the one not used is left in built
+    /// so it's freed off the audio thread
+    pub fn set_params(&mut self, built: &mut Nlms) {
+        if built.params.filter_length != self.params.filter_length {
+            std::mem::swap(self, built);
+        } else {
+            self.params = built.params;
+        }
+    }
+
+    pub fn reset(&mut self) {
+        self.weights.fill(0.0);
+        self.reference.fill(0.0);
+        self.pos = 0;
+        self.primary.fill(0.0);
+        self.primary_pos = 0;
+        self.power = 0.0;
+    }
+
+    /// returns the primary with the predicted noise removed
+    pub fn process(&mut self, primary: f32, reference: f32) -> f32 {
+        let n = self.weights.len();
+        let oldest = self.reference[self.pos];
+        self.power = (self.power + reference * reference - oldest * oldest).max(0.0);
+        self.reference[self.pos] = reference;
+        self.reference[self.pos + n] = reference;
+        self.pos = (self.pos + 1) % n;
+
+        let delayed = if self.primary.is_empty() {
+            primary
+        } else {
+            let d = self.primary[self.primary_pos];
+            self.primary[self.primary_pos] = primary;
+            self.primary_pos = (self.primary_pos + 1) % self.primary.len();
+            d
+        };
+
+        let x = &self.reference[self.pos..self.pos + n];
+        let estimate: f32 = self.weights.iter().zip(x.iter()).map(|(w, x)| w * x).sum();
+        let error = delayed - estimate;
+
+        let mu = self.params.step_size * error / (NLMS_EPS + self.power);
+        for (w, x) in self.weights.iter_mut().zip(x.iter()) {
+            *w += mu * x;
+        }
+        error
+    }
+}
+
+impl Default for Nlms {
+    fn default() -> Self {
+        Nlms::new(AdaptiveCancel::new())
+    }
+}
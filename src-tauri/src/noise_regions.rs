@@ -0,0 +1,175 @@
+use rustfft::{num_complex::Complex, FftPlanner};
+use serde::{Deserialize, Serialize};
+use std::f32::consts::PI;
+
+/// analysis frame, long enough for a stable flatness estimate
+const REGION_FFT_SIZE: usize = 1024;
+const REGION_HOP: usize = REGION_FFT_SIZE / 2;
+/// s, length of a candidate region, about what the fingerprint needs for a smooth spectrum
+pub const REGION_LENGTH: f32 = 0.5;
+/// dB, frames below this are digital silence and say nothing about the noise
+const SILENCE_DB: f32 = -100.0;
+/// dB, a region whose level wanders this much is not stationary
+const LEVEL_SPREAD: f32 = 6.0;
+
+/// a candidate noise-only region, lower scores are better
+#[derive(Clone, Copy, Debug, Serialize, Deserialize, PartialEq)]
+pub struct NoiseRegion {
+    /// frame of the file, same units as message_fingerprint
+    pub start: usize,
+    /// frames
+    pub len: usize,
+    pub score: f32,
+    /// dB, mean frame level
+    pub level: f32,
+    /// 0 - 1, mean spectral flatness
+    pub flatness: f32,
+    /// 0 - 1, mean normalized spectral flux
+    pub flux: f32,
+}
+
+/// level, flatness and flux of one analysis frame
+#[derive(Clone, Copy, Debug)]
+struct FrameFeatures {
+    level: f32,
+    flatness: f32,
+    flux: f32,
+}
+
+fn frame_features(samples: &[f32]) -> Vec<FrameFeatures> {
+    let fft = FftPlanner::<f32>::new().plan_fft_forward(REGION_FFT_SIZE);
+    let window: Vec<f32> = (0..REGION_FFT_SIZE)
+        .map(|i| 0.5 - 0.5 * (2.0 * PI * i as f32 / REGION_FFT_SIZE as f32).cos())
+        .collect();
+    let bins = REGION_FFT_SIZE / 2;
+    let mut buf = vec![Complex::new(0.0, 0.0); REGION_FFT_SIZE];
+    let mut last = vec![0.0f32; bins];
+    let mut mags = vec![0.0f32; bins];
+    let mut features = vec![];
+    let mut start = 0;
+    while start + REGION_FFT_SIZE <= samples.len() {
+        for ((b, x), w) in buf
+            .iter_mut()
+            .zip(samples[start..].iter())
+            .zip(window.iter())
+        {
+            *b = Complex::new(x * w, 0.0);
+        }
+        fft.process(&mut buf);
+
+        let mut power = 0.0;
+        let mut log_power = 0.0;
+        let mut diff = 0.0;
+        let mut norm = 0.0;
+        // skip dc
+        for k in 1..bins {
+            mags[k] = buf[k].norm();
+            let p = mags[k] * mags[k];
+            power += p;
+            log_power += (p + 1e-20).ln();
+            diff += (mags[k] - last[k]).powi(2);
+            norm += mags[k].powi(2) + last[k].powi(2);
+        }
+        let n = bins.saturating_sub(1).max(1) as f32;
+        let mean = power / n;
+        features.push(FrameFeatures {
+            level: 10.0 * (mean / REGION_FFT_SIZE as f32 + 1e-20).log10(),
+            flatness: (log_power / n).exp() / (mean + 1e-20),
+            // 0 for identical spectra, 1 when they don't overlap at all
+            flux: if features.is_empty() || norm <= 0.0 {
+                0.0
+            } else {
+                diff / norm
+            },
+        });
+        last.copy_from_slice(&mags);
+        start += REGION_HOP;
+    }
+    features
+}
+
+/// scan a whole file for the quietest stationary stretches, ranked best first and not overlapping
+/// a region scores well when it is quiet compared to the rest of the file, flat like noise,
+/// and its spectrum and level don't move around
+pub fn find_noise_regions(
+    samples: &[f32],
+    num_channels: usize,
+    sample_rate: f32,
+    max_regions: usize,
+) -> Vec<NoiseRegion> {
+    let num_channels = num_channels.max(1);
+    let mono: Vec<f32> = samples
+        .chunks_exact(num_channels)
+        .map(|f| f.iter().sum::<f32>() / num_channels as f32)
+        .collect();
+    let features = frame_features(&mono);
+    let region_frames = ((REGION_LENGTH * sample_rate - REGION_FFT_SIZE as f32) / REGION_HOP as f32)
+        .max(0.0) as usize
+        + 1;
+    if features.len() < region_frames {
+        return vec![];
+    }
+
+    // level relative to the quiet and loud ends of the file
+    let mut levels: Vec<f32> = features
+        .iter()
+        .map(|f| f.level)
+        .filter(|l| *l > SILENCE_DB)
+        .collect();
+    if levels.is_empty() {
+        return vec![];
+    }
+    levels.sort_by(|a, b| a.total_cmp(b));
+    let quiet = levels[0];
+    let loud = levels[levels.len() * 95 / 100];
+    let range = (loud - quiet).max(1.0);
+
+    let mut candidates = vec![];
+    for first in 0..=features.len() - region_frames {
+        let frames = &features[first..first + region_frames];
+        if frames.iter().any(|f| f.level <= SILENCE_DB) {
+            continue;
+        }
+        let n = region_frames as f32;
+        let level = frames.iter().map(|f| f.level).sum::<f32>() / n;
+        let flatness = frames.iter().map(|f| f.flatness).sum::<f32>() / n;
+        let flux = frames.iter().map(|f| f.flux).sum::<f32>() / n;
+        let spread = (frames
+            .iter()
+            .map(|f| (f.level - level).powi(2))
+            .sum::<f32>()
+            / n)
+            .sqrt();
+
+        let score = (level - quiet) / range
+            + (1.0 - flatness.clamp(0.0, 1.0))
+            + flux.clamp(0.0, 1.0)
+            + spread / LEVEL_SPREAD;
+        candidates.push((first, score, level, flatness, flux));
+    }
+    candidates.sort_by(|a, b| a.1.total_cmp(&b.1));
+
+    let mut taken: Vec<(usize, usize)> = vec![];
+    let mut regions = vec![];
+    for (first, score, level, flatness, flux) in candidates {
+        if regions.len() >= max_regions {
+            break;
+        }
+        let last = first + region_frames;
+        if taken.iter().any(|(a, b)| first < *b && *a < last) {
+            continue;
+        }
+        taken.push((first, last));
+        let start = first * REGION_HOP;
+        let len = (region_frames - 1) * REGION_HOP + REGION_FFT_SIZE;
+        regions.push(NoiseRegion {
+            start,
+            len,
+            score,
+            level,
+            flatness,
+            flux,
+        });
+    }
+    regions
+}
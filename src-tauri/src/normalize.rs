@@ -0,0 +1,123 @@
+use serde::{Deserialize, Serialize};
+use std::collections::VecDeque;
+
+use crate::{
+    constants::from_log,
+    metering::{frame_true_peaks, to_db, Meter, METER_FLOOR},
+    types::NormalizeParams,
+};
+
+/// s, how far ahead the limiter starts turning down for a peak
+const LOOKAHEAD: f32 = 0.005;
+/// ms, time constant of the limiter letting go after a peak
+const RELEASE: f32 = 100.0;
+/// limiting takes some loudness with it, the gain is corrected and limiting redone at most this many times
+const NORMALIZE_PASSES: usize = 4;
+/// LU, close enough to the target to stop correcting
+const TARGET_TOLERANCE: f32 = 0.1;
+
+/// what normalization did to an export
+#[derive(Clone, Copy, Debug, Default, Serialize, Deserialize, PartialEq)]
+pub struct Normalization {
+    /// LUFS, integrated loudness before normalizing
+    pub input_loudness: f32,
+    /// dB, applied to bring it to the target
+    pub gain: f32,
+    /// dB, the most the limiter took off on top of the gain, 0 if it never acted
+    pub limited: f32,
+}
+
+/// measure the integrated loudness then apply the gain to reach the target,
+/// the limiter then keeps the true peak under the ceiling so loud peaks don't clip after the gain
+/// when the limiter pulls the loudness under the target the gain goes up and it's done again
+/// silence is left as it is
+pub fn normalize(
+    samples: &mut [f32],
+    num_channels: usize,
+    sample_rate: f32,
+    params: &NormalizeParams,
+) -> Normalization {
+    let input_loudness = Meter::measure(samples, num_channels, sample_rate).integrated;
+    if input_loudness <= METER_FLOOR {
+        return Normalization {
+            input_loudness,
+            ..Default::default()
+        };
+    }
+    let original = samples.to_vec();
+    let mut gain = params.target - input_loudness;
+    let mut limited = 0.0;
+    for pass in 0..NORMALIZE_PASSES {
+        let g = from_log(gain);
+        for (x, o) in samples.iter_mut().zip(original.iter()) {
+            *x = o * g;
+        }
+        limited = limit(samples, num_channels, sample_rate, from_log(params.ceiling));
+        if limited == 0.0 || pass + 1 == NORMALIZE_PASSES {
+            break;
+        }
+        let error = params.target - Meter::measure(samples, num_channels, sample_rate).integrated;
+        if error.abs() < TARGET_TOLERANCE {
+            break;
+        }
+        gain += error;
+    }
+    Normalization {
+        input_loudness,
+        gain,
+        limited,
+    }
+}
+
+/// lookahead true peak limiter, all channels get the same gain so the image doesn't move
+/// the gain each frame needs is held over the lookahead, then averaged over it,
+/// so it ramps down before a peak and is at or under what the peak needs when it arrives
+/// returns the largest reduction in dB
+pub fn limit(samples: &mut [f32], num_channels: usize, sample_rate: f32, ceiling: f32) -> f32 {
+    let num_channels = num_channels.max(1);
+    let needed: Vec<f32> = frame_true_peaks(samples, num_channels)
+        .iter()
+        .map(|p| if *p > ceiling { ceiling / p } else { 1.0 })
+        .collect();
+    if needed.iter().all(|g| *g >= 1.0) {
+        return 0.0;
+    }
+    let len = needed.len();
+    let lookahead = ((LOOKAHEAD * sample_rate) as usize).max(1);
+
+    // minimum over the next lookahead frames
+    let mut held = vec![1.0; len];
+    let mut window: VecDeque<usize> = VecDeque::new();
+    for n in (0..len).rev() {
+        while window.back().is_some_and(|i| needed[*i] >= needed[n]) {
+            window.pop_back();
+        }
+        window.push_back(n);
+        while window.front().is_some_and(|i| *i > n + lookahead) {
+            window.pop_front();
+        }
+        held[n] = needed[window[0]];
+    }
+
+    // average over the lookahead frames before, frames before the start count as the first
+    let release = (-1.0 / (RELEASE * 0.001 * sample_rate)).exp();
+    let mut sum = held[0] as f64 * (lookahead + 1) as f64;
+    let mut gain = held[0];
+    let mut min_gain = 1.0f32;
+    for n in 0..len {
+        if n > 0 {
+            sum += (held[n] - held[n.saturating_sub(lookahead + 1)]) as f64;
+        }
+        let smoothed = (sum / (lookahead + 1) as f64) as f32;
+        gain = if smoothed <= gain {
+            smoothed
+        } else {
+            smoothed + release * (gain - smoothed)
+        };
+        min_gain = min_gain.min(gain);
+        for x in samples[n * num_channels..(n + 1) * num_channels].iter_mut() {
+            *x *= gain;
+        }
+    }
+    -to_db(min_gain)
+}
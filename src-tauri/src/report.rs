@@ -0,0 +1,260 @@
+use std::{
+    cell::UnsafeCell,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    },
+    thread,
+    time::{Duration, Instant},
+};
+use tauri::Window;
+
+use crate::{
+    compare::{Comparer, Recent},
+    freq_scale::FreqMapping,
+    messages::AudioUIMessage,
+    metering::{Metering, METER_FLOOR},
+    settings::PlotScale,
+    transport::{EndMessage, PositionMessage, Transport, TransportState, POSITION_RATE},
+    types::StereoParams,
+};
+
+/// Hz, range of the ui rate
+pub const MIN_UI_RATE: f32 = 1.0;
+pub const MAX_UI_RATE: f32 = 240.0;
+
+/// low bits of the shared index are the back slot, this bit is set while the reader hasn't taken it
+const SLOT: usize = 3;
+const FRESH: usize = 4;
+
+struct Slots<T> {
+    slots: [UnsafeCell<T>; 3],
+    back: AtomicUsize,
+}
+
+// a slot is only touched by the side holding its index, the index changes hands through the atomic swap
+unsafe impl<T: Send> Sync for Slots<T> {}
+
+/// audio side of a snapshot buffer, never waits
+pub struct SnapshotWriter<T> {
+    shared: Arc<Slots<T>>,
+    index: usize,
+}
+
+/// ui side of a snapshot buffer, sees the newest published value and skips the rest
+pub struct SnapshotReader<T> {
+    shared: Arc<Slots<T>>,
+    index: usize,
+}
+
+/// triple buffer, one slot for each side and one in between that they swap with
+pub fn snapshot_buffer<T: Clone>(init: T) -> (SnapshotWriter<T>, SnapshotReader<T>) {
+    let shared = Arc::new(Slots {
+        slots: [
+            UnsafeCell::new(init.clone()),
+            UnsafeCell::new(init.clone()),
+            UnsafeCell::new(init),
+        ],
+        back: AtomicUsize::new(1),
+    });
+    (
+        SnapshotWriter {
+            shared: shared.clone(),
+            index: 0,
+        },
+        SnapshotReader { shared, index: 2 },
+    )
+}
+
+impl<T> SnapshotWriter<T> {
+    /// the slot being written, it holds whatever was published a couple of times ago
+    pub fn get(&mut self) -> &mut T {
+        unsafe { &mut *self.shared.slots[self.index].get() }
+    }
+
+    /// hand the written slot over and carry on in the one the reader isn't using
+    pub fn publish(&mut self) {
+        let back = self.shared.back.swap(self.index | FRESH, Ordering::AcqRel);
+        self.index = back & SLOT;
+    }
+}
+
+impl<T> SnapshotReader<T> {
+    /// take the newest published slot, false when nothing was published since the last update
+    pub fn update(&mut self) -> bool {
+        if self.shared.back.load(Ordering::Acquire) & FRESH == 0 {
+            return false;
+        }
+        let back = self.shared.back.swap(self.index, Ordering::AcqRel);
+        self.index = back & SLOT;
+        true
+    }
+
+    pub fn get(&self) -> &T {
+        unsafe { &*self.shared.slots[self.index].get() }
+    }
+
+    /// the writer was dropped along with its stream
+    pub fn is_closed(&self) -> bool {
+        Arc::strong_count(&self.shared) == 1
+    }
+}
+
+/// what the ui is shown, copied in place by the callback so it never allocates or serializes
+#[derive(Clone, Debug)]
+pub struct Snapshot {
+    pub transport: Transport,
+    /// magnitudes of the left sdft up to nyquist, mapped to the scale by the reporter
+    pub spectrum: Vec<f32>,
+    pub freq_scale: (PlotScale, usize),
+    pub ui_rate: f32,
+    pub speech: Option<bool>,
+    /// newest meter reading and how many there have been, so each is sent once
+    pub meter: Metering,
+    pub meter_readings: usize,
+    /// recent is only kept up to date while the comparison is on
+    pub compare: bool,
+    pub recent: Vec<Recent>,
+}
+
+impl Snapshot {
+    pub fn new(params: &StereoParams) -> Self {
+        let mut recent = vec![];
+        params.monitor.copy_recent(&mut recent);
+        let mut snapshot = Self {
+            transport: params.transport.clone(),
+            spectrum: vec![0.0; params.left.sdft.size / 2],
+            freq_scale: params.freq_scale,
+            ui_rate: params.ui_rate,
+            speech: None,
+            // sized for the channels so copying readings in never allocates
+            meter: Metering {
+                peak: vec![METER_FLOOR; params.num_channels],
+                rms: vec![METER_FLOOR; params.num_channels],
+                true_peak: vec![METER_FLOOR; params.num_channels],
+                ..Default::default()
+            },
+            meter_readings: 0,
+            compare: false,
+            recent,
+        };
+        let meter = snapshot.meter.clone();
+        snapshot.update(params, &meter, 0);
+        snapshot
+    }
+
+    /// copy the state of the params and the meter, only allocates if the channel count changed
+    pub fn update(&mut self, params: &StereoParams, meter: &Metering, meter_readings: usize) {
+        self.transport.clone_from(&params.transport);
+        for (i, s) in self.spectrum.iter_mut().enumerate() {
+            *s = params.left.sdft.norm(i);
+        }
+        self.freq_scale = params.freq_scale;
+        self.ui_rate = params.ui_rate;
+        self.speech = params
+            .left
+            .vad
+            .params
+            .enabled
+            .then_some(params.left.vad.speech);
+        self.meter.peak.clone_from(&meter.peak);
+        self.meter.rms.clone_from(&meter.rms);
+        self.meter.true_peak.clone_from(&meter.true_peak);
+        self.meter.momentary = meter.momentary;
+        self.meter.short_term = meter.short_term;
+        self.meter.integrated = meter.integrated;
+        self.meter_readings = meter_readings;
+        self.compare = params.monitor.compare;
+        if self.compare {
+            params.monitor.copy_recent(&mut self.recent);
+        }
+    }
+
+    /// the message for the ui, meter_readings is how many readings it has already been sent
+    pub fn message(
+        &self,
+        mapping: &FreqMapping,
+        comparer: &mut Comparer,
+        meter_readings: &mut usize,
+    ) -> AudioUIMessage {
+        let meter = (self.meter_readings != *meter_readings).then(|| self.meter.clone());
+        *meter_readings = self.meter_readings;
+        AudioUIMessage {
+            spectrum: Some(mapping.map(&self.spectrum)),
+            speech: self.speech,
+            comparison: self.compare.then(|| comparer.take(&self.recent, mapping)),
+            meter,
+            ..Default::default()
+        }
+    }
+
+    pub fn position(&self) -> PositionMessage {
+        PositionMessage {
+            state: self.transport.state,
+            frame: self.transport.frame,
+            seconds: self.transport.seconds(),
+            duration: self.transport.duration(),
+            loop_region: self.transport.loop_seconds(),
+        }
+    }
+}
+
+/// emit the newest snapshot to the ui at the rate it asks for and the position at POSITION_RATE, until the stream writing it is dropped
+pub fn spawn_reporter(
+    mut reader: SnapshotReader<Snapshot>,
+    window: Window,
+    bins: usize,
+    sample_rate: f32,
+) {
+    thread::spawn(move || {
+        let (scale, points) = reader.get().freq_scale;
+        let mut mapping = FreqMapping::new(scale, points, bins, sample_rate);
+        let mut comparer = Comparer::new();
+        let mut meter_readings = 0;
+        let mut ends = reader.get().transport.ends;
+        let position_period = Duration::from_secs_f32(1.0 / POSITION_RATE);
+        let mut next_position = Instant::now();
+        let mut next_ui = Instant::now();
+        let mut fresh = false;
+        while !reader.is_closed() {
+            fresh |= reader.update();
+            let snapshot = reader.get();
+            if snapshot.transport.ends != ends {
+                ends = snapshot.transport.ends;
+                let _ = window.emit(
+                    EndMessage::name(),
+                    EndMessage {
+                        action: snapshot.transport.end_action,
+                    },
+                );
+            }
+
+            let now = Instant::now();
+            if now >= next_position {
+                let _ = window.emit(PositionMessage::name(), snapshot.position());
+                next_position = (next_position + position_period).max(now);
+            }
+            if now >= next_ui {
+                // nothing new while the device is paused, and nothing worth showing while the transport is
+                if fresh && snapshot.transport.state == TransportState::Playing {
+                    let (scale, points) = snapshot.freq_scale;
+                    if scale != mapping.scale || points != mapping.points {
+                        mapping.rescale(scale, points);
+                    }
+                    let _ = window.emit(
+                        AudioUIMessage::name(),
+                        snapshot.message(&mapping, &mut comparer, &mut meter_readings),
+                    );
+                    fresh = false;
+                }
+                let rate = snapshot.ui_rate.clamp(MIN_UI_RATE, MAX_UI_RATE);
+                next_ui = (next_ui + Duration::from_secs_f32(1.0 / rate)).max(now);
+            }
+            thread::sleep(
+                next_ui
+                    .min(next_position)
+                    .saturating_duration_since(Instant::now()),
+            );
+        }
+    });
+}
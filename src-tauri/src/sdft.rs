@@ -11,7 +11,11 @@ use std::f32::consts::PI;
 // use std::simd::f32x4;
 use std::time::Instant;
 
-use crate::constants::{czerov, CZERO};
+use crate::constants::{czerov, from_log, CZERO};
+use crate::types::{GainSmoothing, SpectralGate};
+
+/// widest median used when smoothing gains across bins, keeps the window on the stack
+pub const MAX_GAIN_FREQ_WIDTH: usize = 8;
 
 #[derive(Clone, Debug)]
 struct Cvecf32(Vec<Complex<f32>>);
@@ -29,6 +33,11 @@ pub struct SDFT {
     pub ikernel: Vec<Complex<f32>>,
     pub time_output: Complex<f32>,
     pub filter: Vec<f32>,
+    pub raw_gain: Vec<f32>,
+    pub gain_history: Vec<f32>,
+    pub hold_count: Vec<usize>,
+    /// magnitudes from the last analyze
+    pub mags: Vec<f32>,
 }
 
 impl SDFT {
@@ -88,6 +97,10 @@ impl SDFT {
             ikernel,
             time_output: Complex { re: 0.0, im: 0.0 },
             filter,
+            raw_gain: vec![1.0; size],
+            gain_history: vec![1.0; size],
+            hold_count: vec![0; size],
+            mags: vec![0.0; size],
         }
     }
 
@@ -97,11 +110,11 @@ impl SDFT {
         let oldest_input = self.time_history.get(0);
         let delta = signal - oldest_input;
         self.inv_time = CZERO;
-        for freq in 0..self.size {
-            self.new_freq[freq] = (self.freq_history[freq] + delta) * self.fkernel[freq];
+        for (freq, freq_history) in self.freq_history.iter_mut().enumerate() {
+            self.new_freq[freq] = (*freq_history + delta) * self.fkernel[freq];
             self.inv_time += self.new_freq[freq] * self.ikernel[freq];
+            *freq_history = self.new_freq[freq];
         }
-        self.freq_history = self.new_freq.clone();
         self.time_history.push(Complex {
             re: signal,
             im: 0.0,
@@ -117,51 +130,202 @@ impl SDFT {
         pre_smooth_gain: f32,
         post_smooth_gain: f32,
     ) -> f32 {
-        let oldest_input = self.time_history.get(0);
-        let delta = signal - oldest_input;
-        let mut out: f32;
-        let mut denoise;
-        let mut post_smoothed_noise;
-        let mut pre_smoothed_noise;
-        let mut arg;
-        let mut mag;
-        let mut noise;
-        self.inv_time = CZERO;
-
-        for (freq, freq_history) in self.freq_history.iter_mut().enumerate() {
-            // get spectrum of input
-            self.new_freq[freq] = delta + *freq_history * self.fkernel[freq];
+        self.analyze(signal);
+        self.pre_smooth(pre_smooth_gain);
+        self.smoothed_subtraction_gain(noise_spectrum, noise_gain);
+        self.apply_smoothed_gain(post_smooth_gain)
+    }
 
-            noise = (noise_spectrum[freq] - 1.0).abs();
-            // smooth the noise variance
-            pre_smoothed_noise = pre_smooth_gain * self.pre_smooth_noise_history[freq]
+    /// smooth the complex spectrum in time, mags become the smoothed magnitudes
+    pub fn pre_smooth(&mut self, pre_smooth_gain: f32) {
+        for freq in 0..self.size {
+            let smoothed = pre_smooth_gain * self.pre_smooth_noise_history[freq]
                 + (1.0 - pre_smooth_gain) * self.new_freq[freq];
+            self.pre_smooth_noise_history[freq] = smoothed;
+            self.mags[freq] = smoothed.norm();
+        }
+    }
 
-            // polar
-            mag = pre_smoothed_noise.norm();
-            arg = pre_smoothed_noise.arg();
-            out = mag - noise_gain * noise;
-            denoise = Complex32::from_polar(out.clamp(1e-6, f32::MAX), arg);
+    /// subtraction on the pre smoothed magnitudes into raw_gain, no attack/release or median
+    pub fn smoothed_subtraction_gain(&mut self, noise_spectrum: &Vec<f32>, noise_gain: f32) {
+        let mut noise;
+        let mut mag;
+        for freq in 0..self.size {
+            noise = (noise_spectrum[freq] - 1.0).abs();
+            mag = self.mags[freq];
+            self.raw_gain[freq] = if mag > 1e-9 {
+                (mag - noise_gain * noise).clamp(1e-6, f32::MAX) / mag
+            } else {
+                0.0
+            };
+        }
+    }
 
-            // more smoothing
-            post_smoothed_noise = post_smooth_gain * self.post_smooth_noise_history[freq]
+    /// raw_gain on the pre smoothed spectrum, smoothed again in time and inverted
+    pub fn apply_smoothed_gain(&mut self, post_smooth_gain: f32) -> f32 {
+        self.inv_time = CZERO;
+        for freq in 0..self.size {
+            let denoise = self.pre_smooth_noise_history[freq] * self.raw_gain[freq];
+            let smoothed = post_smooth_gain * self.post_smooth_noise_history[freq]
                 + (1.0 - post_smooth_gain) * denoise;
-            //delay
-            *freq_history = self.new_freq[freq];
-            self.pre_smooth_noise_history[freq] = pre_smoothed_noise;
-            self.post_smooth_noise_history[freq] = post_smoothed_noise;
+            self.post_smooth_noise_history[freq] = smoothed;
+            self.inv_time += smoothed * self.ikernel[freq];
+        }
+        self.inv_time.re
+    }
+
+    /// spectral subtraction done as a per-bin gain instead of on complex values
+    /// the gain is median filtered across neighboring bins, then smoothed in time with attack/release
+    /// smoothing gains instead of complex values doesn't average phases, and keeps isolated bins from twinkling
+    pub fn spectral_subtraction_gain(
+        &mut self,
+        signal: f32,
+        noise_spectrum: &Vec<f32>,
+        noise_gain: f32,
+        smoothing: &GainSmoothing,
+        sample_rate: f32,
+    ) -> f32 {
+        self.analyze(signal);
+        self.subtraction_gain(noise_spectrum, noise_gain, smoothing, sample_rate);
+        self.apply_gain()
+    }
+
+    /// per-bin gate/expander, uses the same analysis as spectral_subtraction so the two can be compared directly
+    /// a bin opens above the noise spectrum plus the margin, and stays open for the hold time after falling below it
+    /// below the threshold the gain drops with the expansion ratio, down to the reduction
+    pub fn spectral_gate(
+        &mut self,
+        signal: f32,
+        noise_spectrum: &Vec<f32>,
+        noise_gain: f32,
+        gate: &SpectralGate,
+        sample_rate: f32,
+    ) -> f32 {
+        self.analyze(signal);
+        self.gate_gain(noise_spectrum, noise_gain, gate, sample_rate);
+        self.apply_gain()
+    }
 
-            // inverse
-            self.inv_time += post_smoothed_noise * self.ikernel[freq];
+    /// slide the dft by one sample and keep the magnitudes for the gain functions
+    /// split from the gain and inverse so linked stereo can compute one gain from both channels
+    pub fn analyze(&mut self, signal: f32) {
+        let oldest_input = self.time_history.get(0);
+        let delta = signal - oldest_input;
+        for (freq, freq_history) in self.freq_history.iter_mut().enumerate() {
+            self.new_freq[freq] = delta + *freq_history * self.fkernel[freq];
+            *freq_history = self.new_freq[freq];
+            self.mags[freq] = self.new_freq[freq].norm();
         }
         self.time_history.push(Complex {
             re: signal,
             im: 0.0,
         });
+    }
+
+    /// subtraction gain from mags into gain_history
+    pub fn subtraction_gain(
+        &mut self,
+        noise_spectrum: &Vec<f32>,
+        noise_gain: f32,
+        smoothing: &GainSmoothing,
+        sample_rate: f32,
+    ) {
+        let floor = from_log(smoothing.floor).min(1.0);
+        let attack = GainSmoothing::coeff(smoothing.attack, sample_rate);
+        let release = GainSmoothing::coeff(smoothing.release, sample_rate);
+        let width = smoothing.freq_width.min(MAX_GAIN_FREQ_WIDTH);
+        let mut noise;
+        let mut mag;
+
+        // raw suppression gain for every bin first, the median needs neighbors on both sides
+        for freq in 0..self.size {
+            noise = (noise_spectrum[freq] - 1.0).abs();
+            mag = self.mags[freq];
+            self.raw_gain[freq] = if mag > 1e-9 {
+                ((mag - noise_gain * noise) / mag).clamp(floor, 1.0)
+            } else {
+                floor
+            };
+        }
+
+        let mut window = [0.0f32; 2 * MAX_GAIN_FREQ_WIDTH + 1];
+        for freq in 0..self.size {
+            let lo = freq.saturating_sub(width);
+            let hi = (freq + width).min(self.size - 1);
+            let n = hi - lo + 1;
+            window[..n].copy_from_slice(&self.raw_gain[lo..=hi]);
+            let g = median(&mut window[..n]);
+
+            // attack when the gain opens up, release when it closes
+            let coeff = if g > self.gain_history[freq] {
+                attack
+            } else {
+                release
+            };
+            self.gain_history[freq] = coeff * self.gain_history[freq] + (1.0 - coeff) * g;
+        }
+    }
+
+    /// gate/expander gain from mags into gain_history
+    pub fn gate_gain(
+        &mut self,
+        noise_spectrum: &Vec<f32>,
+        noise_gain: f32,
+        gate: &SpectralGate,
+        sample_rate: f32,
+    ) {
+        let margin = from_log(gate.margin);
+        let reduction = from_log(gate.reduction).min(1.0);
+        let attack = GainSmoothing::coeff(gate.attack, sample_rate);
+        let release = GainSmoothing::coeff(gate.release, sample_rate);
+        let hold = gate.hold_samples(sample_rate);
+        let exponent = gate.ratio.max(1.0) - 1.0;
+        let mut threshold;
+        let mut mag;
+        let mut g;
+
+        for freq in 0..self.size {
+            threshold = noise_gain * (noise_spectrum[freq] - 1.0).abs() * margin;
+            mag = self.mags[freq];
+            g = if mag >= threshold {
+                self.hold_count[freq] = hold;
+                1.0
+            } else if self.hold_count[freq] > 0 {
+                self.hold_count[freq] -= 1;
+                1.0
+            } else {
+                (mag / threshold).powf(exponent).clamp(reduction, 1.0)
+            };
+
+            let coeff = if g > self.gain_history[freq] {
+                attack
+            } else {
+                release
+            };
+            self.gain_history[freq] = coeff * self.gain_history[freq] + (1.0 - coeff) * g;
+        }
+    }
 
+    /// inverse of the last analyzed spectrum with gain_history applied
+    pub fn apply_gain(&mut self) -> f32 {
+        self.inv_time = CZERO;
+        for freq in 0..self.size {
+            self.inv_time += self.new_freq[freq] * self.gain_history[freq] * self.ikernel[freq];
+        }
         self.inv_time.re
     }
 
+    /// clear everything that depends on past input, used when time jumps or processing changes
+    /// buffers are cleared in place, this runs on the audio thread
+    pub fn reset(&mut self) {
+        self.freq_history.fill(CZERO);
+        self.pre_smooth_noise_history.fill(CZERO);
+        self.post_smooth_noise_history.fill(CZERO);
+        self.time_history.iter_mut().for_each(|x| *x = CZERO);
+        self.gain_history.fill(1.0);
+        self.hold_count.fill(0);
+    }
+
     /// magnitude of frequency spectrum
     pub fn norm_vec(&self) -> Vec<f32> {
         self.new_freq.iter().map(|x| x.norm()).collect()
@@ -177,6 +341,18 @@ impl SDFT {
     }
 }
 
+/// insertion sort is fine for the handful of bins in a gain window, and doesn't allocate
+fn median(v: &mut [f32]) -> f32 {
+    for i in 1..v.len() {
+        let mut j = i;
+        while j > 0 && v[j - 1] > v[j] {
+            v.swap(j - 1, j);
+            j -= 1;
+        }
+    }
+    v[v.len() / 2]
+}
+
 impl Default for SDFT {
     fn default() -> Self {
         SDFT::new(256)
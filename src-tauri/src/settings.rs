@@ -46,9 +46,10 @@ impl Default for Settings {
 //     pub fn as_array(&self) ->
 // }
 
-#[derive(Serialize, Deserialize, Debug, TS)]
+#[derive(Clone, Copy, Serialize, Deserialize, Debug, Default, PartialEq, TS)]
 #[ts(export)]
 pub enum PlotScale {
+    #[default]
     Linear,
     Mel,
     Log,
@@ -0,0 +1,217 @@
+use std::f32::consts::PI;
+
+use crate::{
+    transport::Transport,
+    types::{SpeedMode, SpeedParams},
+};
+
+/// range of the playback rate
+pub const MIN_RATE: f32 = 0.5;
+pub const MAX_RATE: f32 = 3.0;
+
+/// frames in a wsola segment, about 23 ms at 44.1 kHz, long enough for a couple of pitch periods
+const SEGMENT: usize = 1024;
+/// segments overlap by half, the hann windows sum to one
+const HOP: usize = SEGMENT / 2;
+/// frames either side of the nominal position searched for the best match
+const TOLERANCE: usize = 256;
+/// every nth frame is used in the search, the match doesn't need to be exact
+const SEARCH_STEP: usize = 4;
+/// input kept for the stretch, enough for a hop at MAX_RATE plus both searches and a segment
+const CAPACITY: usize = 4 * SEGMENT;
+
+/// plays processed frames faster or slower, it pulls them from the transport as it needs them
+/// so the processing always runs on the file's own time base and matches the export
+pub struct Speed {
+    pub params: SpeedParams,
+    num_channels: usize,
+    /// transport jumps seen, a jump throws away whatever is buffered
+    jumps: usize,
+    /// processed frames not played yet, interleaved, the first len are filled
+    input: Vec<f32>,
+    len: usize,
+    /// varispeed, fractional frame in input being played
+    position: f64,
+    /// wsola, where the next segment would start with no search, and where the last one started
+    nominal: f64,
+    previous: usize,
+    first: bool,
+    window: Vec<f32>,
+    /// overlap-added segments, the first HOP frames are played from emitted on
+    output: Vec<f32>,
+    emitted: usize,
+}
+
+impl Speed {
+    pub fn new(num_channels: usize) -> Self {
+        let mut speed = Self {
+            params: SpeedParams::new(),
+            num_channels,
+            jumps: 0,
+            input: vec![0.0; CAPACITY * num_channels],
+            len: 0,
+            position: 0.0,
+            nominal: 0.0,
+            previous: 0,
+            first: true,
+            window: (0..SEGMENT)
+                .map(|n| 0.5 - 0.5 * (2.0 * PI * n as f32 / SEGMENT as f32).cos())
+                .collect(),
+            output: vec![0.0; SEGMENT * num_channels],
+            emitted: HOP,
+        };
+        speed.reset();
+        speed
+    }
+
+    /// pick up a new rate or mode from the transport, and start over if it jumped
+    pub fn sync(&mut self, transport: &Transport) {
+        let params = SpeedParams {
+            rate: transport.speed.rate.clamp(MIN_RATE, MAX_RATE),
+            ..transport.speed
+        };
+        if stage(params) != stage(self.params) || transport.jumps != self.jumps {
+            self.reset();
+        }
+        self.params = params;
+        self.jumps = transport.jumps;
+    }
+
+    /// drop everything buffered, wsola starts with TOLERANCE frames of silence so the first search has room
+    pub fn reset(&mut self) {
+        self.input.fill(0.0);
+        self.len = TOLERANCE;
+        self.position = TOLERANCE as f64;
+        self.nominal = TOLERANCE as f64;
+        self.previous = TOLERANCE;
+        self.first = true;
+        self.output.fill(0.0);
+        self.emitted = HOP;
+    }
+
+    /// the next frame to play, source writes one processed frame and returns false when there are none
+    /// returns false when source ran out, out is left as it was
+    pub fn next(&mut self, out: &mut [f32], mut source: impl FnMut(&mut [f32]) -> bool) -> bool {
+        match stage(self.params) {
+            None => source(out),
+            Some(SpeedMode::Varispeed) => self.varispeed(out, &mut source),
+            Some(SpeedMode::PreservePitch) => self.stretch(out, &mut source),
+        }
+    }
+
+    fn pull(&mut self, source: &mut impl FnMut(&mut [f32]) -> bool) -> bool {
+        let n = self.num_channels;
+        if self.len == CAPACITY || !source(&mut self.input[self.len * n..(self.len + 1) * n]) {
+            return false;
+        }
+        self.len += 1;
+        true
+    }
+
+    /// move the input down so frame comes first, positions into it are moved by the caller
+    fn consume(&mut self, frame: usize) {
+        let n = self.num_channels;
+        self.input.copy_within(frame * n..self.len * n, 0);
+        self.len -= frame;
+    }
+
+    fn frame(&self, i: usize, channel: usize) -> f32 {
+        self.input[i * self.num_channels + channel]
+    }
+
+    /// 4 point hermite between the processed frames, the pitch moves with the rate
+    fn varispeed(&mut self, out: &mut [f32], source: &mut impl FnMut(&mut [f32]) -> bool) -> bool {
+        let i = self.position as usize;
+        while self.len < i + 3 {
+            if !self.pull(source) {
+                return false;
+            }
+        }
+        let t = (self.position - i as f64) as f32;
+        for (c, o) in out.iter_mut().enumerate() {
+            let (y0, y1, y2, y3) = (
+                self.frame(i - 1, c),
+                self.frame(i, c),
+                self.frame(i + 1, c),
+                self.frame(i + 2, c),
+            );
+            let c1 = 0.5 * (y2 - y0);
+            let c2 = y0 - 2.5 * y1 + 2.0 * y2 - 0.5 * y3;
+            let c3 = 0.5 * (y3 - y0) + 1.5 * (y1 - y2);
+            *o = ((c3 * t + c2) * t + c1) * t + y1;
+        }
+        self.position += self.params.rate as f64;
+        // moved down now and then rather than every frame
+        if i > SEGMENT {
+            self.consume(i - 1);
+            self.position -= (i - 1) as f64;
+        }
+        true
+    }
+
+    /// wsola, each segment is taken from near where the rate puts it, wherever it best continues the last one
+    fn stretch(&mut self, out: &mut [f32], source: &mut impl FnMut(&mut [f32]) -> bool) -> bool {
+        if self.emitted == HOP && !self.next_segment(source) {
+            return false;
+        }
+        let n = self.num_channels;
+        out.copy_from_slice(&self.output[self.emitted * n..(self.emitted + 1) * n]);
+        self.emitted += 1;
+        true
+    }
+
+    fn next_segment(&mut self, source: &mut impl FnMut(&mut [f32]) -> bool) -> bool {
+        let n = self.num_channels;
+        let nominal = self.nominal.round() as usize;
+        let needed = (nominal + TOLERANCE).max(self.previous + HOP) + SEGMENT;
+        while self.len < needed {
+            if !self.pull(source) {
+                return false;
+            }
+        }
+
+        // the segment that would have followed the last one is what the next should sound like
+        let best = if self.first {
+            nominal
+        } else {
+            let natural = self.previous + HOP;
+            let mut best = (nominal, f32::MIN);
+            for start in nominal - TOLERANCE..=nominal + TOLERANCE {
+                let mut similarity = 0.0;
+                for j in (0..SEGMENT).step_by(SEARCH_STEP) {
+                    for c in 0..n {
+                        similarity += self.frame(natural + j, c) * self.frame(start + j, c);
+                    }
+                }
+                if similarity > best.1 {
+                    best = (start, similarity);
+                }
+            }
+            best.0
+        };
+
+        self.output.copy_within(HOP * n.., 0);
+        self.output[(SEGMENT - HOP) * n..].fill(0.0);
+        for (j, w) in self.window.iter().enumerate() {
+            for c in 0..n {
+                self.output[j * n + c] += w * self.input[(best + j) * n + c];
+            }
+        }
+        self.emitted = 0;
+        self.first = false;
+        self.previous = best;
+        self.nominal += HOP as f64 * self.params.rate as f64;
+
+        // keep room behind the next nominal position for its search
+        let keep = self.previous.min(self.nominal as usize - TOLERANCE);
+        self.consume(keep);
+        self.previous -= keep;
+        self.nominal -= keep as f64;
+        true
+    }
+}
+
+/// the stage playing at these params, None passes the processed frames straight through
+fn stage(params: SpeedParams) -> Option<SpeedMode> {
+    (params.rate != 1.0).then_some(params.mode)
+}
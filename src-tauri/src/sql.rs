@@ -4,7 +4,11 @@ use crate::{
     constants::NUM_FILTERS,
     errors::DenoiserResult,
     settings::{ComponentColors, Settings, Theme},
-    types::{StereoChoice, UIFilters, UIParams, BPF},
+    types::{
+        AdaptiveCancel, DeclickParams, DeclipParams, GainSmoothing, HumParams, MixParams,
+        NormalizeParams, SpectralGate, StereoChoice, StereoMode, StereoModeParams, UIFilters,
+        UIParams, UIRate, VadParams, BPF,
+    },
 };
 use rusqlite::Connection;
 use tauri::AppHandle;
@@ -16,6 +20,49 @@ pub fn open_connection(p: &PathBuf) -> DenoiserResult<Connection> {
     Ok(conn)
 }
 
+/// rows for a stereo choice in the current stereo mode, each mode keeps its own params
+/// dbs from before stereo modes have no STEREO_MODE table and are independent
+/// a channel past the first two gets its rows the first time they're asked for, copied from both
+fn param_set(conn: &Connection, stereo_choice: StereoChoice) -> String {
+    let mode = conn
+        .query_row("SELECT mode FROM STEREO_MODE", [], |row| row.get(0))
+        .unwrap_or(StereoMode::Independent);
+    let st = mode.param_set(stereo_choice);
+    if let StereoChoice::Channel(_) = stereo_choice {
+        let _ = conn.execute_batch(copy_both_rows(&st).as_str());
+    }
+    st
+}
+
+/// tables with a row per stereo_choice
+const PARAM_TABLES: [&str; 8] = [
+    "UI_PARAMS",
+    "FILTERBANK",
+    "GAIN_SMOOTHING",
+    "HUM",
+    "DECLICK",
+    "DECLIP",
+    "SPECTRAL_GATE",
+    "VAD",
+];
+
+/// start a param set from a copy of the params for both channels, nothing happens if it's there already
+fn copy_both_rows(st: &str) -> String {
+    let mut stmt = "".to_string();
+    for table in PARAM_TABLES {
+        stmt += format!(
+            "CREATE TEMP TABLE param_copy AS SELECT * FROM {table} WHERE stereo_choice='both';
+UPDATE param_copy SET id=NULL, stereo_choice='{st}';
+INSERT INTO {table} SELECT * FROM param_copy
+  WHERE NOT EXISTS (SELECT 1 FROM {table} WHERE stereo_choice='{st}');
+DROP TABLE param_copy;
+"
+        )
+        .as_str();
+    }
+    stmt
+}
+
 pub fn query_theme_name(p: &PathBuf) -> DenoiserResult<Theme> {
     let conn = open_connection(p)?;
     let mut stmt = conn.prepare("SELECT theme FROM settings")?;
@@ -116,15 +163,13 @@ pub fn query_settings(p: PathBuf) -> DenoiserResult<Settings> {
 
 pub fn query_ui_params(stereo_choice: StereoChoice, p: &PathBuf) -> DenoiserResult<UIParams> {
     let conn = open_connection(&p)?;
-    let chan = stereo_choice.as_str().to_lowercase();
+    let chan = param_set(&conn, stereo_choice);
     let mut stmt =
         conn.prepare(format!("SELECT * FROM UI_PARAMS WHERE stereo_choice='{}'", chan).as_str())?;
     let control_iter = stmt.query_map([], |row| {
         Ok(UIParams {
             // id: row.get(0)?,
             clean: row.get(2)?,
-            left_mute: row.get(3)?,
-            right_mute: row.get(4)?,
             output_gain: row.get(5)?,
             noise_gain: row.get(6)?,
             pre_smooth_gain: row.get(7)?,
@@ -134,7 +179,8 @@ pub fn query_ui_params(stereo_choice: StereoChoice, p: &PathBuf) -> DenoiserResu
     })?;
 
     for control in control_iter {
-        if let Ok(sett) = control {
+        if let Ok(mut sett) = control {
+            sett.mute = query_mute(stereo_choice, p).unwrap_or(false);
             return Ok(sett);
         }
     }
@@ -172,7 +218,7 @@ pub fn sql_settings(app_handle: AppHandle) -> DenoiserResult<Settings> {
 
 pub fn query_filter_bank(stereo_choice: StereoChoice, p: &PathBuf) -> DenoiserResult<UIFilters> {
     let conn = open_connection(p)?;
-    let chan = stereo_choice.as_str().to_lowercase();
+    let chan = param_set(&conn, stereo_choice);
     let mut stmt =
         conn.prepare(format!("SELECT * FROM FILTERBANK WHERE stereo_choice='{}'", chan).as_str())?;
     let control_iter = stmt.query_map([], |row| {
@@ -219,7 +265,7 @@ pub fn update_ui_params(
     p: PathBuf,
 ) -> DenoiserResult<()> {
     let conn = open_connection(&p)?;
-    let st = stereo_choice.as_str().to_lowercase();
+    let st = param_set(&conn, stereo_choice);
 
     let mut filter_string = "".to_string();
     let bank = ui_params.filters.bank;
@@ -232,9 +278,9 @@ pub fn update_ui_params(
     }
     filter_string.pop();
 
-    let mut q = format!("UPDATE UI_PARAMS SET clean={}, left_mute={}, right_mute={}, output_gain={}, noise_gain={}, pre_smooth_gain={}, post_smooth_gain={}  WHERE stereo_choice='{}';
+    let mut q = format!("UPDATE UI_PARAMS SET clean={}, output_gain={}, noise_gain={}, pre_smooth_gain={}, post_smooth_gain={}  WHERE stereo_choice='{}';
         UPDATE FILTERBANK SET          
-        ",ui_params.clean, ui_params.left_mute, ui_params.right_mute, ui_params.output_gain, ui_params.noise_gain, ui_params.pre_smooth_gain, ui_params.post_smooth_gain, st);
+        ",ui_params.clean, ui_params.output_gain, ui_params.noise_gain, ui_params.pre_smooth_gain, ui_params.post_smooth_gain, st);
 
     let end_string = format!(" WHERE stereo_choice='{}'", st);
     q += filter_string.as_str();
@@ -269,7 +315,7 @@ pub fn update_filter_bank(
     p: PathBuf,
 ) -> DenoiserResult<()> {
     let conn = open_connection(&p)?;
-    let st = stereo_choice.as_str().to_lowercase();
+    let st = param_set(&conn, stereo_choice);
     let q = format!(
         "UPDATE FILTERBANK SET 
         bpf_gain_{} = {}, bpf_freq_{} = {}, bpf_q_{} = {}  
@@ -307,7 +353,7 @@ pub fn update_output_gain(
     p: PathBuf,
 ) -> DenoiserResult<()> {
     let conn = open_connection(&p)?;
-    let st = stereo_choice.as_str().to_lowercase();
+    let st = param_set(&conn, stereo_choice);
     let q = format!(
         "UPDATE UI_PARAMS SET output_gain={} WHERE stereo_choice='{}';
         ",
@@ -342,7 +388,7 @@ pub fn update_noise_gain(
     p: PathBuf,
 ) -> DenoiserResult<()> {
     let conn = open_connection(&p)?;
-    let st = stereo_choice.as_str().to_lowercase();
+    let st = param_set(&conn, stereo_choice);
     let q = format!(
         "UPDATE UI_PARAMS SET noise_gain={} WHERE stereo_choice='{}';
         ",
@@ -377,7 +423,7 @@ pub fn update_pre_smooth_gain(
     p: PathBuf,
 ) -> DenoiserResult<()> {
     let conn = open_connection(&p)?;
-    let st = stereo_choice.as_str().to_lowercase();
+    let st = param_set(&conn, stereo_choice);
     let q = format!(
         "UPDATE UI_PARAMS SET pre_smooth_gain={} WHERE stereo_choice='{}';
         ",
@@ -412,7 +458,7 @@ pub fn update_post_smooth_gain(
     p: PathBuf,
 ) -> DenoiserResult<()> {
     let conn = open_connection(&p)?;
-    let st = stereo_choice.as_str().to_lowercase();
+    let st = param_set(&conn, stereo_choice);
     let q = format!(
         "UPDATE UI_PARAMS SET post_smooth_gain={} WHERE stereo_choice='{}';
         ",
@@ -443,7 +489,7 @@ pub fn sql_update_post_smooth_gain(
 
 pub fn update_clean(stereo_choice: StereoChoice, clean: bool, p: PathBuf) -> DenoiserResult<()> {
     let conn = open_connection(&p)?;
-    let st = stereo_choice.as_str().to_lowercase();
+    let st = param_set(&conn, stereo_choice);
     let q = format!(
         "UPDATE UI_PARAMS SET clean={} WHERE stereo_choice='{}';
         ",
@@ -472,17 +518,291 @@ pub fn sql_update_clean(
     Ok(q)
 }
 
-pub fn update_left_mute(
+/// channels past the first two that have rows of their own
+pub fn query_extra_channels(p: &PathBuf) -> DenoiserResult<Vec<StereoChoice>> {
+    let conn = open_connection(p)?;
+    let mut stmt =
+        conn.prepare("SELECT stereo_choice FROM UI_PARAMS WHERE stereo_choice LIKE 'channel%'")?;
+    let rows = stmt.query_map([], |row| row.get::<_, String>(0))?;
+    Ok(rows
+        .filter_map(|r| r.ok()?.strip_prefix("channel")?.parse().ok())
+        .map(StereoChoice::Channel)
+        .collect())
+}
+
+/// the mute belongs to the channel, so it's kept in the independent rows whatever the stereo mode
+fn mute_row(conn: &Connection, stereo_choice: StereoChoice) -> String {
+    // makes the rows of a channel past the first two
+    param_set(conn, stereo_choice);
+    StereoMode::Independent.param_set(stereo_choice)
+}
+
+pub fn query_mute(stereo_choice: StereoChoice, p: &PathBuf) -> DenoiserResult<bool> {
+    let conn = open_connection(p)?;
+    let st = mute_row(&conn, stereo_choice);
+    let mute = conn.query_row(
+        format!("SELECT mute FROM UI_PARAMS WHERE stereo_choice='{}'", st).as_str(),
+        [],
+        |row| row.get::<_, Option<bool>>(0),
+    )?;
+    Ok(mute.unwrap_or(false))
+}
+
+#[tauri::command]
+pub fn sql_mute(stereo_choice: StereoChoice, app_handle: AppHandle) -> DenoiserResult<bool> {
+    let p = app_handle
+        .path_resolver()
+        .app_local_data_dir()
+        .expect("app local data dir should exist")
+        .join("db.sqlite");
+
+    let q = query_mute(stereo_choice, &p)?;
+    Ok(q)
+}
+
+pub fn update_mute(stereo_choice: StereoChoice, mute: bool, p: PathBuf) -> DenoiserResult<()> {
+    let conn = open_connection(&p)?;
+    let st = mute_row(&conn, stereo_choice);
+    let q = format!(
+        "UPDATE UI_PARAMS SET mute={} WHERE stereo_choice='{}';
+        ",
+        mute, st
+    );
+
+    conn.execute_batch(q.as_str())?;
+
+    Ok(())
+}
+
+#[tauri::command]
+pub fn sql_update_mute(
+    stereo_choice: StereoChoice,
+    mute: bool,
+    app_handle: AppHandle,
+) -> DenoiserResult<()> {
+    let p = app_handle
+        .path_resolver()
+        .app_local_data_dir()
+        .expect("app local data dir should exist")
+        .join("db.sqlite");
+
+    let q = update_mute(stereo_choice, mute, p)?;
+    Ok(q)
+}
+
+pub fn query_gain_smoothing(
+    stereo_choice: StereoChoice,
+    p: &PathBuf,
+) -> DenoiserResult<GainSmoothing> {
+    let conn = open_connection(p)?;
+    let chan = param_set(&conn, stereo_choice);
+    let mut stmt = conn.prepare(
+        format!(
+            "SELECT * FROM GAIN_SMOOTHING WHERE stereo_choice='{}'",
+            chan
+        )
+        .as_str(),
+    )?;
+    let control_iter = stmt.query_map([], |row| {
+        Ok(GainSmoothing {
+            enabled: row.get(2)?,
+            attack: row.get(3)?,
+            release: row.get(4)?,
+            freq_width: row.get(5)?,
+            floor: row.get(6)?,
+        })
+    })?;
+
+    for control in control_iter {
+        if let Ok(sett) = control {
+            return Ok(sett);
+        }
+    }
+    return Err(rusqlite::Error::InvalidQuery.into());
+}
+
+#[tauri::command]
+pub fn sql_gain_smoothing(
+    stereo_choice: StereoChoice,
+    app_handle: AppHandle,
+) -> DenoiserResult<GainSmoothing> {
+    let p = app_handle
+        .path_resolver()
+        .app_local_data_dir()
+        .expect("app local data dir should exist")
+        .join("db.sqlite");
+
+    let q = query_gain_smoothing(stereo_choice, &p)?;
+
+    Ok(q)
+}
+
+pub fn update_gain_smoothing(
+    stereo_choice: StereoChoice,
+    gain_smoothing: GainSmoothing,
+    p: PathBuf,
+) -> DenoiserResult<()> {
+    let conn = open_connection(&p)?;
+    let st = param_set(&conn, stereo_choice);
+    let q = format!(
+        "UPDATE GAIN_SMOOTHING SET enabled={}, attack={}, release={}, freq_width={}, floor={} WHERE stereo_choice='{}';
+        ",
+        gain_smoothing.enabled,
+        gain_smoothing.attack,
+        gain_smoothing.release,
+        gain_smoothing.freq_width,
+        gain_smoothing.floor,
+        st
+    );
+
+    conn.execute_batch(q.as_str())?;
+
+    Ok(())
+}
+
+#[tauri::command]
+pub fn sql_update_gain_smoothing(
+    stereo_choice: StereoChoice,
+    gain_smoothing: GainSmoothing,
+    app_handle: AppHandle,
+) -> DenoiserResult<()> {
+    let p = app_handle
+        .path_resolver()
+        .app_local_data_dir()
+        .expect("app local data dir should exist")
+        .join("db.sqlite");
+
+    let q = update_gain_smoothing(stereo_choice, gain_smoothing, p)?;
+
+    Ok(q)
+}
+
+pub fn query_hum(stereo_choice: StereoChoice, p: &PathBuf) -> DenoiserResult<HumParams> {
+    let conn = open_connection(p)?;
+    let chan = param_set(&conn, stereo_choice);
+    let mut stmt =
+        conn.prepare(format!("SELECT * FROM HUM WHERE stereo_choice='{}'", chan).as_str())?;
+    let control_iter = stmt.query_map([], |row| {
+        Ok(HumParams {
+            enabled: row.get(2)?,
+            fundamental: row.get(3)?,
+            freq: row.get(4)?,
+            harmonics: row.get(5)?,
+            Q: row.get(6)?,
+            track: row.get(7)?,
+        })
+    })?;
+
+    for control in control_iter {
+        if let Ok(sett) = control {
+            return Ok(sett);
+        }
+    }
+    return Err(rusqlite::Error::InvalidQuery.into());
+}
+
+#[tauri::command]
+pub fn sql_hum(stereo_choice: StereoChoice, app_handle: AppHandle) -> DenoiserResult<HumParams> {
+    let p = app_handle
+        .path_resolver()
+        .app_local_data_dir()
+        .expect("app local data dir should exist")
+        .join("db.sqlite");
+
+    let q = query_hum(stereo_choice, &p)?;
+
+    Ok(q)
+}
+
+pub fn update_hum(
+    stereo_choice: StereoChoice,
+    hum_params: HumParams,
+    p: PathBuf,
+) -> DenoiserResult<()> {
+    let conn = open_connection(&p)?;
+    let st = param_set(&conn, stereo_choice);
+    let q = format!(
+        "UPDATE HUM SET enabled={}, fundamental='{}', freq={}, harmonics={}, Q={}, track={} WHERE stereo_choice='{}';
+        ",
+        hum_params.enabled,
+        hum_params.fundamental.as_str(),
+        hum_params.freq,
+        hum_params.harmonics,
+        hum_params.Q,
+        hum_params.track,
+        st
+    );
+
+    conn.execute_batch(q.as_str())?;
+
+    Ok(())
+}
+
+#[tauri::command]
+pub fn sql_update_hum(
+    stereo_choice: StereoChoice,
+    hum_params: HumParams,
+    app_handle: AppHandle,
+) -> DenoiserResult<()> {
+    let p = app_handle
+        .path_resolver()
+        .app_local_data_dir()
+        .expect("app local data dir should exist")
+        .join("db.sqlite");
+
+    let q = update_hum(stereo_choice, hum_params, p)?;
+
+    Ok(q)
+}
+
+pub fn query_declick(stereo_choice: StereoChoice, p: &PathBuf) -> DenoiserResult<DeclickParams> {
+    let conn = open_connection(p)?;
+    let chan = param_set(&conn, stereo_choice);
+    let mut stmt =
+        conn.prepare(format!("SELECT * FROM DECLICK WHERE stereo_choice='{}'", chan).as_str())?;
+    let control_iter = stmt.query_map([], |row| {
+        Ok(DeclickParams {
+            enabled: row.get(2)?,
+            sensitivity: row.get(3)?,
+            max_click_length: row.get(4)?,
+        })
+    })?;
+
+    for control in control_iter {
+        if let Ok(sett) = control {
+            return Ok(sett);
+        }
+    }
+    return Err(rusqlite::Error::InvalidQuery.into());
+}
+
+#[tauri::command]
+pub fn sql_declick(
+    stereo_choice: StereoChoice,
+    app_handle: AppHandle,
+) -> DenoiserResult<DeclickParams> {
+    let p = app_handle
+        .path_resolver()
+        .app_local_data_dir()
+        .expect("app local data dir should exist")
+        .join("db.sqlite");
+
+    let q = query_declick(stereo_choice, &p)?;
+
+    Ok(q)
+}
+
+pub fn update_declick(
     stereo_choice: StereoChoice,
-    left_mute: bool,
+    declick_params: DeclickParams,
     p: PathBuf,
 ) -> DenoiserResult<()> {
     let conn = open_connection(&p)?;
-    let st = stereo_choice.as_str().to_lowercase();
+    let st = param_set(&conn, stereo_choice);
     let q = format!(
-        "UPDATE UI_PARAMS SET left_mute={} WHERE stereo_choice='{}';
+        "UPDATE DECLICK SET enabled={}, sensitivity={}, max_click_length={} WHERE stereo_choice='{}';
         ",
-        left_mute, st
+        declick_params.enabled, declick_params.sensitivity, declick_params.max_click_length, st
     );
 
     conn.execute_batch(q.as_str())?;
@@ -491,9 +811,9 @@ pub fn update_left_mute(
 }
 
 #[tauri::command]
-pub fn sql_update_left_mute(
+pub fn sql_update_declick(
     stereo_choice: StereoChoice,
-    left_mute: bool,
+    declick_params: DeclickParams,
     app_handle: AppHandle,
 ) -> DenoiserResult<()> {
     let p = app_handle
@@ -502,22 +822,67 @@ pub fn sql_update_left_mute(
         .expect("app local data dir should exist")
         .join("db.sqlite");
 
-    let q = update_left_mute(stereo_choice, left_mute, p)?;
+    let q = update_declick(stereo_choice, declick_params, p)?;
+
+    Ok(q)
+}
+
+pub fn query_vad(stereo_choice: StereoChoice, p: &PathBuf) -> DenoiserResult<VadParams> {
+    let conn = open_connection(p)?;
+    let chan = param_set(&conn, stereo_choice);
+    let mut stmt =
+        conn.prepare(format!("SELECT * FROM VAD WHERE stereo_choice='{}'", chan).as_str())?;
+    let control_iter = stmt.query_map([], |row| {
+        Ok(VadParams {
+            enabled: row.get(2)?,
+            threshold: row.get(3)?,
+            flatness: row.get(4)?,
+            hangover: row.get(5)?,
+            learn_noise: row.get(6)?,
+            learn_time: row.get(7)?,
+            speech_strength: row.get(8)?,
+        })
+    })?;
+
+    for control in control_iter {
+        if let Ok(sett) = control {
+            return Ok(sett);
+        }
+    }
+    return Err(rusqlite::Error::InvalidQuery.into());
+}
+
+#[tauri::command]
+pub fn sql_vad(stereo_choice: StereoChoice, app_handle: AppHandle) -> DenoiserResult<VadParams> {
+    let p = app_handle
+        .path_resolver()
+        .app_local_data_dir()
+        .expect("app local data dir should exist")
+        .join("db.sqlite");
+
+    let q = query_vad(stereo_choice, &p)?;
 
     Ok(q)
 }
 
-pub fn update_right_mute(
+pub fn update_vad(
     stereo_choice: StereoChoice,
-    right_mute: bool,
+    vad_params: VadParams,
     p: PathBuf,
 ) -> DenoiserResult<()> {
     let conn = open_connection(&p)?;
-    let st = stereo_choice.as_str().to_lowercase();
+    let st = param_set(&conn, stereo_choice);
     let q = format!(
-        "UPDATE UI_PARAMS SET right_mute={} WHERE stereo_choice='{}';
+        "UPDATE VAD SET enabled={}, threshold={}, flatness={}, hangover={}, learn_noise={}, learn_time={}, speech_strength={} WHERE stereo_choice='{}';
         ",
-        right_mute, st
+        vad_params.enabled,
+        vad_params.threshold,
+        vad_params.flatness,
+        vad_params.hangover,
+        vad_params.learn_noise,
+        vad_params.learn_time,
+        vad_params.speech_strength,
+        st
     );
 
     conn.execute_batch(q.as_str())?;
@@ -526,9 +891,9 @@ pub fn update_right_mute(
 }
 
 #[tauri::command]
-pub fn sql_update_right_mute(
+pub fn sql_update_vad(
     stereo_choice: StereoChoice,
-    right_mute: bool,
+    vad_params: VadParams,
     app_handle: AppHandle,
 ) -> DenoiserResult<()> {
     let p = app_handle
@@ -537,10 +902,602 @@ pub fn sql_update_right_mute(
         .expect("app local data dir should exist")
         .join("db.sqlite");
 
-    let q = update_right_mute(stereo_choice, right_mute, p)?;
+    let q = update_vad(stereo_choice, vad_params, p)?;
+
+    Ok(q)
+}
+
+pub fn query_declip(stereo_choice: StereoChoice, p: &PathBuf) -> DenoiserResult<DeclipParams> {
+    let conn = open_connection(p)?;
+    let chan = param_set(&conn, stereo_choice);
+    let mut stmt =
+        conn.prepare(format!("SELECT * FROM DECLIP WHERE stereo_choice='{}'", chan).as_str())?;
+    let control_iter = stmt.query_map([], |row| {
+        Ok(DeclipParams {
+            enabled: row.get(2)?,
+            threshold: row.get(3)?,
+            min_run: row.get(4)?,
+        })
+    })?;
+
+    for control in control_iter {
+        if let Ok(sett) = control {
+            return Ok(sett);
+        }
+    }
+    return Err(rusqlite::Error::InvalidQuery.into());
+}
+
+#[tauri::command]
+pub fn sql_declip(
+    stereo_choice: StereoChoice,
+    app_handle: AppHandle,
+) -> DenoiserResult<DeclipParams> {
+    let p = app_handle
+        .path_resolver()
+        .app_local_data_dir()
+        .expect("app local data dir should exist")
+        .join("db.sqlite");
+
+    let q = query_declip(stereo_choice, &p)?;
+
     Ok(q)
 }
 
+pub fn update_declip(
+    stereo_choice: StereoChoice,
+    declip_params: DeclipParams,
+    p: PathBuf,
+) -> DenoiserResult<()> {
+    let conn = open_connection(&p)?;
+    let st = param_set(&conn, stereo_choice);
+    let q = format!(
+        "UPDATE DECLIP SET enabled={}, threshold={}, min_run={} WHERE stereo_choice='{}';
+        ",
+        declip_params.enabled, declip_params.threshold, declip_params.min_run, st
+    );
+
+    conn.execute_batch(q.as_str())?;
+
+    Ok(())
+}
+
+#[tauri::command]
+pub fn sql_update_declip(
+    stereo_choice: StereoChoice,
+    declip_params: DeclipParams,
+    app_handle: AppHandle,
+) -> DenoiserResult<()> {
+    let p = app_handle
+        .path_resolver()
+        .app_local_data_dir()
+        .expect("app local data dir should exist")
+        .join("db.sqlite");
+
+    let q = update_declip(stereo_choice, declip_params, p)?;
+
+    Ok(q)
+}
+
+pub fn query_spectral_gate(
+    stereo_choice: StereoChoice,
+    p: &PathBuf,
+) -> DenoiserResult<SpectralGate> {
+    let conn = open_connection(p)?;
+    let chan = param_set(&conn, stereo_choice);
+    let mut stmt = conn
+        .prepare(format!("SELECT * FROM SPECTRAL_GATE WHERE stereo_choice='{}'", chan).as_str())?;
+    let control_iter = stmt.query_map([], |row| {
+        Ok(SpectralGate {
+            enabled: row.get(2)?,
+            margin: row.get(3)?,
+            reduction: row.get(4)?,
+            ratio: row.get(5)?,
+            attack: row.get(6)?,
+            hold: row.get(7)?,
+            release: row.get(8)?,
+        })
+    })?;
+
+    for control in control_iter {
+        if let Ok(sett) = control {
+            return Ok(sett);
+        }
+    }
+    return Err(rusqlite::Error::InvalidQuery.into());
+}
+
+#[tauri::command]
+pub fn sql_spectral_gate(
+    stereo_choice: StereoChoice,
+    app_handle: AppHandle,
+) -> DenoiserResult<SpectralGate> {
+    let p = app_handle
+        .path_resolver()
+        .app_local_data_dir()
+        .expect("app local data dir should exist")
+        .join("db.sqlite");
+
+    let q = query_spectral_gate(stereo_choice, &p)?;
+
+    Ok(q)
+}
+
+pub fn update_spectral_gate(
+    stereo_choice: StereoChoice,
+    spectral_gate: SpectralGate,
+    p: PathBuf,
+) -> DenoiserResult<()> {
+    let conn = open_connection(&p)?;
+    let st = param_set(&conn, stereo_choice);
+    let q = format!(
+        "UPDATE SPECTRAL_GATE SET enabled={}, margin={}, reduction={}, ratio={}, attack={}, hold={}, release={} WHERE stereo_choice='{}';
+        ",
+        spectral_gate.enabled,
+        spectral_gate.margin,
+        spectral_gate.reduction,
+        spectral_gate.ratio,
+        spectral_gate.attack,
+        spectral_gate.hold,
+        spectral_gate.release, st
+    );
+
+    conn.execute_batch(q.as_str())?;
+
+    Ok(())
+}
+
+#[tauri::command]
+pub fn sql_update_spectral_gate(
+    stereo_choice: StereoChoice,
+    spectral_gate: SpectralGate,
+    app_handle: AppHandle,
+) -> DenoiserResult<()> {
+    let p = app_handle
+        .path_resolver()
+        .app_local_data_dir()
+        .expect("app local data dir should exist")
+        .join("db.sqlite");
+
+    let q = update_spectral_gate(stereo_choice, spectral_gate, p)?;
+
+    Ok(q)
+}
+
+/// only one row, the canceller always works on both channels
+pub fn query_adaptive_cancel(p: &PathBuf) -> DenoiserResult<AdaptiveCancel> {
+    let conn = open_connection(p)?;
+    let mut stmt = conn.prepare("SELECT * FROM ADAPTIVE_CANCEL")?;
+    let control_iter = stmt.query_map([], |row| {
+        Ok(AdaptiveCancel {
+            enabled: row.get(1)?,
+            reference: row.get(2)?,
+            filter_length: row.get(3)?,
+            step_size: row.get(4)?,
+        })
+    })?;
+
+    for control in control_iter {
+        if let Ok(sett) = control {
+            return Ok(sett);
+        }
+    }
+    return Err(rusqlite::Error::InvalidQuery.into());
+}
+
+#[tauri::command]
+pub fn sql_adaptive_cancel(app_handle: AppHandle) -> DenoiserResult<AdaptiveCancel> {
+    let p = app_handle
+        .path_resolver()
+        .app_local_data_dir()
+        .expect("app local data dir should exist")
+        .join("db.sqlite");
+
+    let q = query_adaptive_cancel(&p)?;
+
+    Ok(q)
+}
+
+pub fn update_adaptive_cancel(adaptive_cancel: AdaptiveCancel, p: PathBuf) -> DenoiserResult<()> {
+    let conn = open_connection(&p)?;
+    let q = format!(
+        "UPDATE ADAPTIVE_CANCEL SET enabled={}, reference='{}', filter_length={}, step_size={};
+        ",
+        adaptive_cancel.enabled,
+        adaptive_cancel.reference.as_str(),
+        adaptive_cancel.filter_length,
+        adaptive_cancel.step_size
+    );
+
+    conn.execute_batch(q.as_str())?;
+
+    Ok(())
+}
+
+#[tauri::command]
+pub fn sql_update_adaptive_cancel(
+    adaptive_cancel: AdaptiveCancel,
+    app_handle: AppHandle,
+) -> DenoiserResult<()> {
+    let p = app_handle
+        .path_resolver()
+        .app_local_data_dir()
+        .expect("app local data dir should exist")
+        .join("db.sqlite");
+
+    let q = update_adaptive_cancel(adaptive_cancel, p)?;
+
+    Ok(q)
+}
+
+pub fn query_stereo_mode(p: &PathBuf) -> DenoiserResult<StereoModeParams> {
+    let conn = open_connection(p)?;
+    let mut stmt = conn.prepare("SELECT * FROM STEREO_MODE")?;
+    let control_iter = stmt.query_map([], |row| {
+        Ok(StereoModeParams {
+            mode: row.get(1)?,
+            link: row.get(2)?,
+        })
+    })?;
+
+    for control in control_iter {
+        if let Ok(sett) = control {
+            return Ok(sett);
+        }
+    }
+    return Err(rusqlite::Error::InvalidQuery.into());
+}
+
+#[tauri::command]
+pub fn sql_stereo_mode(app_handle: AppHandle) -> DenoiserResult<StereoModeParams> {
+    let p = app_handle
+        .path_resolver()
+        .app_local_data_dir()
+        .expect("app local data dir should exist")
+        .join("db.sqlite");
+
+    let q = query_stereo_mode(&p)?;
+
+    Ok(q)
+}
+
+/// changes which rows every other query reads from
+pub fn update_stereo_mode(stereo_mode: StereoModeParams, p: PathBuf) -> DenoiserResult<()> {
+    let conn = open_connection(&p)?;
+    let q = format!(
+        "UPDATE STEREO_MODE SET mode='{}', link='{}';
+        ",
+        stereo_mode.mode.as_str(),
+        stereo_mode.link.as_str()
+    );
+
+    conn.execute_batch(q.as_str())?;
+
+    Ok(())
+}
+
+#[tauri::command]
+pub fn sql_update_stereo_mode(
+    stereo_mode: StereoModeParams,
+    app_handle: AppHandle,
+) -> DenoiserResult<()> {
+    let p = app_handle
+        .path_resolver()
+        .app_local_data_dir()
+        .expect("app local data dir should exist")
+        .join("db.sqlite");
+
+    let q = update_stereo_mode(stereo_mode, p)?;
+
+    Ok(q)
+}
+
+pub fn query_normalize(p: &PathBuf) -> DenoiserResult<NormalizeParams> {
+    let conn = open_connection(p)?;
+    let mut stmt = conn.prepare("SELECT * FROM NORMALIZE")?;
+    let control_iter = stmt.query_map([], |row| {
+        Ok(NormalizeParams {
+            enabled: row.get(1)?,
+            target: row.get(2)?,
+            ceiling: row.get(3)?,
+        })
+    })?;
+
+    for control in control_iter {
+        if let Ok(sett) = control {
+            return Ok(sett);
+        }
+    }
+    return Err(rusqlite::Error::InvalidQuery.into());
+}
+
+#[tauri::command]
+pub fn sql_normalize(app_handle: AppHandle) -> DenoiserResult<NormalizeParams> {
+    let p = app_handle
+        .path_resolver()
+        .app_local_data_dir()
+        .expect("app local data dir should exist")
+        .join("db.sqlite");
+
+    let q = query_normalize(&p)?;
+
+    Ok(q)
+}
+
+pub fn update_normalize(normalize: NormalizeParams, p: PathBuf) -> DenoiserResult<()> {
+    let conn = open_connection(&p)?;
+    let q = format!(
+        "UPDATE NORMALIZE SET enabled={}, target={}, ceiling={};
+        ",
+        normalize.enabled, normalize.target, normalize.ceiling
+    );
+
+    conn.execute_batch(q.as_str())?;
+
+    Ok(())
+}
+
+#[tauri::command]
+pub fn sql_update_normalize(
+    normalize: NormalizeParams,
+    app_handle: AppHandle,
+) -> DenoiserResult<()> {
+    let p = app_handle
+        .path_resolver()
+        .app_local_data_dir()
+        .expect("app local data dir should exist")
+        .join("db.sqlite");
+
+    let q = update_normalize(normalize, p)?;
+
+    Ok(q)
+}
+
+pub fn query_ui_rate(p: &PathBuf) -> DenoiserResult<UIRate> {
+    let conn = open_connection(p)?;
+    let mut stmt = conn.prepare("SELECT * FROM UI_RATE")?;
+    let control_iter = stmt.query_map([], |row| Ok(UIRate { rate: row.get(1)? }))?;
+
+    for control in control_iter {
+        if let Ok(sett) = control {
+            return Ok(sett);
+        }
+    }
+    return Err(rusqlite::Error::InvalidQuery.into());
+}
+
+#[tauri::command]
+pub fn sql_ui_rate(app_handle: AppHandle) -> DenoiserResult<UIRate> {
+    let p = app_handle
+        .path_resolver()
+        .app_local_data_dir()
+        .expect("app local data dir should exist")
+        .join("db.sqlite");
+
+    let q = query_ui_rate(&p)?;
+
+    Ok(q)
+}
+
+pub fn update_ui_rate(ui_rate: UIRate, p: PathBuf) -> DenoiserResult<()> {
+    let conn = open_connection(&p)?;
+    let q = format!(
+        "UPDATE UI_RATE SET rate={};
+        ",
+        ui_rate.rate
+    );
+
+    conn.execute_batch(q.as_str())?;
+
+    Ok(())
+}
+
+#[tauri::command]
+pub fn sql_update_ui_rate(ui_rate: UIRate, app_handle: AppHandle) -> DenoiserResult<()> {
+    let p = app_handle
+        .path_resolver()
+        .app_local_data_dir()
+        .expect("app local data dir should exist")
+        .join("db.sqlite");
+
+    let q = update_ui_rate(ui_rate, p)?;
+
+    Ok(q)
+}
+
+pub fn query_mix(p: &PathBuf) -> DenoiserResult<MixParams> {
+    let conn = open_connection(p)?;
+    let mut stmt = conn.prepare("SELECT * FROM MIX")?;
+    let control_iter = stmt.query_map([], |row| Ok(MixParams { wet: row.get(1)? }))?;
+
+    for control in control_iter {
+        if let Ok(sett) = control {
+            return Ok(sett);
+        }
+    }
+    return Err(rusqlite::Error::InvalidQuery.into());
+}
+
+#[tauri::command]
+pub fn sql_mix(app_handle: AppHandle) -> DenoiserResult<MixParams> {
+    let p = app_handle
+        .path_resolver()
+        .app_local_data_dir()
+        .expect("app local data dir should exist")
+        .join("db.sqlite");
+
+    let q = query_mix(&p)?;
+
+    Ok(q)
+}
+
+pub fn update_mix(mix: MixParams, p: PathBuf) -> DenoiserResult<()> {
+    let conn = open_connection(&p)?;
+    let q = format!(
+        "UPDATE MIX SET wet={};
+        ",
+        mix.wet
+    );
+
+    conn.execute_batch(q.as_str())?;
+
+    Ok(())
+}
+
+#[tauri::command]
+pub fn sql_update_mix(mix: MixParams, app_handle: AppHandle) -> DenoiserResult<()> {
+    let p = app_handle
+        .path_resolver()
+        .app_local_data_dir()
+        .expect("app local data dir should exist")
+        .join("db.sqlite");
+
+    let q = update_mix(mix, p)?;
+
+    Ok(q)
+}
+
+/// tables added after the first release, create_db only runs when there is no db file
+/// so these are created here for dbs that already exist, safe to run on every startup
+pub fn update_db(p: &PathBuf) -> DenoiserResult<()> {
+    let conn = open_connection(p)?;
+
+    // one mute per channel took over from the left and right mutes, which are left unused
+    if conn.prepare("SELECT mute FROM UI_PARAMS").is_err() {
+        conn.execute_batch("ALTER TABLE UI_PARAMS ADD COLUMN mute BOOLEAN DEFAULT false;")?;
+    }
+
+    let mut stmt = "CREATE TABLE IF NOT EXISTS GAIN_SMOOTHING (
+  id INTEGER PRIMARY KEY,
+  stereo_choice TEXT NOT NULL,
+  enabled BOOLEAN,
+  attack REAL,
+  release REAL,
+  freq_width INTEGER,
+  floor REAL
+);
+CREATE TABLE IF NOT EXISTS HUM (
+  id INTEGER PRIMARY KEY,
+  stereo_choice TEXT NOT NULL,
+  enabled BOOLEAN,
+  fundamental TEXT NOT NULL,
+  freq REAL,
+  harmonics INTEGER,
+  Q REAL,
+  track BOOLEAN
+);
+CREATE TABLE IF NOT EXISTS DECLICK (
+  id INTEGER PRIMARY KEY,
+  stereo_choice TEXT NOT NULL,
+  enabled BOOLEAN,
+  sensitivity REAL,
+  max_click_length REAL
+);
+CREATE TABLE IF NOT EXISTS DECLIP (
+  id INTEGER PRIMARY KEY,
+  stereo_choice TEXT NOT NULL,
+  enabled BOOLEAN,
+  threshold REAL,
+  min_run INTEGER
+);
+CREATE TABLE IF NOT EXISTS VAD (
+  id INTEGER PRIMARY KEY,
+  stereo_choice TEXT NOT NULL,
+  enabled BOOLEAN,
+  threshold REAL,
+  flatness REAL,
+  hangover REAL,
+  learn_noise BOOLEAN,
+  learn_time REAL,
+  speech_strength REAL
+);
+CREATE TABLE IF NOT EXISTS SPECTRAL_GATE (
+  id INTEGER PRIMARY KEY,
+  stereo_choice TEXT NOT NULL,
+  enabled BOOLEAN,
+  margin REAL,
+  reduction REAL,
+  ratio REAL,
+  attack REAL,
+  hold REAL,
+  release REAL
+);
+CREATE TABLE IF NOT EXISTS ADAPTIVE_CANCEL (
+  id INTEGER PRIMARY KEY,
+  enabled BOOLEAN,
+  reference TEXT NOT NULL,
+  filter_length INTEGER,
+  step_size REAL
+);
+INSERT INTO ADAPTIVE_CANCEL (id, enabled, reference, filter_length, step_size)
+  SELECT 1, false, 'Right', 256, 0.1
+  WHERE NOT EXISTS (SELECT 1 FROM ADAPTIVE_CANCEL);
+CREATE TABLE IF NOT EXISTS STEREO_MODE (
+  id INTEGER PRIMARY KEY,
+  mode TEXT NOT NULL,
+  link TEXT NOT NULL
+);
+INSERT INTO STEREO_MODE (id, mode, link)
+  SELECT 1, 'Independent', 'Max'
+  WHERE NOT EXISTS (SELECT 1 FROM STEREO_MODE);
+CREATE TABLE IF NOT EXISTS NORMALIZE (
+  id INTEGER PRIMARY KEY,
+  enabled BOOLEAN,
+  target REAL,
+  ceiling REAL
+);
+INSERT INTO NORMALIZE (id, enabled, target, ceiling)
+  SELECT 1, false, -23.0, -1.0
+  WHERE NOT EXISTS (SELECT 1 FROM NORMALIZE);
+CREATE TABLE IF NOT EXISTS UI_RATE (
+  id INTEGER PRIMARY KEY,
+  rate REAL
+);
+INSERT INTO UI_RATE (id, rate)
+  SELECT 1, 30.0
+  WHERE NOT EXISTS (SELECT 1 FROM UI_RATE);
+CREATE TABLE IF NOT EXISTS MIX (
+  id INTEGER PRIMARY KEY,
+  wet REAL
+);
+INSERT INTO MIX (id, wet)
+  SELECT 1, 1.0
+  WHERE NOT EXISTS (SELECT 1 FROM MIX);
+"
+    .to_string();
+    for st in ["left", "right", "both"] {
+        stmt += format!(
+            "INSERT INTO GAIN_SMOOTHING (stereo_choice, enabled, attack, release, freq_width, floor)
+  SELECT '{st}', false, 5.0, 50.0, 1, -30.0
+  WHERE NOT EXISTS (SELECT 1 FROM GAIN_SMOOTHING WHERE stereo_choice='{st}');
+INSERT INTO HUM (stereo_choice, enabled, fundamental, freq, harmonics, Q, track)
+  SELECT '{st}', false, 'Auto', 50.0, 8, 30.0, true
+  WHERE NOT EXISTS (SELECT 1 FROM HUM WHERE stereo_choice='{st}');
+INSERT INTO DECLICK (stereo_choice, enabled, sensitivity, max_click_length)
+  SELECT '{st}', false, 0.5, 1.0
+  WHERE NOT EXISTS (SELECT 1 FROM DECLICK WHERE stereo_choice='{st}');
+INSERT INTO DECLIP (stereo_choice, enabled, threshold, min_run)
+  SELECT '{st}', false, 0.99, 3
+  WHERE NOT EXISTS (SELECT 1 FROM DECLIP WHERE stereo_choice='{st}');
+INSERT INTO VAD (stereo_choice, enabled, threshold, flatness, hangover, learn_noise, learn_time, speech_strength)
+  SELECT '{st}', false, 6.0, 0.3, 200.0, true, 500.0, 1.0
+  WHERE NOT EXISTS (SELECT 1 FROM VAD WHERE stereo_choice='{st}');
+INSERT INTO SPECTRAL_GATE (stereo_choice, enabled, margin, reduction, ratio, attack, hold, release)
+  SELECT '{st}', false, 6.0, -24.0, 4.0, 2.0, 20.0, 80.0
+  WHERE NOT EXISTS (SELECT 1 FROM SPECTRAL_GATE WHERE stereo_choice='{st}');
+"
+        )
+        .as_str();
+    }
+
+    // linked and mid/side start from a copy of the params for both channels
+    for st in ["linked", "mid", "side", "midside"] {
+        stmt += copy_both_rows(st).as_str();
+    }
+    conn.execute_batch(stmt.as_str())?;
+    Ok(())
+}
+
 pub fn create_db(p: PathBuf) -> DenoiserResult<()> {
     let conn = open_connection(&p)?;
 
@@ -0,0 +1,281 @@
+use serde::{Deserialize, Serialize};
+use std::f32::consts::FRAC_PI_2;
+use ts_rs::TS;
+
+use crate::types::SpeedParams;
+
+/// Hz, how often the position is sent to the ui
+pub const POSITION_RATE: f32 = 20.0;
+/// ms, crossfade at the loop point, shorter when there isn't room before the loop start
+pub const LOOP_FADE_MS: f32 = 10.0;
+
+#[derive(Clone, Copy, Debug, Serialize, Deserialize, PartialEq, TS)]
+#[ts(export)]
+pub enum TransportState {
+    /// at the start, or wherever the last seek went, playing picks up from there
+    Stopped,
+    Playing,
+    Paused,
+}
+
+/// what playback does when it reaches the end of the file
+#[derive(Clone, Copy, Debug, Serialize, Deserialize, PartialEq, TS)]
+#[ts(export)]
+pub enum EndAction {
+    /// stop and go back to the start
+    Stop,
+    /// carry on from the start without resetting the processing
+    Loop,
+}
+
+/// a position in the file, frames are samples of one channel so mono and stereo count the same
+#[derive(Clone, Copy, Debug, Serialize, Deserialize, PartialEq, TS)]
+#[ts(export)]
+pub enum Seek {
+    Seconds(f32),
+    Frames(usize),
+}
+
+/// sent to the callback, applied in the order they were sent
+#[derive(Clone, Copy, Debug, Serialize, Deserialize, PartialEq, TS)]
+#[ts(export)]
+pub enum TransportCommand {
+    Play,
+    Pause,
+    /// pause and go back to the start
+    Stop,
+    Seek(Seek),
+    EndAction(EndAction),
+    /// start and end, playback jumps to the start and wraps inside the region, None plays on from wherever it is
+    Loop(Option<(Seek, Seek)>),
+    /// rate and whether the pitch follows it, the processing doesn't change
+    Speed(SpeedParams),
+}
+
+/// sent at POSITION_RATE
+#[derive(Clone, Copy, Debug, Serialize, Deserialize, PartialEq)]
+pub struct PositionMessage {
+    pub state: TransportState,
+    pub frame: usize,
+    pub seconds: f32,
+    /// s, length of the file
+    pub duration: f32,
+    /// s, start and end of the loop, rounded to the frames that are actually played
+    pub loop_region: Option<(f32, f32)>,
+}
+
+impl PositionMessage {
+    pub fn name() -> &'static str {
+        "position_message"
+    }
+}
+
+/// sent once each time playback reaches the end of the file
+#[derive(Clone, Copy, Debug, Serialize, Deserialize, PartialEq)]
+pub struct EndMessage {
+    /// what playback did about it
+    pub action: EndAction,
+}
+
+impl EndMessage {
+    pub fn name() -> &'static str {
+        "end_message"
+    }
+}
+
+/// frames start..end repeat, the last fade frames are crossfaded with the ones leading up to start
+/// so the input is continuous at the jump and the processing state carries over without a reset
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct LoopRegion {
+    pub start: usize,
+    pub end: usize,
+    pub fade: usize,
+}
+
+impl LoopRegion {
+    /// fade is in frames, as long as asked for if there is room before start and in the region
+    pub fn new(start: usize, end: usize, fade: usize) -> Self {
+        Self {
+            start,
+            end,
+            fade: fade.min(start).min((end - start) / 2),
+        }
+    }
+
+    /// the frame mixed in with frame and the gains of both, equal power since the two are unrelated
+    pub fn blend(&self, frame: usize) -> Option<(usize, f32, f32)> {
+        let fade_start = self.end - self.fade;
+        if frame < fade_start || frame >= self.end {
+            return None;
+        }
+        let i = frame - fade_start;
+        let t = (i as f32 + 0.5) / self.fade as f32 * FRAC_PI_2;
+        Some((self.start - self.fade + i, t.cos(), t.sin()))
+    }
+}
+
+/// what the callback reads for one output frame
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Playhead {
+    pub frame: usize,
+    /// another frame to mix in near the loop point, and the gains of frame and that one
+    pub blend: Option<(usize, f32, f32)>,
+}
+
+impl Playhead {
+    /// write the frame, blended if it's in a crossfade, out holds num_channels samples
+    pub fn read(&self, samples: &[f32], out: &mut [f32]) {
+        let n = out.len();
+        let frame = &samples[self.frame * n..(self.frame + 1) * n];
+        match self.blend {
+            None => out.copy_from_slice(frame),
+            Some((other, gain, other_gain)) => {
+                let other = &samples[other * n..(other + 1) * n];
+                for ((o, x), y) in out.iter_mut().zip(frame).zip(other) {
+                    *o = x * gain + y * other_gain;
+                }
+            }
+        }
+    }
+}
+
+/// playback state and position, owned by the callback
+#[derive(Clone, Debug)]
+pub struct Transport {
+    pub state: TransportState,
+    pub end_action: EndAction,
+    /// next frame to play
+    pub frame: usize,
+    pub num_frames: usize,
+    pub sample_rate: f32,
+    pub loop_region: Option<LoopRegion>,
+    pub speed: SpeedParams,
+    /// times the position has jumped, anything buffered after the processing is stale when this changes
+    pub jumps: usize,
+    /// times playback has reached the end, the reporter sends an end message when this changes
+    pub ends: usize,
+}
+
+impl Transport {
+    pub fn new(num_frames: usize, sample_rate: f32) -> Self {
+        Self {
+            state: TransportState::Stopped,
+            end_action: EndAction::Stop,
+            frame: 0,
+            num_frames,
+            sample_rate,
+            loop_region: None,
+            speed: SpeedParams::new(),
+            jumps: 0,
+            ends: 0,
+        }
+    }
+
+    /// returns true when the position jumped, so processing state from before doesn't carry over
+    pub fn apply(&mut self, command: TransportCommand) -> bool {
+        use TransportCommand::*;
+        let jumped = match command {
+            Play => {
+                self.state = TransportState::Playing;
+                false
+            }
+            Pause => {
+                if self.state == TransportState::Playing {
+                    self.state = TransportState::Paused;
+                }
+                false
+            }
+            Stop => {
+                self.state = TransportState::Stopped;
+                self.frame = 0;
+                true
+            }
+            Seek(seek) => {
+                self.frame = self.to_frame(seek);
+                true
+            }
+            EndAction(action) => {
+                self.end_action = action;
+                false
+            }
+            Loop(region) => {
+                let fade = (LOOP_FADE_MS / 1000.0 * self.sample_rate) as usize;
+                self.loop_region = region
+                    .map(|(start, end)| (self.to_frame(start), self.to_frame(end)))
+                    .filter(|(start, end)| end > start)
+                    .map(|(start, end)| LoopRegion::new(start, end, fade));
+                if let Some(region) = self.loop_region {
+                    self.frame = region.start;
+                }
+                self.loop_region.is_some()
+            }
+            Speed(speed) => {
+                self.speed = speed;
+                false
+            }
+        };
+        if jumped {
+            self.jumps += 1;
+        }
+        jumped
+    }
+
+    /// clamped to the file, a seek to the very end plays nothing and ends
+    pub fn to_frame(&self, seek: Seek) -> usize {
+        let frame = match seek {
+            Seek::Seconds(s) => (s.max(0.0) * self.sample_rate).round() as usize,
+            Seek::Frames(f) => f,
+        };
+        frame.min(self.num_frames)
+    }
+
+    /// what to play now, then moves on one frame, None while not playing
+    pub fn advance(&mut self) -> Option<Playhead> {
+        if self.state != TransportState::Playing {
+            return None;
+        }
+        if let Some(region) = self.loop_region {
+            if self.frame >= region.end {
+                self.frame = region.start;
+            }
+        }
+        if self.frame >= self.num_frames {
+            self.ends += 1;
+            self.frame = 0;
+            if self.end_action == EndAction::Stop || self.num_frames == 0 {
+                self.state = TransportState::Stopped;
+                return None;
+            }
+        }
+        let frame = self.frame;
+        self.frame += 1;
+        Some(Playhead {
+            frame,
+            blend: self.loop_region.and_then(|r| r.blend(frame)),
+        })
+    }
+
+    pub fn seconds(&self) -> f32 {
+        self.frame as f32 / self.sample_rate
+    }
+
+    pub fn duration(&self) -> f32 {
+        self.num_frames as f32 / self.sample_rate
+    }
+
+    /// s, start and end of the loop
+    pub fn loop_seconds(&self) -> Option<(f32, f32)> {
+        self.loop_region.map(|r| {
+            (
+                r.start as f32 / self.sample_rate,
+                r.end as f32 / self.sample_rate,
+            )
+        })
+    }
+}
+
+impl Default for Transport {
+    fn default() -> Self {
+        Transport::new(0, crate::constants::SAMPLING_RATE)
+    }
+}
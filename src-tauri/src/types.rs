@@ -6,9 +6,18 @@ use std::{f32::consts::PI, sync::Mutex};
 use ts_rs::TS;
 
 use crate::{
+    compare::Monitor,
     constants::{CZERO, NUM_FILTERS, SAMPLING_RATE},
+    declick::{Declicker, DECLICK_HOP},
+    dehum::Dehum,
+    fourier::SpectrogramCache,
+    freq_scale::SPECTRUM_POINTS,
     messages::{AudioUIMessage, UIAudioMessage},
+    mix::Mix,
+    nlms::Nlms,
     sdft::SDFT,
+    transport::Transport,
+    vad::Vad,
 };
 
 // cpal stream
@@ -20,12 +29,22 @@ pub struct MAudioSender(pub Mutex<tauri::async_runtime::Sender<AudioUIMessage>>)
 // send message from ui to audio thread
 pub struct MSender(pub Mutex<tauri::async_runtime::Sender<UIAudioMessage>>);
 pub struct MStreamSend(pub Mutex<StreamSend>);
+// stft of recently drawn files for the spectrogram
+pub struct MSpectrogramCache(pub Mutex<SpectrogramCache>);
 
 pub struct StreamSend {
     pub stream: MStream,
     pub msender: MSender,
     pub mreceiver: MUIReceiver,
     pub mtx_ui: MAudioSender,
+    pub format: StreamFormat,
+}
+
+/// channels and rate of what the stream processes, buffers sent to it are built for these
+#[derive(Clone, Copy, Debug)]
+pub struct StreamFormat {
+    pub num_channels: usize,
+    pub sample_rate: f32,
 }
 unsafe impl Sync for MStream {}
 unsafe impl Send for MStream {}
@@ -110,6 +129,35 @@ impl From<BPF> for IIR2 {
 }
 
 impl IIR2 {
+    /// notch at freq, higher Q is narrower
+    pub fn notch(freq: f32, q: f32, sample_rate: f32) -> Self {
+        let w0 = (2.0 * PI * freq) / sample_rate;
+        let alpha = (w0).sin() / 2.0 / q;
+        Self {
+            b0: 1.0,
+            b1: -2.0 * w0.cos(),
+            b2: 1.0,
+            a0: 1.0 + alpha,
+            a1: -2.0 * w0.cos(),
+            a2: 1.0 - alpha,
+            x: [0.0, 0.0],
+            y: [0.0, 0.0],
+        }
+    }
+
+    /// direct form 1, one sample at a time
+    pub fn process(&mut self, x: f32) -> f32 {
+        let y = (self.b0 * x + self.b1 * self.x[0] + self.b2 * self.x[1]
+            - self.a1 * self.y[0]
+            - self.a2 * self.y[1])
+            / self.a0;
+        self.x[1] = self.x[0];
+        self.x[0] = x;
+        self.y[1] = self.y[0];
+        self.y[0] = y;
+        y
+    }
+
     pub fn new() -> Self {
         Self {
             b0: 1.0,
@@ -131,21 +179,15 @@ impl IIR2 {
         self.a2 = iir.a2;
     }
 
-    pub fn freq_response(&self, n: usize) -> Vec<Complex32> {
-        let mut H = vec![];
+    /// response at bin i of n, from dc up to nyquist
+    pub fn response_at(&self, i: usize, n: usize) -> Complex32 {
         let L = n as f32;
-        for i in 0..n {
-            let x = (-PI * i as f32 / L).cos();
-            let y = (-PI * i as f32 / L).sin();
-            let z = Complex32 { re: x, im: y };
-            let z2 = z * z;
-
-            let w = (self.b0 + self.b1 * z + self.b2 * z2) / (self.a0 + self.a1 * z + self.a2 * z2);
+        let x = (-PI * i as f32 / L).cos();
+        let y = (-PI * i as f32 / L).sin();
+        let z = Complex32 { re: x, im: y };
+        let z2 = z * z;
 
-            H.push(w);
-        }
-
-        H
+        (self.b0 + self.b1 * z + self.b2 * z2) / (self.a0 + self.a1 * z + self.a2 * z2)
     }
 }
 
@@ -176,23 +218,29 @@ impl Filters {
         Self { bank }
     }
     pub fn parallel_transfer(&self, n: usize) -> Vec<f32> {
-        let mut H: Vec<Complex32> = vec![CZERO; n];
+        let mut out = vec![0.0; n];
+        self.parallel_transfer_into(&mut out);
+        out
+    }
+
+    /// same as parallel_transfer, one value per element of out, for the audio thread
+    pub fn parallel_transfer_into(&self, out: &mut [f32]) {
+        let n = out.len();
         let l = Complex32 {
             re: NUM_FILTERS as f32,
             im: 0.0,
         };
-        // loop over all filters first
-        for (_i, filt) in self.bank.iter().enumerate() {
-            let h = filt.freq_response(n);
-            H.iter_mut().enumerate().for_each(|(i, x)| *x += h[i] / l);
+        for (i, o) in out.iter_mut().enumerate() {
+            // take norm after summing filters
+            let h = self
+                .bank
+                .iter()
+                .fold(CZERO, |h, filt| h + filt.response_at(i, n) / l);
+            *o = h.norm();
         }
-
-        // take norm after summing filters
-        let mut out: Vec<f32> = H.iter().map(|x| x.norm()).collect();
-        if out[0].is_nan() {
+        if out.first().is_some_and(|x| x.is_nan()) {
             out[0] = 0.0;
         }
-        out
     }
 }
 
@@ -227,12 +275,280 @@ impl Default for FiltersMessage {
     }
 }
 
-#[derive(Clone, Debug, Deserialize, Serialize, Copy, TS)]
+/// smoothing of the per-bin suppression gain, in time (attack/release) and across neighboring bins (median)
+#[derive(Clone, Copy, Serialize, Deserialize, Debug, TS)]
+#[ts(export)]
+pub struct GainSmoothing {
+    pub enabled: bool,
+    /// ms
+    pub attack: f32,
+    /// ms
+    pub release: f32,
+    /// number of bins on each side of the median
+    pub freq_width: usize,
+    /// dB, lowest gain any bin can be pulled down to
+    pub floor: f32,
+}
+
+impl GainSmoothing {
+    pub fn new() -> Self {
+        Self {
+            enabled: false,
+            attack: 5.0,
+            release: 50.0,
+            freq_width: 1,
+            floor: -30.0,
+        }
+    }
+
+    /// gain is used as computed, for linked stereo when gain smoothing is off
+    pub fn unsmoothed() -> Self {
+        Self {
+            enabled: true,
+            attack: 0.0,
+            release: 0.0,
+            freq_width: 0,
+            floor: -120.0,
+        }
+    }
+
+    /// one-pole coefficient for a time constant in ms
+    pub fn coeff(ms: f32, sample_rate: f32) -> f32 {
+        if ms <= 0.0 {
+            return 0.0;
+        }
+        (-1.0 / (ms * 0.001 * sample_rate)).exp()
+    }
+}
+
+impl Default for GainSmoothing {
+    fn default() -> Self {
+        GainSmoothing::new()
+    }
+}
+
+/// per-bin gate/expander keyed off the noise spectrum, an alternative to subtraction
+#[derive(Clone, Copy, Serialize, Deserialize, Debug, TS)]
+#[ts(export)]
+pub struct SpectralGate {
+    pub enabled: bool,
+    /// dB above the noise spectrum where a bin opens
+    pub margin: f32,
+    /// dB, gain of a closed bin
+    pub reduction: f32,
+    /// expansion below the threshold, 1 does nothing and large values act as a hard gate
+    pub ratio: f32,
+    /// ms
+    pub attack: f32,
+    /// ms, a bin stays open this long after dropping below the threshold
+    pub hold: f32,
+    /// ms
+    pub release: f32,
+}
+
+impl SpectralGate {
+    pub fn new() -> Self {
+        Self {
+            enabled: false,
+            margin: 6.0,
+            reduction: -24.0,
+            ratio: 4.0,
+            attack: 2.0,
+            hold: 20.0,
+            release: 80.0,
+        }
+    }
+
+    pub fn hold_samples(&self, sample_rate: f32) -> usize {
+        (self.hold.max(0.0) * 0.001 * sample_rate) as usize
+    }
+}
+
+impl Default for SpectralGate {
+    fn default() -> Self {
+        SpectralGate::new()
+    }
+}
+
+/// voice activity detection, the noise profile is only learned while nobody is talking
+#[derive(Clone, Copy, Serialize, Deserialize, Debug, TS)]
+#[ts(export)]
+pub struct VadParams {
+    pub enabled: bool,
+    /// dB above the noise floor where a frame counts as speech
+    pub threshold: f32,
+    /// 0 - 1, frames flatter than this are noise no matter how loud
+    pub flatness: f32,
+    /// ms, speech is held this long after the last speech frame so word endings aren't cut
+    pub hangover: f32,
+    /// learn the noise profile from non-speech frames instead of using the filters
+    pub learn_noise: bool,
+    /// ms, time constant of the learned profile
+    pub learn_time: f32,
+    /// 0 - 1, processing strength during speech, 1 doesn't duck
+    pub speech_strength: f32,
+}
+
+impl VadParams {
+    pub fn new() -> Self {
+        Self {
+            enabled: false,
+            threshold: 6.0,
+            flatness: 0.3,
+            hangover: 200.0,
+            learn_noise: true,
+            learn_time: 500.0,
+            speech_strength: 1.0,
+        }
+    }
+}
+
+impl Default for VadParams {
+    fn default() -> Self {
+        VadParams::new()
+    }
+}
+
+/// mains frequency, auto detects it from the spectrum of the file
+#[derive(Clone, Debug, Deserialize, Serialize, Copy, PartialEq, TS)]
+#[ts(export)]
+pub enum HumFundamental {
+    Auto,
+    Fifty,
+    Sixty,
+}
+
+impl FromSql for HumFundamental {
+    fn column_result(value: rusqlite::types::ValueRef<'_>) -> rusqlite::types::FromSqlResult<Self> {
+        use HumFundamental::*;
+        match value.as_str()? {
+            "Auto" => Ok(Auto),
+            "Fifty" => Ok(Fifty),
+            "Sixty" => Ok(Sixty),
+            _ => Err(rusqlite::types::FromSqlError::InvalidType),
+        }
+    }
+}
+
+impl HumFundamental {
+    pub fn as_str(&self) -> &str {
+        use HumFundamental::*;
+        match self {
+            Auto => "Auto",
+            Fifty => "Fifty",
+            Sixty => "Sixty",
+        }
+    }
+}
+
+/// comb of notches on the hum fundamental and its harmonics
+#[derive(Clone, Copy, Serialize, Deserialize, Debug, TS)]
+#[ts(export)]
+pub struct HumParams {
+    pub enabled: bool,
+    pub fundamental: HumFundamental,
+    /// Hz, the detected fundamental when using auto, otherwise ignored
+    pub freq: f32,
+    /// number of notches including the fundamental
+    pub harmonics: usize,
+    pub Q: f32,
+    /// follow small drifts of the fundamental while processing
+    pub track: bool,
+}
+
+impl HumParams {
+    pub fn new() -> Self {
+        Self {
+            enabled: false,
+            fundamental: HumFundamental::Auto,
+            freq: 50.0,
+            harmonics: 8,
+            Q: 30.0,
+            track: true,
+        }
+    }
+
+    /// frequency the notches start from
+    pub fn nominal(&self) -> f32 {
+        use HumFundamental::*;
+        match self.fundamental {
+            Auto => self.freq,
+            Fifty => 50.0,
+            Sixty => 60.0,
+        }
+    }
+}
+
+impl Default for HumParams {
+    fn default() -> Self {
+        HumParams::new()
+    }
+}
+
+/// click and crackle repair, runs before everything else in the chain
+#[derive(Clone, Copy, Serialize, Deserialize, Debug, TS)]
+#[ts(export)]
+pub struct DeclickParams {
+    pub enabled: bool,
+    /// 0 - 1
+    pub sensitivity: f32,
+    /// ms, anything longer is left alone, clamped to a few ms
+    pub max_click_length: f32,
+}
+
+impl DeclickParams {
+    pub fn new() -> Self {
+        Self {
+            enabled: false,
+            sensitivity: 0.5,
+            max_click_length: 1.0,
+        }
+    }
+}
+
+impl Default for DeclickParams {
+    fn default() -> Self {
+        DeclickParams::new()
+    }
+}
+
+/// reconstruction of clipped peaks, done on the whole file when it's loaded
+/// repaired peaks go over full scale so the output gain may need to come down
+#[derive(Clone, Copy, Serialize, Deserialize, Debug, TS)]
+#[ts(export)]
+pub struct DeclipParams {
+    pub enabled: bool,
+    /// 0 - 1, samples at or above this fraction of the peak count as clipped
+    pub threshold: f32,
+    /// shortest run of samples at the clip level that counts as clipping
+    pub min_run: usize,
+}
+
+impl DeclipParams {
+    pub fn new() -> Self {
+        Self {
+            enabled: false,
+            threshold: 0.99,
+            min_run: 3,
+        }
+    }
+}
+
+impl Default for DeclipParams {
+    fn default() -> Self {
+        DeclipParams::new()
+    }
+}
+
+/// which channel's params a control changes, both sets every channel
+#[derive(Clone, Debug, Deserialize, Serialize, Copy, PartialEq, TS)]
 #[ts(export)]
 pub enum StereoChoice {
-    Left = 0,
-    Right = 1,
-    Both = 2,
+    Left,
+    Right,
+    Both,
+    /// a channel past the first two, counting from 0
+    Channel(usize),
 }
 
 impl FromSql for StereoChoice {
@@ -242,7 +558,11 @@ impl FromSql for StereoChoice {
             "Left" => Ok(Left),
             "Right" => Ok(Right),
             "Both" => Ok(Both),
-            _ => Err(rusqlite::types::FromSqlError::InvalidType),
+            s => s
+                .strip_prefix("Channel")
+                .and_then(|i| i.parse().ok())
+                .map(Channel)
+                .ok_or(rusqlite::types::FromSqlError::InvalidType),
         }
     }
 }
@@ -257,12 +577,22 @@ impl IntoIterator for StereoChoice {
 }
 
 impl StereoChoice {
-    pub fn as_str(&self) -> &str {
+    pub fn name(&self) -> String {
         use StereoChoice::*;
         match self {
-            Left => "Left",
-            Right => "Right",
-            Both => "Both",
+            Left => "Left".to_string(),
+            Right => "Right".to_string(),
+            Both => "Both".to_string(),
+            Channel(i) => format!("Channel{}", i),
+        }
+    }
+
+    /// which saved params a channel of the file uses
+    pub fn for_channel(i: usize) -> Self {
+        match i {
+            0 => StereoChoice::Left,
+            1 => StereoChoice::Right,
+            _ => StereoChoice::Channel(i),
         }
     }
 }
@@ -274,7 +604,12 @@ pub struct AudioParams {
     // other stuff not from ui
     pub time: usize,
     pub dft_size: usize,
+    pub gain_smoothing: GainSmoothing,
+    pub spectral_gate: SpectralGate,
     // don't serialize all
+    /// Hz, the rate samples are processed at, the device rate when playing
+    #[serde(skip)]
+    pub sample_rate: f32,
     #[serde(skip)]
     pub filters: Filters,
     #[serde(skip)]
@@ -283,6 +618,12 @@ pub struct AudioParams {
     pub noise_spectrum: Vec<f32>,
     #[serde(skip)]
     pub sdft: SDFT,
+    #[serde(skip)]
+    pub dehum: Dehum,
+    #[serde(skip)]
+    pub declicker: Declicker,
+    #[serde(skip)]
+    pub vad: Vad,
 }
 
 impl AudioParams {
@@ -294,12 +635,84 @@ impl AudioParams {
             ui_params,
             dft_size: n,
             time: 0,
+            gain_smoothing: GainSmoothing::new(),
+            spectral_gate: SpectralGate::new(),
+            sample_rate: SAMPLING_RATE,
             output_spectrum: vec![],
             noise_spectrum: filters.parallel_transfer(n),
             sdft: SDFT::new(n),
+            dehum: Dehum::new(HumParams::new(), SAMPLING_RATE),
+            declicker: Declicker::new(DeclickParams::new(), SAMPLING_RATE),
+            vad: Vad::new(VadParams::new(), n, SAMPLING_RATE),
             filters,
         }
     }
+
+    /// rate the channel runs at, everything timed in ms follows it
+    pub fn set_sample_rate(&mut self, sample_rate: f32) {
+        self.sample_rate = sample_rate;
+        self.dehum = Dehum::new(self.dehum.params, sample_rate);
+        self.declicker = Declicker::new(self.declicker.params, sample_rate);
+        self.vad = Vad::new(self.vad.params, self.dft_size, sample_rate);
+    }
+
+    /// output gain and the time domain stages that run before the spectral processing
+    pub fn pre_process(&mut self, sample: f32) -> f32 {
+        let mut sample = sample * self.ui_params.output_gain;
+        if self.declicker.params.enabled {
+            sample = self.declicker.process(sample);
+        }
+        if self.dehum.params.enabled {
+            sample = self.dehum.process(sample);
+        }
+        sample
+    }
+
+    /// samples between the input and output of process, one for the sdft plus the declicker's hops
+    pub fn latency(&self) -> usize {
+        let declick = if self.declicker.params.enabled {
+            2 * DECLICK_HOP
+        } else {
+            0
+        };
+        1 + declick
+    }
+
+    /// denoise a single sample of this channel, output gain is applied before processing
+    pub fn process(&mut self, sample: f32) -> f32 {
+        let sample = self.pre_process(sample);
+        let noise_spectrum = self.vad.noise_profile(&self.noise_spectrum);
+        let noise_gain = self.ui_params.noise_gain * self.vad.strength();
+        let out = if self.spectral_gate.enabled {
+            self.sdft.spectral_gate(
+                sample,
+                noise_spectrum,
+                noise_gain,
+                &self.spectral_gate,
+                self.sample_rate,
+            )
+        } else if self.gain_smoothing.enabled {
+            self.sdft.spectral_subtraction_gain(
+                sample,
+                noise_spectrum,
+                noise_gain,
+                &self.gain_smoothing,
+                self.sample_rate,
+            )
+        } else {
+            self.sdft.spectral_subtraction(
+                sample,
+                noise_spectrum,
+                noise_gain,
+                self.ui_params.pre_smooth_gain,
+                self.ui_params.post_smooth_gain,
+            )
+        };
+        if self.vad.params.enabled {
+            self.vad.process(&self.sdft);
+        }
+        out
+    }
 }
 
 impl Default for AudioParams {
@@ -311,16 +724,284 @@ impl Default for AudioParams {
             ui_params,
             dft_size: n,
             time: 0,
+            gain_smoothing: GainSmoothing::new(),
+            spectral_gate: SpectralGate::new(),
+            sample_rate: SAMPLING_RATE,
             output_spectrum: vec![],
             noise_spectrum: filters.parallel_transfer(n),
             sdft: SDFT::new(n),
+            dehum: Dehum::new(HumParams::new(), SAMPLING_RATE),
+            declicker: Declicker::new(DeclickParams::new(), SAMPLING_RATE),
+            vad: Vad::new(VadParams::new(), n, SAMPLING_RATE),
             filters,
         }
     }
 }
 
+/// how the two channels of a stereo file are processed
+/// each mode keeps its own params in the db, see param_set
+#[derive(Clone, Debug, Deserialize, Serialize, Copy, PartialEq, TS)]
+#[ts(export)]
+pub enum StereoMode {
+    /// left and right get their own gains
+    Independent,
+    /// one gain from both channels' spectra keeps the stereo image from shifting
+    Linked,
+    /// mid and side are processed instead of left and right, the left params are mid and the right params are side
+    MidSide,
+}
+
+impl FromSql for StereoMode {
+    fn column_result(value: rusqlite::types::ValueRef<'_>) -> rusqlite::types::FromSqlResult<Self> {
+        use StereoMode::*;
+        match value.as_str()? {
+            "Independent" => Ok(Independent),
+            "Linked" => Ok(Linked),
+            "MidSide" => Ok(MidSide),
+            _ => Err(rusqlite::types::FromSqlError::InvalidType),
+        }
+    }
+}
+
+impl StereoMode {
+    pub fn as_str(&self) -> &str {
+        use StereoMode::*;
+        match self {
+            Independent => "Independent",
+            Linked => "Linked",
+            MidSide => "MidSide",
+        }
+    }
+
+    /// the stereo_choice rows this mode's params are stored under
+    /// independent keeps the original left/right/both rows, linked only has one set
+    /// channels past the first two are always processed on their own so they keep their rows in every mode
+    pub fn param_set(&self, stereo_choice: StereoChoice) -> String {
+        use StereoChoice::*;
+        match (self, stereo_choice) {
+            (_, Channel(_)) | (StereoMode::Independent, _) => stereo_choice.name().to_lowercase(),
+            (StereoMode::Linked, _) => "linked".to_string(),
+            (StereoMode::MidSide, Left) => "mid".to_string(),
+            (StereoMode::MidSide, Right) => "side".to_string(),
+            (StereoMode::MidSide, _) => "midside".to_string(),
+        }
+    }
+}
+
+/// how linked stereo combines the two spectra before computing the gain
+#[derive(Clone, Debug, Deserialize, Serialize, Copy, PartialEq, TS)]
+#[ts(export)]
+pub enum LinkDetection {
+    Max,
+    Average,
+}
+
+impl FromSql for LinkDetection {
+    fn column_result(value: rusqlite::types::ValueRef<'_>) -> rusqlite::types::FromSqlResult<Self> {
+        use LinkDetection::*;
+        match value.as_str()? {
+            "Max" => Ok(Max),
+            "Average" => Ok(Average),
+            _ => Err(rusqlite::types::FromSqlError::InvalidType),
+        }
+    }
+}
+
+impl LinkDetection {
+    pub fn as_str(&self) -> &str {
+        use LinkDetection::*;
+        match self {
+            Max => "Max",
+            Average => "Average",
+        }
+    }
+}
+
+#[derive(Clone, Copy, Serialize, Deserialize, Debug, TS)]
+#[ts(export)]
+pub struct StereoModeParams {
+    pub mode: StereoMode,
+    /// only used when linked
+    pub link: LinkDetection,
+}
+
+impl StereoModeParams {
+    pub fn new() -> Self {
+        Self {
+            mode: StereoMode::Independent,
+            link: LinkDetection::Max,
+        }
+    }
+}
+
+impl Default for StereoModeParams {
+    fn default() -> Self {
+        StereoModeParams::new()
+    }
+}
+
+/// which channel of a stereo file is the noise reference for adaptive cancellation
+#[derive(Clone, Debug, Deserialize, Serialize, Copy, PartialEq, TS)]
+#[ts(export)]
+pub enum ReferenceChannel {
+    Left,
+    Right,
+}
+
+impl FromSql for ReferenceChannel {
+    fn column_result(value: rusqlite::types::ValueRef<'_>) -> rusqlite::types::FromSqlResult<Self> {
+        use ReferenceChannel::*;
+        match value.as_str()? {
+            "Left" => Ok(Left),
+            "Right" => Ok(Right),
+            _ => Err(rusqlite::types::FromSqlError::InvalidType),
+        }
+    }
+}
+
+impl ReferenceChannel {
+    pub fn as_str(&self) -> &str {
+        use ReferenceChannel::*;
+        match self {
+            Left => "Left",
+            Right => "Right",
+        }
+    }
+}
+
+/// two channel noise cancellation, one channel is a reference mic that mostly hears the noise
+/// the cleaned primary is played on both channels
+#[derive(Clone, Copy, Serialize, Deserialize, Debug, TS)]
+#[ts(export)]
+pub struct AdaptiveCancel {
+    pub enabled: bool,
+    pub reference: ReferenceChannel,
+    /// taps in the adaptive filter, longer follows more reverberant paths but adapts slower
+    pub filter_length: usize,
+    /// NLMS step size, 0 - 1
+    pub step_size: f32,
+}
+
+impl AdaptiveCancel {
+    pub fn new() -> Self {
+        Self {
+            enabled: false,
+            reference: ReferenceChannel::Right,
+            filter_length: 256,
+            step_size: 0.1,
+        }
+    }
+}
+
+impl Default for AdaptiveCancel {
+    fn default() -> Self {
+        AdaptiveCancel::new()
+    }
+}
+
+/// loudness normalization of the export, applied after everything else
+#[derive(Clone, Copy, Serialize, Deserialize, Debug, TS)]
+#[ts(export)]
+pub struct NormalizeParams {
+    pub enabled: bool,
+    /// LUFS, integrated loudness of the result
+    pub target: f32,
+    /// dBTP, a lookahead limiter keeps the true peak under this
+    pub ceiling: f32,
+}
+
+impl NormalizeParams {
+    pub fn new() -> Self {
+        Self {
+            enabled: false,
+            target: -23.0,
+            ceiling: -1.0,
+        }
+    }
+}
+
+impl Default for NormalizeParams {
+    fn default() -> Self {
+        NormalizeParams::new()
+    }
+}
+
+/// how often the audio state is sent to the ui
+#[derive(Clone, Copy, Serialize, Deserialize, Debug, TS)]
+#[ts(export)]
+pub struct UIRate {
+    /// Hz, messages per second at most, one per new audio callback
+    pub rate: f32,
+}
+
+impl UIRate {
+    pub fn new() -> Self {
+        Self { rate: 30.0 }
+    }
+}
+
+impl Default for UIRate {
+    fn default() -> Self {
+        UIRate::new()
+    }
+}
+
+/// how much of the processed signal is heard
+#[derive(Clone, Copy, Serialize, Deserialize, Debug, PartialEq, TS)]
+#[ts(export)]
+pub struct MixParams {
+    /// 0 - 1, share of the processed signal, the rest is the input lined up with it
+    pub wet: f32,
+}
+
+impl MixParams {
+    pub fn new() -> Self {
+        Self { wet: 1.0 }
+    }
+}
+
+impl Default for MixParams {
+    fn default() -> Self {
+        MixParams::new()
+    }
+}
+
+/// how faster or slower playback gets there, only the audition changes, processing stays at the file's rate
+#[derive(Clone, Copy, Debug, Deserialize, Serialize, PartialEq, TS)]
+#[ts(export)]
+pub enum SpeedMode {
+    /// resampled, pitch goes up and down with the rate like a tape
+    Varispeed,
+    /// time-stretched with wsola, voices keep their pitch
+    PreservePitch,
+}
+
+/// playback rate for reviewing long files
+#[derive(Clone, Copy, Serialize, Deserialize, Debug, PartialEq, TS)]
+#[ts(export)]
+pub struct SpeedParams {
+    /// 0.5 - 3, 1 plays the processed frames as they are
+    pub rate: f32,
+    pub mode: SpeedMode,
+}
+
+impl SpeedParams {
+    pub fn new() -> Self {
+        Self {
+            rate: 1.0,
+            mode: SpeedMode::PreservePitch,
+        }
+    }
+}
+
+impl Default for SpeedParams {
+    fn default() -> Self {
+        SpeedParams::new()
+    }
+}
+
 /// stereo params includes AudioParams for each channel as well as other params that are independent of the channels
-#[derive(Debug, TS, Serialize, Deserialize)]
+#[derive(Clone, Debug, TS, Serialize, Deserialize)]
 #[ts(export)]
 pub struct StereoParams {
     pub left: AudioParams,
@@ -329,11 +1010,32 @@ pub struct StereoParams {
     pub clean: bool,
     pub num_file_samples: usize,
     pub file_path: String,
-    pub is_stereo: bool,
-    pub time: usize,
-    pub loop_start_time: usize,
-    pub loop_length: usize,
-    pub is_looping: bool,
+    /// channels in the file, left and right are the first two
+    pub num_channels: usize,
+    /// Hz, every channel runs at this rate
+    #[serde(skip)]
+    pub sample_rate: f32,
+    /// channels past the first two, each with its own saved params like left and right
+    #[serde(skip)]
+    pub extra: Vec<AudioParams>,
+    pub stereo_mode: StereoModeParams,
+    /// play state and position in frames, owned by the callback
+    #[serde(skip)]
+    pub transport: Transport,
+    #[serde(skip)]
+    pub canceller: Nlms,
+    /// scale and number of points of the spectrum sent to the ui
+    #[serde(skip)]
+    pub freq_scale: (crate::settings::PlotScale, usize),
+    /// Hz, how often the reporter sends the ui what the callback publishes
+    #[serde(skip)]
+    pub ui_rate: f32,
+    /// lines the input up with the output for the comparison and residual audition
+    #[serde(skip)]
+    pub monitor: Monitor,
+    /// wet/dry balance, clean crossfades it all the way dry
+    #[serde(skip)]
+    pub mix: Mix,
 }
 
 impl StereoParams {
@@ -345,13 +1047,225 @@ impl StereoParams {
             clean: false,
             num_file_samples: 0,
             file_path: "".to_string(),
-            is_stereo: false,
-            time: 0,
-            loop_start_time: 0,
-            loop_length: 0,
-            is_looping: false,
+            num_channels: 1,
+            sample_rate: SAMPLING_RATE,
+            extra: vec![],
+            stereo_mode: StereoModeParams::new(),
+            transport: Transport::default(),
+            canceller: Nlms::new(AdaptiveCancel::new()),
+            freq_scale: (crate::settings::PlotScale::Linear, SPECTRUM_POINTS),
+            ui_rate: UIRate::new().rate,
+            monitor: Monitor::default(),
+            mix: Mix::default(),
+        }
+    }
+
+    pub fn set_num_channels(&mut self, num_channels: usize) {
+        self.num_channels = num_channels.max(1);
+        self.extra = (2..self.num_channels)
+            .map(|_| {
+                let mut params = AudioParams::new();
+                params.set_sample_rate(self.sample_rate);
+                params
+            })
+            .collect();
+        self.monitor = Monitor::new(self.num_channels);
+    }
+
+    /// rate of the file as it's processed, the device rate when playing
+    pub fn set_sample_rate(&mut self, sample_rate: f32) {
+        self.sample_rate = sample_rate;
+        for params in [&mut self.left, &mut self.right]
+            .into_iter()
+            .chain(self.extra.iter_mut())
+        {
+            params.set_sample_rate(sample_rate);
+        }
+        self.mix = Mix::new(self.mix.params, sample_rate);
+    }
+
+    pub fn channel(&self, i: usize) -> &AudioParams {
+        match i {
+            0 => &self.left,
+            1 => &self.right,
+            _ => &self.extra[i - 2],
         }
     }
+
+    pub fn channel_mut(&mut self, i: usize) -> &mut AudioParams {
+        match i {
+            0 => &mut self.left,
+            1 => &mut self.right,
+            _ => &mut self.extra[i - 2],
+        }
+    }
+
+    /// process one frame of the file, frame and out hold num_channels samples
+    /// the stereo mode and adaptive cancellation only apply to the first two channels
+    pub fn process_frame(&mut self, frame: &[f32], out: &mut [f32]) {
+        if self.num_channels == 1 {
+            out[0] = self.left.process(frame[0]);
+            return;
+        }
+        (out[0], out[1]) = self.process(frame[0], frame[1]);
+        for (i, params) in self.extra.iter_mut().enumerate() {
+            out[i + 2] = params.process(frame[i + 2]);
+        }
+    }
+
+    /// samples between a channel's input and its output
+    /// linked and mid/side mix the first two channels so they get the slower of the two
+    pub fn latency(&self, i: usize) -> usize {
+        if i >= 2 {
+            return self.channel(i).latency();
+        }
+        if self.canceller.params.enabled {
+            let primary = match self.canceller.params.reference {
+                ReferenceChannel::Right => &self.left,
+                ReferenceChannel::Left => &self.right,
+            };
+            return primary.latency() + self.canceller.params.filter_length.max(1) / 2;
+        }
+        match self.stereo_mode.mode {
+            StereoMode::Independent => self.channel(i).latency(),
+            _ => self.left.latency().max(self.right.latency()),
+        }
+    }
+
+    /// feed a processed frame to the monitor, out is replaced with the wet/dry mix or what the audition mode plays
+    /// with adaptive cancellation both outputs come from the primary, so that's the input they're compared with
+    /// the input is always fed in so it's lined up the moment the mix or bypass starts moving
+    pub fn monitor_frame(&mut self, frame: &[f32], out: &mut [f32]) {
+        let wet = self.mix.next(self.clean);
+        for (i, o) in out.iter_mut().enumerate() {
+            let input = if i < 2 && self.canceller.params.enabled {
+                match self.canceller.params.reference {
+                    ReferenceChannel::Right => frame[0],
+                    ReferenceChannel::Left => frame[1],
+                }
+            } else {
+                frame[i]
+            };
+            let input = input * self.channel(i).ui_params.output_gain;
+            let latency = self.latency(i);
+            *o = self.monitor.process(i, input, *o, latency, wet);
+        }
+    }
+
+    /// process one frame of a stereo file, the channels are independent unless adaptive cancellation is on
+    pub fn process(&mut self, left: f32, right: f32) -> (f32, f32) {
+        if self.canceller.params.enabled {
+            let out = match self.canceller.params.reference {
+                ReferenceChannel::Right => {
+                    let cleaned = self.canceller.process(left, right);
+                    self.left.process(cleaned)
+                }
+                ReferenceChannel::Left => {
+                    let cleaned = self.canceller.process(right, left);
+                    self.right.process(cleaned)
+                }
+            };
+            return (out, out);
+        }
+        match self.stereo_mode.mode {
+            StereoMode::Independent => (self.left.process(left), self.right.process(right)),
+            StereoMode::Linked => self.process_linked(left, right),
+            StereoMode::MidSide => {
+                let mid = self.left.process(0.5 * (left + right));
+                let side = self.right.process(0.5 * (left - right));
+                (mid + side, mid - side)
+            }
+        }
+    }
+
+    /// both channels get the gain computed from the combined spectrum, using the left params
+    /// both sides hold the linked set so it doesn't matter which one the gain comes from
+    fn process_linked(&mut self, left: f32, right: f32) -> (f32, f32) {
+        let left = self.left.pre_process(left);
+        let right = self.right.pre_process(right);
+        self.left.sdft.analyze(left);
+        self.right.sdft.analyze(right);
+        // same paths as independent channels, only the detection is shared
+        let complex = !self.left.spectral_gate.enabled && !self.left.gain_smoothing.enabled;
+        let (pre_smooth_gain, post_smooth_gain) = (
+            self.left.ui_params.pre_smooth_gain,
+            self.left.ui_params.post_smooth_gain,
+        );
+        if complex {
+            self.left.sdft.pre_smooth(pre_smooth_gain);
+            self.right.sdft.pre_smooth(pre_smooth_gain);
+        }
+        for (l, r) in self
+            .left
+            .sdft
+            .mags
+            .iter_mut()
+            .zip(self.right.sdft.mags.iter())
+        {
+            *l = match self.stereo_mode.link {
+                LinkDetection::Max => l.max(*r),
+                LinkDetection::Average => 0.5 * (*l + *r),
+            };
+        }
+
+        let params = &mut self.left;
+        let noise_spectrum = params.vad.noise_profile(&params.noise_spectrum);
+        let noise_gain = params.ui_params.noise_gain * params.vad.strength();
+        if params.spectral_gate.enabled {
+            params.sdft.gate_gain(
+                noise_spectrum,
+                noise_gain,
+                &params.spectral_gate,
+                params.sample_rate,
+            );
+        } else if params.gain_smoothing.enabled {
+            params.sdft.subtraction_gain(
+                noise_spectrum,
+                noise_gain,
+                &params.gain_smoothing,
+                params.sample_rate,
+            );
+        } else {
+            params
+                .sdft
+                .smoothed_subtraction_gain(noise_spectrum, noise_gain);
+        }
+        if params.vad.params.enabled {
+            params.vad.process(&params.sdft);
+        }
+
+        if complex {
+            self.right
+                .sdft
+                .raw_gain
+                .copy_from_slice(&self.left.sdft.raw_gain);
+            (
+                self.left.sdft.apply_smoothed_gain(post_smooth_gain),
+                self.right.sdft.apply_smoothed_gain(post_smooth_gain),
+            )
+        } else {
+            self.right
+                .sdft
+                .gain_history
+                .copy_from_slice(&self.left.sdft.gain_history);
+            (self.left.sdft.apply_gain(), self.right.sdft.apply_gain())
+        }
+    }
+
+    /// clear the processing state of every channel, used when time jumps or the stereo mode changes
+    pub fn reset(&mut self) {
+        for params in [&mut self.left, &mut self.right]
+            .into_iter()
+            .chain(self.extra.iter_mut())
+        {
+            params.sdft.reset();
+            params.dehum.reset();
+            params.declicker.reset();
+            params.vad.reset();
+        }
+        self.canceller.reset();
+        self.monitor.reset();
+    }
 }
 
 impl Default for StereoParams {
@@ -363,11 +1277,16 @@ impl Default for StereoParams {
             clean: false,
             num_file_samples: 0,
             file_path: "".to_string(),
-            is_stereo: false,
-            time: 0,
-            loop_start_time: 0,
-            loop_length: 0,
-            is_looping: false,
+            num_channels: 1,
+            sample_rate: SAMPLING_RATE,
+            extra: vec![],
+            stereo_mode: StereoModeParams::new(),
+            transport: Transport::default(),
+            canceller: Nlms::new(AdaptiveCancel::new()),
+            freq_scale: (crate::settings::PlotScale::Linear, SPECTRUM_POINTS),
+            ui_rate: UIRate::new().rate,
+            monitor: Monitor::default(),
+            mix: Mix::default(),
         }
     }
 }
@@ -377,8 +1296,8 @@ impl Default for StereoParams {
 #[ts(export)]
 pub struct UIParams {
     pub clean: bool,
-    pub left_mute: bool,
-    pub right_mute: bool,
+    /// silences the channel in playback, export ignores it
+    pub mute: bool,
     pub stereo_choice: StereoChoice,
     pub output_gain: f32,
     pub noise_gain: f32,
@@ -391,8 +1310,7 @@ impl UIParams {
     pub fn new() -> Self {
         Self {
             clean: false,
-            left_mute: false,
-            right_mute: false,
+            mute: false,
             stereo_choice: StereoChoice::Both,
             output_gain: 1.0,
             noise_gain: 0.0,
@@ -407,8 +1325,7 @@ impl Default for UIParams {
     fn default() -> Self {
         Self {
             clean: false,
-            left_mute: false,
-            right_mute: false,
+            mute: false,
             stereo_choice: StereoChoice::Both,
             output_gain: 1.0,
             noise_gain: 0.0,
@@ -0,0 +1,248 @@
+use rustfft::{num_complex::Complex, FftPlanner};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    constants::SAMPLING_RATE,
+    sdft::SDFT,
+    types::{GainSmoothing, VadParams},
+};
+
+/// keeps the log of empty bins finite
+const VAD_EPS: f32 = 1e-12;
+/// mean bin power below this is treated as digital silence
+const SILENCE: f32 = 1e-10;
+/// dB per second the noise floor creeps up during speech, so a louder noise bed is eventually picked up
+const FLOOR_RISE: f32 = 3.0;
+/// ms, how fast the processing strength follows the speech decision
+const DUCK_TIME: f32 = 20.0;
+
+/// a run of frames that were all speech or all non-speech
+#[derive(Clone, Copy, Debug, Serialize, Deserialize, PartialEq)]
+pub struct VadSegment {
+    /// frame of the file, same units as the playback time
+    pub start: usize,
+    /// frames
+    pub length: usize,
+    pub speech: bool,
+}
+
+/// energy and spectral flatness detector that runs once per dft frame
+/// speech is loud compared to the noise floor and has a peaky spectrum, noise is quiet and flat
+#[derive(Clone, Debug)]
+pub struct Vad {
+    pub params: VadParams,
+    /// learned profile, same convention as AudioParams.noise_spectrum, the noise magnitude is |x - 1|
+    pub noise_spectrum: Vec<f32>,
+    pub speech: bool,
+    /// frames the learned profile has been updated with
+    learned: usize,
+    /// mean bin power of the noise
+    floor: f32,
+    hangover: usize,
+    mags: Vec<f32>,
+    count: usize,
+    strength: f32,
+    /// Hz, frames and ducking are timed with it
+    pub sample_rate: f32,
+}
+
+impl Vad {
+    pub fn new(params: VadParams, size: usize, sample_rate: f32) -> Self {
+        Self {
+            params,
+            noise_spectrum: vec![1.0; size],
+            speech: false,
+            learned: 0,
+            floor: 0.0,
+            hangover: 0,
+            mags: vec![0.0; size],
+            count: 0,
+            strength: 1.0,
+            sample_rate,
+        }
+    }
+
+    /// new params from the ui, what's been learned so far is kept
+    pub fn set_params(&mut self, params: VadParams) {
+        self.params = params;
+    }
+
+    /// start over on the decision, the learned profile and floor still describe the file
+    pub fn reset(&mut self) {
+        self.speech = false;
+        self.hangover = 0;
+        self.count = 0;
+        self.strength = 1.0;
+    }
+
+    /// the learned profile once there is one, otherwise the profile from the filters
+    pub fn noise_profile<'a>(&'a self, profile: &'a Vec<f32>) -> &'a Vec<f32> {
+        if self.params.enabled && self.params.learn_noise && self.learned > 0 {
+            &self.noise_spectrum
+        } else {
+            profile
+        }
+    }
+
+    /// multiplier on the noise gain, ducks toward speech_strength while someone is talking
+    pub fn strength(&self) -> f32 {
+        if self.params.enabled {
+            self.strength
+        } else {
+            1.0
+        }
+    }
+
+    /// called every sample after the sdft has moved, runs the detector once per frame
+    pub fn process(&mut self, sdft: &SDFT) {
+        self.count += 1;
+        if self.count >= sdft.size {
+            self.count = 0;
+            for (m, x) in self.mags.iter_mut().zip(sdft.new_freq.iter()) {
+                *m = x.norm();
+            }
+            let mags = std::mem::take(&mut self.mags);
+            self.frame(&mags);
+            self.mags = mags;
+        }
+
+        let target = if self.speech {
+            self.params.speech_strength.clamp(0.0, 1.0)
+        } else {
+            1.0
+        };
+        let coeff = GainSmoothing::coeff(DUCK_TIME, self.sample_rate);
+        self.strength = coeff * self.strength + (1.0 - coeff) * target;
+    }
+
+    /// decide on one frame of dft magnitudes, returns whether it's speech
+    /// the noise floor and the learned profile only move on non-speech frames
+    pub fn frame(&mut self, mags: &[f32]) -> bool {
+        let n = mags.len();
+        let hop = n as f32 / self.sample_rate;
+        // dc and the mirrored half add nothing
+        let bins = &mags[1..(n / 2).max(2)];
+        let mut mean = 0.0;
+        let mut log_mean = 0.0;
+        for m in bins.iter() {
+            let p = m * m;
+            mean += p;
+            log_mean += (p + VAD_EPS).ln();
+        }
+        mean /= bins.len() as f32;
+        log_mean /= bins.len() as f32;
+        let flatness = log_mean.exp() / (mean + VAD_EPS);
+
+        // digital silence says nothing about the noise
+        if mean < SILENCE {
+            self.hangover = self.hangover.saturating_sub(1);
+            self.speech = self.hangover > 0;
+            return self.speech;
+        }
+        if self.floor == 0.0 {
+            self.floor = mean;
+        }
+        let above = mean > self.floor * 10f32.powf(self.params.threshold / 10.0);
+        let active = above && flatness < self.params.flatness;
+
+        if active {
+            self.hangover = (self.params.hangover.max(0.0) * 0.001 / hop) as usize;
+        } else if self.hangover > 0 {
+            self.hangover -= 1;
+        }
+        self.speech = active || self.hangover > 0;
+
+        if self.speech {
+            self.floor *= 10f32.powf(FLOOR_RISE * hop / 10.0);
+        } else {
+            let a = if self.params.learn_time > 0.0 {
+                (-hop / (self.params.learn_time * 0.001)).exp()
+            } else {
+                0.0
+            };
+            // the floor drops right away, quiet frames are never speech
+            self.floor = if mean < self.floor {
+                mean
+            } else {
+                a * self.floor + (1.0 - a) * mean
+            };
+            let first = self.learned == 0;
+            for (x, m) in self.noise_spectrum.iter_mut().zip(mags.iter()) {
+                *x = if first {
+                    1.0 + m
+                } else {
+                    1.0 + a * (*x - 1.0) + (1.0 - a) * m
+                };
+            }
+            self.learned += 1;
+        }
+        self.speech
+    }
+}
+
+impl Default for Vad {
+    fn default() -> Self {
+        Vad::new(VadParams::new(), 256, SAMPLING_RATE)
+    }
+}
+
+/// run the detector over a whole channel, frames are the same size as the sdft so the segments match playback
+pub fn find_segments(
+    samples: &[f32],
+    params: &VadParams,
+    size: usize,
+    sample_rate: f32,
+) -> Vec<(usize, usize, bool)> {
+    let mut vad = Vad::new(*params, size, sample_rate);
+    let fft = FftPlanner::<f32>::new().plan_fft_forward(size);
+    let mut buf = vec![Complex::new(0.0, 0.0); size];
+    let mut mags = vec![0.0; size];
+    let mut segments: Vec<(usize, usize, bool)> = vec![];
+    for (i, chunk) in samples.chunks_exact(size).enumerate() {
+        for (b, x) in buf.iter_mut().zip(chunk.iter()) {
+            *b = Complex::new(*x, 0.0);
+        }
+        fft.process(&mut buf);
+        for (m, b) in mags.iter_mut().zip(buf.iter()) {
+            *m = b.norm();
+        }
+        let speech = vad.frame(&mags);
+        match segments.last_mut() {
+            Some(last) if last.2 == speech => last.1 += size,
+            _ => segments.push((i * size, size, speech)),
+        }
+    }
+    segments
+}
+
+/// speech/non-speech segments of interleaved samples, one channel or the average of all of them
+pub fn vad_segments(
+    samples: &[f32],
+    num_channels: usize,
+    channel: Option<usize>,
+    params: &VadParams,
+    size: usize,
+    sample_rate: f32,
+) -> Vec<VadSegment> {
+    let num_channels = num_channels.max(1);
+    let mono: Vec<f32> = match channel {
+        Some(c) => samples
+            .iter()
+            .skip(c.min(num_channels - 1))
+            .step_by(num_channels)
+            .cloned()
+            .collect(),
+        None => samples
+            .chunks_exact(num_channels)
+            .map(|f| f.iter().sum::<f32>() / num_channels as f32)
+            .collect(),
+    };
+    find_segments(&mono, params, size, sample_rate)
+        .into_iter()
+        .map(|(start, length, speech)| VadSegment {
+            start,
+            length,
+            speech,
+        })
+        .collect()
+}
@@ -0,0 +1,36 @@
+use std::path::PathBuf;
+
+use denoiser::sql::{create_db, update_db};
+
+/// a fresh database in its own temp directory, removed when dropped
+pub struct TempDb {
+    dir: PathBuf,
+    pub path: PathBuf,
+}
+
+impl TempDb {
+    pub fn new(name: &str) -> Self {
+        let dir =
+            std::env::temp_dir().join(format!("denoiser_test_{}_{}", name, std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).expect("failed to make temp dir");
+        let path = dir.join("db.sqlite");
+        // same as app startup
+        create_db(path.clone()).expect("failed to create db");
+        update_db(&path).expect("failed to update db");
+        Self { dir, path }
+    }
+}
+
+impl Drop for TempDb {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_dir_all(&self.dir);
+    }
+}
+
+pub fn golden_path(name: &str) -> PathBuf {
+    PathBuf::from(env!("CARGO_MANIFEST_DIR"))
+        .join("tests")
+        .join("golden")
+        .join(format!("{}.json", name))
+}
@@ -0,0 +1,312 @@
+//! golden-file regression tests for the export pipeline
+//! each case writes a fixed parameter set to a temporary database, generates its input,
+//! runs export_pipeline and compares the output envelope and metrics with tests/golden
+//! set UPDATE_GOLDEN=1 to rewrite the golden files after an intended change
+
+mod common;
+
+use serde::{Deserialize, Serialize};
+use std::path::PathBuf;
+
+use common::{golden_path, TempDb};
+use denoiser::{
+    file_io::export_pipeline,
+    generator::{mix_at_snr, Generator, Source},
+    metrics::QualityMetrics,
+    sql::{update_declick, update_hum, update_spectral_gate, update_stereo_mode, update_ui_params},
+    types::{
+        DeclickParams, HumFundamental, HumParams, LinkDetection, SpectralGate, StereoChoice,
+        StereoMode, StereoModeParams, UIParams, BPF,
+    },
+};
+
+const SAMPLE_RATE: u32 = 44100;
+/// samples per channel in every case, half a second
+const LEN: usize = 22050;
+/// samples in each rms block of the stored envelope
+const BLOCK: usize = 441;
+/// dB, blocks quieter than this in both outputs aren't compared
+const SILENCE_DB: f32 = -80.0;
+const RMS_TOLERANCE: f32 = 0.5;
+const DB_TOLERANCE: f32 = 0.5;
+const STOI_TOLERANCE: f32 = 0.02;
+
+#[derive(Debug, Serialize, Deserialize)]
+struct Golden {
+    /// dB, per channel
+    block_rms: Vec<Vec<f32>>,
+    metrics: Vec<QualityMetrics>,
+    clips: usize,
+}
+
+impl Golden {
+    fn new(
+        samples: &[f32],
+        num_channels: usize,
+        metrics: Vec<QualityMetrics>,
+        clips: usize,
+    ) -> Self {
+        let block_rms = (0..num_channels)
+            .map(|i| {
+                let channel: Vec<f32> = samples
+                    .iter()
+                    .skip(i)
+                    .step_by(num_channels)
+                    .cloned()
+                    .collect();
+                channel
+                    .chunks(BLOCK)
+                    .map(|b| {
+                        let ms = b.iter().map(|x| x * x).sum::<f32>() / b.len() as f32;
+                        // rounded so the files diff cleanly
+                        ((10.0 * (ms + 1e-12).log10()) * 100.0).round() / 100.0
+                    })
+                    .collect()
+            })
+            .collect();
+        Self {
+            block_rms,
+            metrics,
+            clips,
+        }
+    }
+
+    fn compare(&self, golden: &Golden, name: &str) {
+        assert_eq!(self.clips, golden.clips, "{}: clips", name);
+        assert_eq!(self.block_rms.len(), golden.block_rms.len(), "{}", name);
+        for (c, (a, b)) in self
+            .block_rms
+            .iter()
+            .zip(golden.block_rms.iter())
+            .enumerate()
+        {
+            assert_eq!(a.len(), b.len(), "{}: blocks in channel {}", name, c);
+            for (i, (a, b)) in a.iter().zip(b.iter()).enumerate() {
+                if a.max(*b) < SILENCE_DB {
+                    continue;
+                }
+                assert!(
+                    (a - b).abs() < RMS_TOLERANCE,
+                    "{}: channel {} block {} is {} dB, golden {} dB",
+                    name,
+                    c,
+                    i,
+                    a,
+                    b
+                );
+            }
+        }
+        for (c, (a, b)) in self.metrics.iter().zip(golden.metrics.iter()).enumerate() {
+            let close = |x: f32, y: f32, tolerance: f32| (x - y).abs() < tolerance;
+            assert!(
+                close(a.snr, b.snr, DB_TOLERANCE)
+                    && close(a.segmental_snr, b.segmental_snr, DB_TOLERANCE)
+                    && close(
+                        a.log_spectral_distance,
+                        b.log_spectral_distance,
+                        DB_TOLERANCE
+                    )
+                    && close(a.stoi, b.stoi, STOI_TOLERANCE)
+                    && close(
+                        a.noise_floor_reduction,
+                        b.noise_floor_reduction,
+                        DB_TOLERANCE
+                    ),
+                "{}: channel {} metrics {:?}, golden {:?}",
+                name,
+                c,
+                a,
+                b
+            );
+        }
+    }
+}
+
+/// export the source with whatever the db holds and check it against the golden file
+fn check_golden(name: &str, source: &Source, stereo_choice: StereoChoice, db: &PathBuf) -> Golden {
+    let (samples, manifest) = export_pipeline(
+        source.samples.clone(),
+        source.num_channels,
+        source.sample_rate,
+        stereo_choice,
+        db,
+        |_| {},
+    );
+    assert_eq!(samples.len(), source.samples.len(), "{}", name);
+    assert!(samples.iter().all(|x| x.is_finite()), "{}", name);
+
+    let result = Golden::new(
+        &samples,
+        source.num_channels,
+        manifest.metrics,
+        manifest.clips,
+    );
+    let path = golden_path(name);
+    if std::env::var("UPDATE_GOLDEN").is_ok() {
+        let json = serde_json::to_string_pretty(&result).unwrap();
+        std::fs::write(&path, json).expect("failed to write golden file");
+        return result;
+    }
+    let json = std::fs::read_to_string(&path)
+        .unwrap_or_else(|_| panic!("missing {:?}, run with UPDATE_GOLDEN=1", path));
+    let golden: Golden = serde_json::from_str(&json).expect("bad golden file");
+    result.compare(&golden, name);
+    result
+}
+
+/// tones with a syllable-rate envelope, something for the metrics to follow
+fn speech_like(generator: &Generator) -> Vec<f32> {
+    let a = generator.sine(300.0, 0.3, LEN);
+    let b = generator.sine(1200.0, 0.15, LEN);
+    (0..LEN)
+        .map(|n| {
+            let t = n as f32 / SAMPLE_RATE as f32;
+            let env = 0.5 - 0.5 * (2.0 * std::f32::consts::PI * 4.0 * t).cos();
+            env * (a[n] + b[n])
+        })
+        .collect()
+}
+
+/// ui params with noise gain in dB and a flat filter bank at the given gain, as the ui stores them
+fn ui_params(noise_gain: f32, filter_gain: f32) -> UIParams {
+    let mut p = UIParams::new();
+    p.output_gain = 0.0;
+    p.noise_gain = noise_gain;
+    for (i, bpf) in p.filters.bank.iter_mut().enumerate() {
+        *bpf = BPF {
+            gain: filter_gain,
+            freq: 250.0 * 2f32.powi(i as i32),
+            Q: 0.7,
+        };
+    }
+    p
+}
+
+#[test]
+fn passthrough() {
+    let db = TempDb::new("passthrough");
+    let mut p = ui_params(-100.0, 0.0);
+    p.pre_smooth_gain = 0.0;
+    p.post_smooth_gain = 0.0;
+    update_ui_params(StereoChoice::Both, p, db.path.clone()).unwrap();
+
+    let mut g = Generator::new(42, SAMPLE_RATE as f32);
+    let source = Source::mono(g.white(0.25, LEN), SAMPLE_RATE);
+    let result = check_golden("passthrough", &source, StereoChoice::Both, &db.path);
+    // nothing subtracted, the output is the input
+    assert!(result.metrics[0].snr > 60.0, "{:?}", result.metrics);
+}
+
+#[test]
+fn subtraction() {
+    let db = TempDb::new("subtraction");
+    update_ui_params(StereoChoice::Both, ui_params(0.0, 6.0), db.path.clone()).unwrap();
+
+    let mut g = Generator::new(42, SAMPLE_RATE as f32);
+    let clean = speech_like(&g);
+    let noise = g.white(1.0, LEN);
+    let source = Source::mono(mix_at_snr(&clean, &noise, 10.0), SAMPLE_RATE);
+    let result = check_golden("subtraction", &source, StereoChoice::Both, &db.path);
+    assert!(result.metrics[0].noise_floor_reduction > 3.0);
+}
+
+#[test]
+fn spectral_gate() {
+    let db = TempDb::new("spectral_gate");
+    update_ui_params(StereoChoice::Both, ui_params(0.0, 20.0), db.path.clone()).unwrap();
+    let gate = SpectralGate {
+        enabled: true,
+        ..SpectralGate::new()
+    };
+    update_spectral_gate(StereoChoice::Both, gate, db.path.clone()).unwrap();
+
+    let mut g = Generator::new(42, SAMPLE_RATE as f32);
+    let clean = speech_like(&g);
+    let noise = g.white(1.0, LEN);
+    let source = Source::mono(mix_at_snr(&clean, &noise, 10.0), SAMPLE_RATE);
+    let result = check_golden("spectral_gate", &source, StereoChoice::Both, &db.path);
+    assert!(result.metrics[0].noise_floor_reduction > 0.5);
+}
+
+#[test]
+fn declick_dehum() {
+    let db = TempDb::new("declick_dehum");
+    update_ui_params(StereoChoice::Both, ui_params(-100.0, 0.0), db.path.clone()).unwrap();
+    let hum = HumParams {
+        enabled: true,
+        fundamental: HumFundamental::Sixty,
+        track: false,
+        ..HumParams::new()
+    };
+    update_hum(StereoChoice::Both, hum, db.path.clone()).unwrap();
+    let declick = DeclickParams {
+        enabled: true,
+        ..DeclickParams::new()
+    };
+    update_declick(StereoChoice::Both, declick, db.path.clone()).unwrap();
+
+    let mut g = Generator::new(42, SAMPLE_RATE as f32);
+    let clean = speech_like(&g);
+    let hum = g.hum(60.0, 8, 0.1, LEN);
+    let clicks = g.clicks(20.0, 0.5, LEN);
+    let samples = clean
+        .iter()
+        .zip(hum.iter().zip(clicks.iter()))
+        .map(|(c, (h, k))| c + h + k)
+        .collect();
+    check_golden(
+        "declick_dehum",
+        &Source::mono(samples, SAMPLE_RATE),
+        StereoChoice::Both,
+        &db.path,
+    );
+
+    // the declicker's delay is taken out of the export, an impulse comes out where it went in
+    // it's in digital silence so there's no error level to call it a click against
+    let at = LEN / 3;
+    let mut impulse = vec![0.0; LEN];
+    impulse[at] = 0.5;
+    let (samples, _) = export_pipeline(
+        impulse,
+        1,
+        SAMPLE_RATE,
+        StereoChoice::Both,
+        &db.path,
+        |_| {},
+    );
+    assert_eq!(samples.len(), LEN);
+    let peak = samples
+        .iter()
+        .enumerate()
+        .max_by(|a, b| a.1.abs().total_cmp(&b.1.abs()))
+        .map(|(n, _)| n);
+    assert_eq!(peak, Some(at));
+}
+
+#[test]
+fn stereo_linked() {
+    let db = TempDb::new("stereo_linked");
+    // the linked set is only written once the mode is linked
+    let mode = StereoModeParams {
+        mode: StereoMode::Linked,
+        link: LinkDetection::Max,
+    };
+    update_stereo_mode(mode, db.path.clone()).unwrap();
+    update_ui_params(StereoChoice::Both, ui_params(0.0, 6.0), db.path.clone()).unwrap();
+
+    let mut g = Generator::new(42, SAMPLE_RATE as f32);
+    let clean = speech_like(&g);
+    let left = mix_at_snr(&clean, &g.white(1.0, LEN), 10.0);
+    let right = mix_at_snr(&clean, &g.white(1.0, LEN), 0.0);
+    let source = Source {
+        samples: left
+            .iter()
+            .zip(&right)
+            .flat_map(|(l, r)| [*l, *r])
+            .collect(),
+        num_channels: 2,
+        sample_rate: SAMPLE_RATE,
+    };
+    let result = check_golden("stereo_linked", &source, StereoChoice::Both, &db.path);
+    assert_eq!(result.block_rms.len(), 2);
+}
@@ -1,14 +1,51 @@
+/**
+ * one of the two stored parameter sets
+ */
+export type AbSlot = "A" | "B";
+
+/**
+ * two channel noise cancellation, one channel is a reference mic that mostly hears the noise
+ * the cleaned primary is played on both channels
+ */
+export type AdaptiveCancel = {
+  enabled: boolean;
+  reference: ReferenceChannel;
+  /**
+   * taps in the adaptive filter, longer follows more reverberant paths but adapts slower
+   */
+  filter_length: number;
+  /**
+   * NLMS step size, 0 - 1
+   */
+  step_size: number;
+};
+
 export type AudioParams = {
   ui_params: UIParams;
   time: number;
   dft_size: number;
+  gain_smoothing: GainSmoothing;
+  spectral_gate: SpectralGate;
 };
 
+/**
+ * what goes to the speakers
+ */
+export type Audition = "Output" | "Input" | "Residual";
+
 /**
  * user-facing params that control a bandpass filter, convert to IIR for internal audio processing
  */
 export type BPF = { gain: number; freq: number; Q: number };
 
+/**
+ * sliding dft and block denoiser on the same file and saved settings, each scored against the original
+ */
+export type BlockComparison = {
+  sliding: Array<QualityMetrics>;
+  block: Array<QualityMetrics>;
+};
+
 export type Colors = "RotaryTick" | "RotaryHover";
 
 export type Complex = { re: number; im: number };
@@ -31,8 +68,93 @@ export type ComponentColors = {
   button_text: string;
 };
 
+/**
+ * click and crackle repair, runs before everything else in the chain
+ */
+export type DeclickParams = {
+  enabled: boolean;
+  /**
+   * 0 - 1
+   */
+  sensitivity: number;
+  /**
+   * ms, anything longer is left alone, clamped to a few ms
+   */
+  max_click_length: number;
+};
+
+/**
+ * reconstruction of clipped peaks, done on the whole file when it's loaded
+ * repaired peaks go over full scale so the output gain may need to come down
+ */
+export type DeclipParams = {
+  enabled: boolean;
+  /**
+   * 0 - 1, samples at or above this fraction of the peak count as clipped
+   */
+  threshold: number;
+  /**
+   * shortest run of samples at the clip level that counts as clipping
+   */
+  min_run: number;
+};
+
+/**
+ * what playback does when it reaches the end of the file
+ */
+export type EndAction = "Stop" | "Loop";
+
 export type Filters = { bank: [IIR2, IIR2, IIR2, IIR2, IIR2] };
 
+/**
+ * smoothing of the per-bin suppression gain, in time (attack/release) and across neighboring bins (median)
+ */
+export type GainSmoothing = {
+  enabled: boolean;
+  /**
+   * ms
+   */
+  attack: number;
+  /**
+   * ms
+   */
+  release: number;
+  /**
+   * number of bins on each side of the median
+   */
+  freq_width: number;
+  /**
+   * dB, lowest gain any bin can be pulled down to
+   */
+  floor: number;
+};
+
+/**
+ * mains frequency, auto detects it from the spectrum of the file
+ */
+export type HumFundamental = "Auto" | "Fifty" | "Sixty";
+
+/**
+ * comb of notches on the hum fundamental and its harmonics
+ */
+export type HumParams = {
+  enabled: boolean;
+  fundamental: HumFundamental;
+  /**
+   * Hz, the detected fundamental when using auto, otherwise ignored
+   */
+  freq: number;
+  /**
+   * number of notches including the fundamental
+   */
+  harmonics: number;
+  Q: number;
+  /**
+   * follow small drifts of the fundamental while processing
+   */
+  track: boolean;
+};
+
 /**
  * IIR filter, second order
  */
@@ -47,8 +169,105 @@ export type IIR2 = {
   y: [number, number];
 };
 
+/**
+ * how linked stereo combines the two spectra before computing the gain
+ */
+export type LinkDetection = "Max" | "Average";
+
+/**
+ * levels of the output, peak and rms are since the last reading, loudness is EBU R128
+ */
+export type Metering = {
+  /**
+   * dBFS, per channel
+   */
+  peak: Array<number>;
+  /**
+   * dBFS, per channel
+   */
+  rms: Array<number>;
+  /**
+   * dBTP, per channel, peak of the 4x oversampled signal
+   */
+  true_peak: Array<number>;
+  /**
+   * LUFS, last 400 ms
+   */
+  momentary: number;
+  /**
+   * LUFS, last 3 s
+   */
+  short_term: number;
+  /**
+   * LUFS, gated over everything measured
+   */
+  integrated: number;
+};
+
+/**
+ * how much of the processed signal is heard
+ */
+export type MixParams = {
+  /**
+   * 0 - 1, share of the processed signal, the rest is the input lined up with it
+   */
+  wet: number;
+};
+
+/**
+ * loudness normalization of the export, applied after everything else
+ */
+export type NormalizeParams = {
+  enabled: boolean;
+  /**
+   * LUFS, integrated loudness of the result
+   */
+  target: number;
+  /**
+   * dBTP, a lookahead limiter keeps the true peak under this
+   */
+  ceiling: number;
+};
+
 export type PlotScale = "Linear" | "Mel" | "Log" | "Bark";
 
+/**
+ * objective comparison of a processed channel with its reference
+ * the reference is the original for real files, or the clean signal for synthetic mixes
+ */
+export type QualityMetrics = {
+  /**
+   * dB, reference energy over the energy of the difference
+   */
+  snr: number;
+  /**
+   * dB, mean of the per-frame snr over frames that aren't silent
+   */
+  segmental_snr: number;
+  /**
+   * dB, rms difference of the log power spectra, averaged over frames
+   */
+  log_spectral_distance: number;
+  /**
+   * 0 - 1, short-time envelope correlation in third octave bands, higher is more intelligible
+   */
+  stoi: number;
+  /**
+   * dB the quietest frames came down by, positive when the noise floor dropped
+   */
+  noise_floor_reduction: number;
+};
+
+/**
+ * which channel of a stereo file is the noise reference for adaptive cancellation
+ */
+export type ReferenceChannel = "Left" | "Right";
+
+/**
+ * a position in the file, frames are samples of one channel so mono and stereo count the same
+ */
+export type Seek = { Seconds: number } | { Frames: number };
+
 export type Settings = {
   id: number;
   plot_scale: PlotScale;
@@ -58,7 +277,101 @@ export type Settings = {
   draw_filter_amp_axis: boolean;
 };
 
-export type StereoChoice = "Left" | "Right" | "Both";
+/**
+ * one kind of test material, amplitudes are linear peak values
+ */
+export type SignalKind =
+  | { Sine: { freq: number; amplitude: number } }
+  | { Sweep: { start: number; end: number; amplitude: number } }
+  | { SpeechNoise: { amplitude: number } }
+  | { WhiteNoise: { amplitude: number } }
+  | { PinkNoise: { amplitude: number } }
+  | { BrownNoise: { amplitude: number } }
+  | { Hum: { freq: number; harmonics: number; amplitude: number } }
+  | { Clicks: { rate: number; amplitude: number } };
+
+/**
+ * per-bin gate/expander keyed off the noise spectrum, an alternative to subtraction
+ */
+export type SpectralGate = {
+  enabled: boolean;
+  /**
+   * dB above the noise spectrum where a bin opens
+   */
+  margin: number;
+  /**
+   * dB, gain of a closed bin
+   */
+  reduction: number;
+  /**
+   * expansion below the threshold, 1 does nothing and large values act as a hard gate
+   */
+  ratio: number;
+  /**
+   * ms
+   */
+  attack: number;
+  /**
+   * ms, a bin stays open this long after dropping below the threshold
+   */
+  hold: number;
+  /**
+   * ms
+   */
+  release: number;
+};
+
+/**
+ * spectrogram of part of a file, one column per pixel and one row per pixel from the lowest frequency up
+ */
+export type Spectrogram = {
+  /**
+   * frames of the file, same units as the playback time
+   */
+  start: number;
+  len: number;
+  db_min: number;
+  db_max: number;
+  /**
+   * dB, clamped to the range, columns[x][y]
+   */
+  columns: Array<Array<number>>;
+};
+
+/**
+ * how faster or slower playback gets there, only the audition changes, processing stays at the file's rate
+ */
+export type SpeedMode = "Varispeed" | "PreservePitch";
+
+/**
+ * playback rate for reviewing long files
+ */
+export type SpeedParams = {
+  /**
+   * 0.5 - 3, 1 plays the processed frames as they are
+   */
+  rate: number;
+  mode: SpeedMode;
+};
+
+/**
+ * which channel's params a control changes, both sets every channel
+ */
+export type StereoChoice = "Left" | "Right" | "Both" | { Channel: number };
+
+/**
+ * how the two channels of a stereo file are processed
+ * each mode keeps its own params in the db, see param_set
+ */
+export type StereoMode = "Independent" | "Linked" | "MidSide";
+
+export type StereoModeParams = {
+  mode: StereoMode;
+  /**
+   * only used when linked
+   */
+  link: LinkDetection;
+};
 
 /**
  * stereo params includes AudioParams for each channel as well as other params that are independent of the channels
@@ -70,12 +383,29 @@ export type StereoParams = {
   clean: boolean;
   num_file_samples: number;
   file_path: string;
-  is_stereo: boolean;
-  time: number;
+  /**
+   * channels in the file, left and right are the first two
+   */
+  num_channels: number;
+  stereo_mode: StereoModeParams;
 };
 
 export type Theme = "RGB" | "CYM" | "POG" | "BWG" | "SEPIA" | "CUSTOM";
 
+/**
+ * sent to the callback, applied in the order they were sent
+ */
+export type TransportCommand =
+  | "Play"
+  | "Pause"
+  | "Stop"
+  | { Seek: Seek }
+  | { EndAction: EndAction }
+  | { Loop: [Seek, Seek] | null }
+  | { Speed: SpeedParams };
+
+export type TransportState = "Stopped" | "Playing" | "Paused";
+
 export type UIFilters = { bank: [BPF, BPF, BPF, BPF, BPF] };
 
 /**
@@ -83,8 +413,10 @@ export type UIFilters = { bank: [BPF, BPF, BPF, BPF, BPF] };
  */
 export type UIParams = {
   clean: boolean;
-  left_mute: boolean;
-  right_mute: boolean;
+  /**
+   * silences the channel in playback, export ignores it
+   */
+  mute: boolean;
   stereo_choice: StereoChoice;
   output_gain: number;
   noise_gain: number;
@@ -92,3 +424,49 @@ export type UIParams = {
   post_smooth_gain: number;
   filters: UIFilters;
 };
+
+/**
+ * how often the audio state is sent to the ui
+ */
+export type UIRate = {
+  /**
+   * Hz, messages per second at most, one per new audio callback
+   */
+  rate: number;
+};
+
+/**
+ * voice activity detection, the noise profile is only learned while nobody is talking
+ */
+export type VadParams = {
+  enabled: boolean;
+  /**
+   * dB above the noise floor where a frame counts as speech
+   */
+  threshold: number;
+  /**
+   * 0 - 1, frames flatter than this are noise no matter how loud
+   */
+  flatness: number;
+  /**
+   * ms, speech is held this long after the last speech frame so word endings aren't cut
+   */
+  hangover: number;
+  /**
+   * learn the noise profile from non-speech frames instead of using the filters
+   */
+  learn_noise: boolean;
+  /**
+   * ms, time constant of the learned profile
+   */
+  learn_time: number;
+  /**
+   * 0 - 1, processing strength during speech, 1 doesn't duck
+   */
+  speech_strength: number;
+};
+
+/**
+ * analysis/synthesis window for the stft, all periodic so shifted copies overlap-add evenly
+ */
+export type WindowKind = "Rectangular" | "Hann" | "SqrtHann" | "Hamming";